@@ -0,0 +1,354 @@
+#![allow(dead_code)]
+
+use crate::matrix::Matrix;
+use crate::ray::Ray;
+use crate::raytuple::{Axis, RayTuple};
+
+//Rough price of visiting a BVH node, as a fraction of intersecting one shape
+const SAH_TRAVERSAL_COST: f64 = 0.125;
+
+//Axis aligned box, an empty box has min above max so adding any point fixes it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BoundingBox {
+    pub min: RayTuple,
+    pub max: RayTuple,
+}
+
+impl BoundingBox {
+    pub fn new(min: RayTuple, max: RayTuple) -> Self {
+        Self { min, max }
+    }
+
+    pub fn empty() -> Self {
+        Self {
+            min: RayTuple::point(f64::INFINITY, f64::INFINITY, f64::INFINITY),
+            max: RayTuple::point(f64::NEG_INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.min.x > self.max.x || self.min.y > self.max.y || self.min.z > self.max.z
+    }
+
+    pub fn add_point(&mut self, p: RayTuple) {
+        self.min = RayTuple::point(
+            self.min.x.min(p.x),
+            self.min.y.min(p.y),
+            self.min.z.min(p.z),
+        );
+        self.max = RayTuple::point(
+            self.max.x.max(p.x),
+            self.max.y.max(p.y),
+            self.max.z.max(p.z),
+        );
+    }
+
+    pub fn merge(&mut self, other: BoundingBox) {
+        if !other.is_empty() {
+            self.add_point(other.min);
+            self.add_point(other.max);
+        }
+    }
+
+    pub fn is_finite(&self) -> bool {
+        [self.min, self.max]
+            .iter()
+            .all(|c| c.x.is_finite() && c.y.is_finite() && c.z.is_finite())
+    }
+
+    pub fn contains_point(&self, p: RayTuple) -> bool {
+        self.min.x <= p.x
+            && p.x <= self.max.x
+            && self.min.y <= p.y
+            && p.y <= self.max.y
+            && self.min.z <= p.z
+            && p.z <= self.max.z
+    }
+
+    pub fn center(&self) -> RayTuple {
+        RayTuple::point(
+            (self.min.x + self.max.x) / 2.0,
+            (self.min.y + self.max.y) / 2.0,
+            (self.min.z + self.max.z) / 2.0,
+        )
+    }
+
+    pub fn size(&self) -> RayTuple {
+        self.max - self.min
+    }
+
+    //Ties go to x, then y
+    pub fn longest_axis(&self) -> Axis {
+        let size = self.size();
+        if size.x >= size.y && size.x >= size.z {
+            Axis::X
+        } else if size.y >= size.z {
+            Axis::Y
+        } else {
+            Axis::Z
+        }
+    }
+
+    //An empty box fits inside anything
+    pub fn contains_box(&self, other: BoundingBox) -> bool {
+        other.is_empty() || (self.contains_point(other.min) && self.contains_point(other.max))
+    }
+
+    //Two halves cut through the middle of the given axis
+    pub fn split_along(&self, axis: Axis) -> (BoundingBox, BoundingBox) {
+        let middle = axis.of(self.center());
+        let mut left_max = self.max;
+        let mut right_min = self.min;
+        match axis {
+            Axis::X => (left_max.x, right_min.x) = (middle, middle),
+            Axis::Y => (left_max.y, right_min.y) = (middle, middle),
+            Axis::Z => (left_max.z, right_min.z) = (middle, middle),
+        }
+        (
+            BoundingBox::new(self.min, left_max),
+            BoundingBox::new(right_min, self.max),
+        )
+    }
+
+    pub fn surface_area(&self) -> f64 {
+        if self.is_empty() {
+            return 0.0;
+        }
+        let size = self.size();
+        2.0 * (size.x * size.y + size.y * size.z + size.z * size.x)
+    }
+
+    //Surface area heuristic for splitting this box's shapes into two children: a ray
+    //that hits this box hits each child in proportion to its area and then tests
+    //every shape inside. Lower is better, compare against count as a leaf.
+    pub fn sah_cost(
+        &self,
+        left: BoundingBox,
+        left_count: usize,
+        right: BoundingBox,
+        right_count: usize,
+    ) -> f64 {
+        let area = self.surface_area();
+        if area <= 0.0 {
+            return SAH_TRAVERSAL_COST + (left_count + right_count) as f64;
+        }
+        SAH_TRAVERSAL_COST
+            + (left.surface_area() * left_count as f64 + right.surface_area() * right_count as f64)
+                / area
+    }
+
+    //Slab test, true when the ray's line passes through the box in front of the origin
+    pub fn intersects(&self, r: Ray) -> bool {
+        self.line_span(r).is_some_and(|(_, exit)| exit >= 0.0)
+    }
+
+    //The t range where the ray's line is inside the box, negative ts included.
+    //None when the line misses it.
+    pub fn line_span(&self, r: Ray) -> Option<(f64, f64)> {
+        if self.is_empty() {
+            return None;
+        }
+        let mut tmin = f64::NEG_INFINITY;
+        let mut tmax = f64::INFINITY;
+        for (origin, direction, min, max) in [
+            (r.origin.x, r.direction.x, self.min.x, self.max.x),
+            (r.origin.y, r.direction.y, self.min.y, self.max.y),
+            (r.origin.z, r.direction.z, self.min.z, self.max.z),
+        ] {
+            if direction.abs() < 0.00001 {
+                //parallel to this slab, so the origin has to already be between its sides
+                if origin < min || origin > max {
+                    return None;
+                }
+                continue;
+            }
+            let t1 = (min - origin) / direction;
+            let t2 = (max - origin) / direction;
+            tmin = tmin.max(t1.min(t2));
+            tmax = tmax.min(t1.max(t2));
+        }
+        (tmin <= tmax).then_some((tmin, tmax))
+    }
+
+    //Box around all eight transformed corners. Infinite boxes (planes) can't be
+    //transformed corner by corner, instead each new axis adds up the old ranges it
+    //mixes in, so a plane only spreads to infinity along the axes it tilts into.
+    pub fn transform(&self, m: Matrix) -> BoundingBox {
+        if self.is_empty() {
+            return *self;
+        }
+        if !self.is_finite() {
+            let ranges = [
+                (self.min.x, self.max.x),
+                (self.min.y, self.max.y),
+                (self.min.z, self.max.z),
+            ];
+            let axis = |row: usize| {
+                let (mut lo, mut hi) = (m[row][3], m[row][3]);
+                for (col, (min, max)) in ranges.iter().enumerate() {
+                    //skipping zero factors keeps 0 * infinity from turning into NaN
+                    let factor = m[row][col];
+                    if factor != 0.0 {
+                        lo += (factor * min).min(factor * max);
+                        hi += (factor * min).max(factor * max);
+                    }
+                }
+                (lo, hi)
+            };
+            let (x, y, z) = (axis(0), axis(1), axis(2));
+            return BoundingBox::new(
+                RayTuple::point(x.0, y.0, z.0),
+                RayTuple::point(x.1, y.1, z.1),
+            );
+        }
+
+        let mut result = BoundingBox::empty();
+        for x in [self.min.x, self.max.x] {
+            for y in [self.min.y, self.max.y] {
+                for z in [self.min.z, self.max.z] {
+                    result.add_point(m * RayTuple::point(x, y, z));
+                }
+            }
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::{FRAC_1_SQRT_2, FRAC_PI_4, SQRT_2};
+
+    #[test]
+    fn adding_points_grows_the_box() {
+        let mut b = BoundingBox::empty();
+        assert!(b.is_empty());
+
+        b.add_point(RayTuple::point(-5.0, 2.0, 0.0));
+        b.add_point(RayTuple::point(7.0, 0.0, -3.0));
+        assert_eq!(b.min, RayTuple::point(-5.0, 0.0, -3.0));
+        assert_eq!(b.max, RayTuple::point(7.0, 2.0, 0.0));
+        assert!(b.contains_point(RayTuple::point(0.0, 1.0, -1.0)));
+        assert!(!b.contains_point(RayTuple::point(8.0, 1.0, -1.0)));
+    }
+
+    #[test]
+    fn transforming_a_box_bounds_its_corners() {
+        let b = BoundingBox::new(
+            RayTuple::point(-1.0, -1.0, -1.0),
+            RayTuple::point(1.0, 1.0, 1.0),
+        );
+        let t = b.transform(Matrix::rotation_x(FRAC_PI_4) * Matrix::rotation_y(FRAC_PI_4));
+
+        assert_eq!(
+            t.min,
+            RayTuple::point(-SQRT_2, -1.0 - FRAC_1_SQRT_2, -1.0 - FRAC_1_SQRT_2)
+        );
+        assert_eq!(
+            t.max,
+            RayTuple::point(SQRT_2, 1.0 + FRAC_1_SQRT_2, 1.0 + FRAC_1_SQRT_2)
+        );
+    }
+
+    #[test]
+    fn ray_box_slab_test() {
+        let b = BoundingBox::new(
+            RayTuple::point(-1.0, -1.0, -1.0),
+            RayTuple::point(1.0, 1.0, 1.0),
+        );
+        let toward = Ray::new(
+            RayTuple::point(0.5, 0.5, -5.0),
+            RayTuple::vector(0.0, 0.0, 1.0),
+        );
+        let beside = Ray::new(
+            RayTuple::point(2.0, 0.5, -5.0),
+            RayTuple::vector(0.0, 0.0, 1.0),
+        );
+        let away = Ray::new(
+            RayTuple::point(0.0, 0.0, -5.0),
+            RayTuple::vector(0.0, 0.0, -1.0),
+        );
+
+        assert!(b.intersects(toward));
+        assert!(!b.intersects(beside));
+        assert!(!b.intersects(away));
+        assert!(!BoundingBox::empty().intersects(toward));
+    }
+
+    #[test]
+    fn merging_disjoint_boxes_encloses_both() {
+        let a = BoundingBox::new(
+            RayTuple::point(-3.0, -1.0, 0.0),
+            RayTuple::point(-2.0, 1.0, 1.0),
+        );
+        let b = BoundingBox::new(
+            RayTuple::point(4.0, 2.0, -5.0),
+            RayTuple::point(6.0, 3.0, -4.0),
+        );
+        let mut merged = a;
+        merged.merge(b);
+
+        assert!(merged.contains_box(a));
+        assert!(merged.contains_box(b));
+        assert!(!a.contains_box(merged));
+        assert!(a.contains_box(BoundingBox::empty()));
+        assert_eq!(merged.min, RayTuple::point(-3.0, -1.0, -5.0));
+        assert_eq!(merged.max, RayTuple::point(6.0, 3.0, 1.0));
+    }
+
+    #[test]
+    fn split_along_halves_the_longest_axis() {
+        let b = BoundingBox::new(
+            RayTuple::point(-1.0, 0.0, 2.0),
+            RayTuple::point(1.0, 8.0, 4.0),
+        );
+        assert_eq!(b.longest_axis(), Axis::Y);
+
+        let (low, high) = b.split_along(b.longest_axis());
+        assert_eq!(low.min, RayTuple::point(-1.0, 0.0, 2.0));
+        assert_eq!(low.max, RayTuple::point(1.0, 4.0, 4.0));
+        assert_eq!(high.min, RayTuple::point(-1.0, 4.0, 2.0));
+        assert_eq!(high.max, RayTuple::point(1.0, 8.0, 4.0));
+        assert_eq!(low.surface_area(), high.surface_area());
+        assert!(b.contains_box(low) && b.contains_box(high));
+    }
+
+    #[test]
+    fn tight_split_costs_less_than_degenerate_one() {
+        let left = BoundingBox::new(
+            RayTuple::point(-10.0, -1.0, -1.0),
+            RayTuple::point(-8.0, 1.0, 1.0),
+        );
+        let right = BoundingBox::new(
+            RayTuple::point(8.0, -1.0, -1.0),
+            RayTuple::point(10.0, 1.0, 1.0),
+        );
+        let mut parent = left;
+        parent.merge(right);
+
+        let tight = parent.sah_cost(left, 5, right, 5);
+        let degenerate = parent.sah_cost(parent, 10, BoundingBox::empty(), 0);
+
+        assert!(tight < degenerate);
+        assert!(tight < 10.0);
+    }
+
+    #[test]
+    fn transformed_plane_box_stays_flat_unless_tilted() {
+        let plane = BoundingBox::new(
+            RayTuple::point(f64::NEG_INFINITY, 0.0, f64::NEG_INFINITY),
+            RayTuple::point(f64::INFINITY, 0.0, f64::INFINITY),
+        );
+
+        let raised = plane.transform(Matrix::translation(1.0, 2.0, 3.0));
+        assert_eq!(raised.min.y, 2.0);
+        assert_eq!(raised.max.y, 2.0);
+        assert_eq!(raised.min.x, f64::NEG_INFINITY);
+        assert_eq!(raised.max.z, f64::INFINITY);
+
+        let tilted = plane.transform(Matrix::rotation_x(FRAC_PI_4));
+        assert_eq!(tilted.min.y, f64::NEG_INFINITY);
+        assert_eq!(tilted.max.y, f64::INFINITY);
+        assert!(!tilted.min.x.is_nan() && !tilted.max.z.is_nan());
+    }
+}
@@ -0,0 +1,210 @@
+#![allow(dead_code)]
+
+use crate::bounds::BoundingBox;
+use crate::intersection::{Intersection, NearestHits};
+use crate::ray::Ray;
+use crate::raytuple::RayTuple;
+use crate::shape::Shape;
+use std::thread;
+
+//Leaves hold at most this many objects
+const LEAF_SIZE: usize = 4;
+//Subtrees with more objects than this build their two halves on separate threads
+const PARALLEL_THRESHOLD: usize = 64;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum BvhNode {
+    Leaf {
+        bounds: BoundingBox,
+        objects: Vec<usize>,
+    },
+    Branch {
+        bounds: BoundingBox,
+        left: Box<BvhNode>,
+        right: Box<BvhNode>,
+    },
+}
+
+//Bounding volume hierarchy over a list of shapes, referring to them by index.
+//Shapes without a finite box (planes, groups) and moving shapes are kept in
+//`unbounded` and tested against every ray.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Bvh {
+    pub root: Option<BvhNode>,
+    pub unbounded: Vec<usize>,
+}
+
+impl Bvh {
+    //Large subtrees are split on scoped threads, the tree is the same as build_serial's.
+    //Each threaded level doubles the threads, so they stop once there is one per core.
+    pub fn build(objects: &[Shape]) -> Self {
+        let cores = thread::available_parallelism().map_or(1, |n| n.get());
+        Self::build_with(objects, cores.next_power_of_two().trailing_zeros())
+    }
+
+    pub fn build_serial(objects: &[Shape]) -> Self {
+        Self::build_with(objects, 0)
+    }
+
+    fn build_with(objects: &[Shape], parallel_depth: u32) -> Self {
+        let mut items = Vec::new();
+        let mut unbounded = Vec::new();
+        for (index, o) in objects.iter().enumerate() {
+            //the same box intersect works in, the shape's own transform at time 0
+            let b = o.bounds().transform(o.transform);
+            if b.is_empty() || !b.is_finite() || o.velocity != RayTuple::vector(0.0, 0.0, 0.0) {
+                unbounded.push(index);
+            } else {
+                items.push((index, b));
+            }
+        }
+
+        let root = if items.is_empty() {
+            None
+        } else {
+            Some(build_node(items, parallel_depth))
+        };
+        Self { root, unbounded }
+    }
+
+    //Nearest non negative hit. Nodes are only entered when the ray passes through their
+    //box closer than the nearest hit found so far.
+    pub fn nearest_hit<'a>(&self, objects: &'a [Shape], r: Ray) -> Option<Intersection<'a>> {
+        let mut nearest = NearestHits::new(r, 1);
+        for &index in &self.unbounded {
+            nearest.offer(&objects[index]);
+        }
+        if let Some(root) = &self.root {
+            visit(root, objects, &mut nearest);
+        }
+        nearest.into_hits().pop()
+    }
+}
+
+//The top `parallel_depth` levels of large subtrees split across threads, below that
+//the build is serial
+fn build_node(mut items: Vec<(usize, BoundingBox)>, parallel_depth: u32) -> BvhNode {
+    let mut bounds = BoundingBox::empty();
+    let mut centers = BoundingBox::empty();
+    for (_, b) in &items {
+        bounds.merge(*b);
+        centers.add_point(b.center());
+    }
+
+    if items.len() <= LEAF_SIZE {
+        return BvhNode::Leaf {
+            bounds,
+            objects: items.iter().map(|(index, _)| *index).collect(),
+        };
+    }
+
+    //median split along the widest spread of centers, ties go by index so the
+    //order never depends on how the work was scheduled
+    let axis = centers.longest_axis();
+    items.sort_by(|a, b| {
+        axis.of(a.1.center())
+            .total_cmp(&axis.of(b.1.center()))
+            .then(a.0.cmp(&b.0))
+    });
+
+    let count = items.len();
+    let right_items = items.split_off(count / 2);
+    let (left, right) = if parallel_depth > 0 && count > PARALLEL_THRESHOLD {
+        thread::scope(|s| {
+            let left = s.spawn(move || build_node(items, parallel_depth - 1));
+            let right = build_node(right_items, parallel_depth - 1);
+            (left.join().unwrap(), right)
+        })
+    } else {
+        (
+            build_node(items, parallel_depth.saturating_sub(1)),
+            build_node(right_items, parallel_depth.saturating_sub(1)),
+        )
+    };
+
+    BvhNode::Branch {
+        bounds,
+        left: Box::new(left),
+        right: Box::new(right),
+    }
+}
+
+fn visit<'a>(node: &BvhNode, objects: &'a [Shape], nearest: &mut NearestHits<'a>) {
+    match node {
+        BvhNode::Leaf {
+            bounds,
+            objects: indices,
+        } => {
+            if nearest.reaches(bounds) {
+                for &index in indices {
+                    nearest.offer(&objects[index]);
+                }
+            }
+        }
+        BvhNode::Branch {
+            bounds,
+            left,
+            right,
+        } => {
+            if nearest.reaches(bounds) {
+                visit(left, objects, nearest);
+                visit(right, objects, nearest);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matrix::Matrix;
+    use crate::rng::Rng;
+
+    fn scattered_spheres(count: usize) -> Vec<Shape> {
+        let mut rng = Rng::new(3);
+        let mut objects = vec![Shape::plane()];
+        for _ in 0..count {
+            let mut s = Shape::sphere();
+            s.transform = Matrix::translation(
+                rng.next_f64() * 20.0 - 10.0,
+                rng.next_f64() * 20.0 - 10.0,
+                rng.next_f64() * 20.0 - 10.0,
+            ) * Matrix::scaling(0.4, 0.4, 0.4);
+            objects.push(s);
+        }
+        objects
+    }
+
+    #[test]
+    fn parallel_build_matches_serial_build() {
+        let objects = scattered_spheres(200);
+        let parallel = Bvh::build(&objects);
+        let serial = Bvh::build_serial(&objects);
+        assert_eq!(parallel, serial);
+        //as if there were eight cores, whatever this machine has
+        assert_eq!(Bvh::build_with(&objects, 3), serial);
+        assert_eq!(parallel.unbounded, vec![0]);
+
+        let mut rng = Rng::new(8);
+        for _ in 0..200 {
+            let r = Ray::from_points(
+                RayTuple::point(0.0, 0.0, -30.0),
+                RayTuple::point(
+                    rng.next_f64() * 20.0 - 10.0,
+                    rng.next_f64() * 20.0 - 10.0,
+                    0.0,
+                ),
+            );
+            let a = parallel.nearest_hit(&objects, r);
+            let b = serial.nearest_hit(&objects, r);
+            let mut every = Vec::new();
+            for o in &objects {
+                every.append(&mut o.intersect(r));
+            }
+            let expected = Intersection::hit(every);
+
+            assert_eq!(a, b);
+            assert_eq!(a.map(|i| i.object.id()), expected.map(|i| i.object.id()));
+        }
+    }
+}
@@ -1,13 +1,23 @@
 #![allow(dead_code)]
 use std::f64::consts::{FRAC_PI_2, FRAC_PI_3, FRAC_PI_4};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
 
-use crate::canvas::Canvas;
-use crate::color::Color;
+use crate::bounds::BoundingBox;
+use crate::canvas::{Canvas, CanvasRgba};
+use crate::color::{Color, ToneMap};
 use crate::matrix::Matrix;
 use crate::ray::Ray;
 use crate::raytuple::RayTuple;
+use crate::rng::Rng;
 use crate::shape::Shape;
 use crate::world::World;
+use uuid::Uuid;
+
+//Diffuse bounces followed per path by render_gi
+const GI_BOUNCES: i32 = 3;
+//Reflection and refraction depth renders start with unless max_depth is changed
+const DEFAULT_DEPTH: i32 = 5;
 
 #[derive(Debug)]
 pub struct Camera {
@@ -15,9 +25,27 @@ pub struct Camera {
     vsize: i32,
     field_of_view: f64,
     pub transform: Matrix,
+    //length of the shutter interval in ray time, 0 renders a single instant
+    pub shutter: f64,
+    //antialiasing rays per pixel, each through its own jittered point of the pixel.
+    //0 and 1 both trace the single ray through the pixel's center.
+    pub samples: usize,
+    //recursion depth handed to World::color_at for every ray
+    pub max_depth: i32,
     pixel_size: f64,
     half_width: f64,
     half_height: f64,
+    letterbox: Option<Letterbox>,
+}
+
+//Region of the image the scene is drawn into, pixels outside it are bars of `color`
+#[derive(Debug, Clone, Copy)]
+struct Letterbox {
+    x: i32,
+    y: i32,
+    width: i32,
+    height: i32,
+    color: Color,
 }
 
 impl Camera {
@@ -37,15 +65,103 @@ impl Camera {
             vsize,
             field_of_view,
             transform: Matrix::identity(),
+            shutter: 0.0,
+            samples: 1,
+            max_depth: DEFAULT_DEPTH,
             pixel_size: (half_width * 2.0) / hsize as f64,
             half_width,
             half_height,
+            letterbox: None,
+        }
+    }
+
+    pub fn hsize(&self) -> i32 {
+        self.hsize
+    }
+
+    pub fn vsize(&self) -> i32 {
+        self.vsize
+    }
+
+    pub fn field_of_view(&self) -> f64 {
+        self.field_of_view
+    }
+
+    pub fn pixel_size(&self) -> f64 {
+        self.pixel_size
+    }
+
+    pub fn half_width(&self) -> f64 {
+        self.half_width
+    }
+
+    pub fn half_height(&self) -> f64 {
+        self.half_height
+    }
+
+    //Banks the view by rotating about its viewing axis. It is applied on top of the
+    //current transform, so set the view transform first.
+    pub fn set_roll(&mut self, radians: f64) {
+        self.transform = Matrix::rotation_z(radians) * self.transform;
+    }
+
+    //Keeps the scene at content_aspect (width / height) instead of stretching it, by
+    //drawing it into the largest centered region of that shape and filling the bars
+    //on either side with color
+    pub fn set_letterbox(&mut self, content_aspect: f64, color: Color) {
+        let aspect = self.hsize as f64 / self.vsize as f64;
+        let (width, height) = if content_aspect < aspect {
+            let width = (self.vsize as f64 * content_aspect).round() as i32;
+            (width.clamp(1, self.hsize), self.vsize)
+        } else {
+            let height = (self.hsize as f64 / content_aspect).round() as i32;
+            (self.hsize, height.clamp(1, self.vsize))
+        };
+
+        let content = Camera::new(width, height, self.field_of_view);
+        self.pixel_size = content.pixel_size;
+        self.half_width = content.half_width;
+        self.half_height = content.half_height;
+        self.letterbox = Some(Letterbox {
+            x: (self.hsize - width) / 2,
+            y: (self.vsize - height) / 2,
+            width,
+            height,
+            color,
+        });
+    }
+
+    //The bar color when the pixel is outside the letterboxed content
+    pub fn letterbox_color(&self, px: i32, py: i32) -> Option<Color> {
+        let b = self.letterbox?;
+        let inside = px >= b.x && px < b.x + b.width && py >= b.y && py < b.y + b.height;
+        if inside {
+            None
+        } else {
+            Some(b.color)
         }
     }
 
     pub fn ray_for_pixel(&self, px: i32, py: i32) -> Ray {
-        let xoffset = (px as f64 + 0.5) * self.pixel_size;
-        let yoffset = (py as f64 + 0.5) * self.pixel_size;
+        self.ray_for_pass(px, py, 0)
+    }
+
+    //The pixel's ray as drawn on a given accumulation pass, the pass picks a new moment
+    //of the shutter. Pass 0 is the ray render uses.
+    fn ray_for_pass(&self, px: i32, py: i32, pass: u64) -> Ray {
+        self.ray_through(px, py, (0.5, 0.5), pass)
+    }
+
+    //Ray through the point (u, v) of the pixel, each in [0, 1) from its top left corner
+    fn ray_through(&self, px: i32, py: i32, (u, v): (f64, f64), pass: u64) -> Ray {
+        //letterboxed pixels are numbered from the corner of the content
+        let (px, py) = match self.letterbox {
+            Some(b) => (px - b.x, py - b.y),
+            None => (px, py),
+        };
+
+        let xoffset = (px as f64 + u) * self.pixel_size;
+        let yoffset = (py as f64 + v) * self.pixel_size;
 
         let world_x = self.half_width - xoffset;
         let world_y = self.half_height - yoffset;
@@ -54,23 +170,317 @@ impl Camera {
         let origin = self.transform.inverse().unwrap() * RayTuple::point(0.0, 0.0, 0.0);
         let direction = (pixel - origin).normalize();
 
-        Ray::new(origin, direction)
+        //each pixel samples its own moment of the shutter, seeded so renders repeat
+        let mut time = 0.0;
+        if self.shutter > 0.0 {
+            let seed =
+                (((px as u64) << 32) | py as u32 as u64) ^ pass.wrapping_mul(0x9e37_79b9_7f4a_7c15);
+            time = Rng::new(seed).next_f64() * self.shutter;
+        }
+
+        Ray::new(origin, direction).with_time(time)
     }
 
-    pub fn render(self, mut w: World) -> Canvas {
+    //Looks along direction from far enough back that the sphere around the bounded objects
+    //fits the narrower side of the view. Unbounded ones (planes) are left out of the framing,
+    //and a scene with nothing bounded is left alone.
+    pub fn frame_scene(&mut self, w: &World, direction: RayTuple) {
+        let mut bounds = BoundingBox::empty();
+        for o in &w.objects {
+            let b = o.bounds().transform(o.transform);
+            if b.is_finite() {
+                bounds.merge(b);
+            }
+        }
+        if bounds.is_empty() {
+            return;
+        }
+        let size = bounds.size();
+
+        let forward = direction.normalize();
+        let radius = size.magnitude() / 2.0;
+        let half_angle = self.half_width.min(self.half_height).atan();
+        let distance = radius / half_angle.sin();
+
+        let to = bounds.center();
+        let from = to - forward * distance;
+        let mut up = RayTuple::vector(0.0, 1.0, 0.0);
+        if forward.cross(up).magnitude() < 0.00001 {
+            up = RayTuple::vector(0.0, 0.0, 1.0);
+        }
+        self.transform = Matrix::view_transform(from, to, up);
+    }
+
+    //Distance along the ray through the exact middle of the image to the nearest
+    //surface, for focusing on whatever the camera is pointed at
+    pub fn autofocus(&self, w: &World) -> Option<f64> {
+        let inverse = self.transform.inverse().unwrap();
+        let origin = inverse * RayTuple::point(0.0, 0.0, 0.0);
+        let target = inverse * RayTuple::point(0.0, 0.0, -1.0);
+        let ray = Ray::from_points(origin, target);
+
+        w.nearest_hit(ray).map(|hit| hit.t)
+    }
+
+    pub fn render(self, w: World) -> Canvas {
         let mut image = Canvas::new(self.hsize, self.vsize);
 
         let camera_vsize = self.vsize;
         let camera_hsize = self.hsize;
         for y in 0..camera_vsize {
             for x in 0..camera_hsize {
-                let ray = self.ray_for_pixel(x, y);
-                let color = w.color_at(ray, 5);
+                let color = self.pixel_color(&w, x, y);
                 image.write_pixel(x, y, color);
             }
         }
         image
     }
+
+    //Adds up full passes in an unclamped buffer and averages them. Each pass draws its own
+    //shutter times and light samples, so sampled effects refine the more passes are run.
+    //A single pass is the image render makes with one antialiasing sample.
+    //Panics when passes is 0, there is nothing to average.
+    pub fn render_accumulate(self, w: World, passes: usize) -> Canvas {
+        self.render_accumulate_tone_mapped(w, passes, ToneMap::Linear)
+    }
+
+    //render_accumulate with the averaged colors tone mapped before they are written
+    pub fn render_accumulate_tone_mapped(
+        self,
+        mut w: World,
+        passes: usize,
+        tone_map: ToneMap,
+    ) -> Canvas {
+        assert!(passes > 0, "render_accumulate needs at least one pass");
+        let mut buffer = vec![Color::new(0.0, 0.0, 0.0); (self.hsize * self.vsize) as usize];
+        for pass in 0..passes as u64 {
+            w.sample_seed = pass;
+            for y in 0..self.vsize {
+                for x in 0..self.hsize {
+                    let color = match self.letterbox_color(x, y) {
+                        Some(bar) => bar,
+                        None => w.color_at(self.ray_for_pass(x, y, pass), self.max_depth),
+                    };
+                    let i = (y * self.hsize + x) as usize;
+                    buffer[i] = buffer[i] + color;
+                }
+            }
+        }
+
+        let mut image = Canvas::new(self.hsize, self.vsize);
+        let scale = 1.0 / passes as f64;
+        for y in 0..self.vsize {
+            for x in 0..self.hsize {
+                let average = buffer[(y * self.hsize + x) as usize] * scale;
+                image.write_pixel(x, y, tone_map.apply(average));
+            }
+        }
+        image
+    }
+
+    //Renders over an existing canvas instead of allocating one, for repeated renders.
+    //The canvas must match the camera's size.
+    pub fn render_into(&self, w: &World, canvas: &mut Canvas) -> Result<(), String> {
+        if canvas.get_width() != self.hsize || canvas.get_height() != self.vsize {
+            return Err(format!(
+                "canvas is {}x{} but the camera renders {}x{}",
+                canvas.get_width(),
+                canvas.get_height(),
+                self.hsize,
+                self.vsize
+            ));
+        }
+
+        for y in 0..self.vsize {
+            for (x, color) in self.render_row(w, y).into_iter().enumerate() {
+                canvas.write_pixel(x as i32, y, color);
+            }
+        }
+        Ok(())
+    }
+
+    //Path traced render, each pixel averages `samples` paths from World::color_at_gi.
+    //Every pixel seeds its own generator, so renders repeat exactly.
+    pub fn render_gi(self, w: World, samples: usize) -> Canvas {
+        let mut image = Canvas::new(self.hsize, self.vsize);
+
+        for y in 0..self.vsize {
+            for x in 0..self.hsize {
+                image.write_pixel(x, y, self.gi_pixel_color(&w, x, y, samples));
+            }
+        }
+        image
+    }
+
+    //render_gi with the rows split across threads as in render_parallel, same image
+    pub fn render_gi_parallel(self, w: World, samples: usize, threads: usize) -> Canvas {
+        self.render_parallel_with(w, threads, |camera, w, x, y| {
+            camera.gi_pixel_color(w, x, y, samples)
+        })
+    }
+
+    //Renders with coverage so the layer can be composited, missed rays are transparent
+    pub fn render_rgba(self, w: World) -> CanvasRgba {
+        let mut image = CanvasRgba::new(self.hsize, self.vsize);
+
+        for y in 0..self.vsize {
+            for x in 0..self.hsize {
+                if let Some(bar) = self.letterbox_color(x, y) {
+                    image.write_pixel(x, y, bar, 1.0);
+                    continue;
+                }
+                let ray = self.ray_for_pixel(x, y);
+                let (color, alpha) = w.color_at_coverage(ray, self.max_depth);
+                image.write_pixel(x, y, color, alpha);
+            }
+        }
+        image
+    }
+
+    //Object id pass for compositing: the id of the nearest object each pixel sees,
+    //row by row from the top left. Background and letterbox pixels are None.
+    pub fn render_object_ids(self, w: World) -> Vec<Option<Uuid>> {
+        let mut ids = Vec::with_capacity((self.hsize * self.vsize) as usize);
+        for y in 0..self.vsize {
+            for x in 0..self.hsize {
+                if self.letterbox_color(x, y).is_some() {
+                    ids.push(None);
+                    continue;
+                }
+                let hit = w.nearest_hit(self.ray_for_pixel(x, y));
+                ids.push(hit.map(|i| i.object.id()));
+            }
+        }
+        ids
+    }
+
+    //Splits the rows across worker threads, 0 uses every available core.
+    //The workers share the world, tracing only reads it.
+    pub fn render_parallel(self, w: World, threads: usize) -> Canvas {
+        self.render_parallel_with(w, threads, Self::pixel_color)
+    }
+
+    fn render_parallel_with(
+        &self,
+        w: World,
+        threads: usize,
+        pixel_color: impl Fn(&Camera, &World, i32, i32) -> Color + Sync,
+    ) -> Canvas {
+        let threads = if threads == 0 {
+            thread::available_parallelism().map_or(1, |n| n.get())
+        } else {
+            threads
+        };
+
+        let mut image = Canvas::new(self.hsize, self.vsize);
+        let camera = self;
+        let pixel_color = &pixel_color;
+        let rows: Vec<(i32, Vec<Color>)> = thread::scope(|scope| {
+            let workers: Vec<_> = (0..threads)
+                .map(|worker| {
+                    let world = &w;
+                    scope.spawn(move || {
+                        (worker as i32..camera.vsize)
+                            .step_by(threads)
+                            .map(|y| {
+                                let row = (0..camera.hsize)
+                                    .map(|x| pixel_color(camera, world, x, y))
+                                    .collect();
+                                (y, row)
+                            })
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect();
+
+            workers
+                .into_iter()
+                .flat_map(|worker| worker.join().unwrap())
+                .collect()
+        });
+
+        for (y, row) in rows {
+            for (x, color) in row.into_iter().enumerate() {
+                image.write_pixel(x as i32, y, color);
+            }
+        }
+        image
+    }
+
+    //Yields the image one scanline at a time so the caller can stream rows out
+    //without holding the whole canvas in memory
+    pub fn render_rows(&self, w: World) -> impl Iterator<Item = Vec<Color>> + '_ {
+        (0..self.vsize).map(move |y| self.render_row(&w, y))
+    }
+
+    //Same as render_rows but stops before the next scanline once cancel is set
+    pub fn render_rows_until<'a>(
+        &'a self,
+        w: World,
+        cancel: &'a AtomicBool,
+    ) -> impl Iterator<Item = Vec<Color>> + 'a {
+        (0..self.vsize)
+            .take_while(move |_| !cancel.load(Ordering::Relaxed))
+            .map(move |y| self.render_row(&w, y))
+    }
+
+    //Returns None if cancel was set before every row finished
+    pub fn render_cancellable(&self, w: World, cancel: &AtomicBool) -> Option<Canvas> {
+        let mut image = Canvas::new(self.hsize, self.vsize);
+        let mut completed = 0;
+
+        for (y, row) in self.render_rows_until(w, cancel).enumerate() {
+            for (x, color) in row.into_iter().enumerate() {
+                image.write_pixel(x as i32, y as i32, color);
+            }
+            completed += 1;
+        }
+
+        if completed < self.vsize {
+            None
+        } else {
+            Some(image)
+        }
+    }
+
+    fn render_row(&self, w: &World, y: i32) -> Vec<Color> {
+        (0..self.hsize).map(|x| self.pixel_color(w, x, y)).collect()
+    }
+
+    //Average over the antialiasing samples. The jitter is seeded by the pixel and each
+    //sample draws its own shutter time, so renders repeat.
+    fn pixel_color(&self, w: &World, x: i32, y: i32) -> Color {
+        if let Some(bar) = self.letterbox_color(x, y) {
+            return bar;
+        }
+        if self.samples <= 1 {
+            return w.color_at(self.ray_for_pixel(x, y), self.max_depth);
+        }
+
+        let mut rng = Rng::new(((x as u64) << 32) | y as u32 as u64);
+        let mut total = Color::new(0.0, 0.0, 0.0);
+        for sample in 0..self.samples as u64 {
+            let offset = (rng.next_f64(), rng.next_f64());
+            let ray = self.ray_through(x, y, offset, sample);
+            total = total + w.color_at(ray, self.max_depth);
+        }
+        total * (1.0 / self.samples as f64)
+    }
+
+    //Average of `samples` paths, every pixel seeds its own generator
+    fn gi_pixel_color(&self, w: &World, x: i32, y: i32, samples: usize) -> Color {
+        if let Some(bar) = self.letterbox_color(x, y) {
+            return bar;
+        }
+        let samples = samples.max(1);
+        let mut rng = Rng::new(((x as u64) << 32) | y as u32 as u64);
+        let ray = self.ray_for_pixel(x, y);
+        let mut total = Color::new(0.0, 0.0, 0.0);
+        for _ in 0..samples {
+            total = total + w.color_at_gi(ray, GI_BOUNCES, &mut rng);
+        }
+        total * (1.0 / samples as f64)
+    }
 }
 
 pub fn chapter_seven_scene() {
@@ -84,14 +494,14 @@ pub fn chapter_seven_scene() {
         * Matrix::rotation_y(-FRAC_PI_4)
         * Matrix::rotation_x(FRAC_PI_2)
         * Matrix::scaling(10.0, 0.01, 10.0);
-    left_wall.material = floor.material;
+    left_wall.material = floor.material.clone();
 
     let mut right_wall = Shape::sphere();
     right_wall.transform = Matrix::translation(0.0, 0.0, 5.0)
         * Matrix::rotation_y(FRAC_PI_4)
         * Matrix::rotation_x(FRAC_PI_2)
         * Matrix::scaling(10.0, 0.01, 10.0);
-    right_wall.material = floor.material;
+    right_wall.material = floor.material.clone();
 
     let mut middle = Shape::sphere();
     middle.transform = Matrix::translation(-0.5, 1.0, 0.5);
@@ -135,10 +545,11 @@ pub fn chapter_seven_scene() {
 
 #[cfg(test)]
 mod tests {
+    use crate::light::Light;
     use crate::raytuple::RayTuple;
 
     use super::*;
-    use crate::color::Color;
+    use crate::color::{Color, ToneMap};
     use std::f64::consts::{FRAC_PI_2, FRAC_PI_4};
 
     #[test]
@@ -209,4 +620,397 @@ mod tests {
 
         assert_eq!(image.pixel_at(5, 5), Color::new(0.38066, 0.47583, 0.2855));
     }
+
+    #[test]
+    fn render_rows_matches_render() {
+        let mut c = Camera::new(11, 11, FRAC_PI_2);
+        let from = RayTuple::point(0.0, 0.0, -5.0);
+        let to = RayTuple::point(0.0, 0.0, 0.0);
+        let up = RayTuple::vector(0.0, 1.0, 0.0);
+        c.transform = Matrix::view_transform(from, to, up);
+
+        let rows: Vec<Vec<Color>> = c.render_rows(World::default_world()).collect();
+        let image = c.render(World::default_world());
+
+        assert_eq!(rows.len(), 11);
+        for (y, row) in rows.iter().enumerate() {
+            assert_eq!(row.len(), 11);
+            for (x, color) in row.iter().enumerate() {
+                assert_eq!(*color, image.pixel_at(x as i32, y as i32));
+            }
+        }
+    }
+
+    #[test]
+    fn render_parallel_is_independent_of_thread_count() {
+        let mut c = Camera::new(11, 11, FRAC_PI_2);
+        let from = RayTuple::point(0.0, 0.0, -5.0);
+        let to = RayTuple::point(0.0, 0.0, 0.0);
+        let up = RayTuple::vector(0.0, 1.0, 0.0);
+        c.transform = Matrix::view_transform(from, to, up);
+
+        let mut c4 = Camera::new(11, 11, FRAC_PI_2);
+        c4.transform = c.transform;
+
+        let single = c.render_parallel(World::default_world(), 1);
+        let four = c4.render_parallel(World::default_world(), 4);
+
+        assert_eq!(single, four);
+        assert_eq!(single.pixel_at(5, 5), Color::new(0.38066, 0.47583, 0.2855));
+    }
+
+    #[test]
+    fn render_rgba_leaves_missed_pixels_transparent() {
+        let mut c = Camera::new(11, 11, FRAC_PI_2);
+        let from = RayTuple::point(0.0, 0.0, -5.0);
+        let to = RayTuple::point(0.0, 0.0, 0.0);
+        let up = RayTuple::vector(0.0, 1.0, 0.0);
+        c.transform = Matrix::view_transform(from, to, up);
+        let image = c.render_rgba(World::default_world());
+
+        assert_eq!(image.pixel_at(0, 0).1, 0.0);
+        assert_eq!(
+            image.pixel_at(5, 5),
+            (Color::new(0.38066, 0.47583, 0.2855), 1.0)
+        );
+    }
+
+    #[test]
+    fn cancelling_after_first_row_stops_render() {
+        let c = Camera::new(11, 11, FRAC_PI_2);
+        let cancel = AtomicBool::new(false);
+
+        let mut rows = 0;
+        for _ in c.render_rows_until(World::default_world(), &cancel) {
+            rows += 1;
+            cancel.store(true, Ordering::Relaxed);
+        }
+        assert_eq!(rows, 1);
+
+        assert!(c
+            .render_cancellable(World::default_world(), &cancel)
+            .is_none());
+
+        let not_cancelled = AtomicBool::new(false);
+        assert!(c
+            .render_cancellable(World::default_world(), &not_cancelled)
+            .is_some());
+    }
+
+    #[test]
+    fn accumulating_passes_of_fixed_scene_matches_single_render() {
+        let mut c = Camera::new(11, 11, FRAC_PI_2);
+        let from = RayTuple::point(0.0, 0.0, -5.0);
+        let to = RayTuple::point(0.0, 0.0, 0.0);
+        let up = RayTuple::vector(0.0, 1.0, 0.0);
+        c.transform = Matrix::view_transform(from, to, up);
+        let single = c.render_rows(World::default_world()).collect::<Vec<_>>();
+
+        let image = c.render_accumulate(World::default_world(), 4);
+        for (y, row) in single.iter().enumerate() {
+            for (x, color) in row.iter().enumerate() {
+                assert_eq!(*color, image.pixel_at(x as i32, y as i32));
+            }
+        }
+    }
+
+    #[test]
+    fn roll_turns_corner_rays_about_view_axis() {
+        let from = RayTuple::point(0.0, 0.0, -5.0);
+        let to = RayTuple::point(0.0, 0.0, 0.0);
+        let up = RayTuple::vector(0.0, 1.0, 0.0);
+        let mut level = Camera::new(11, 11, FRAC_PI_2);
+        level.transform = Matrix::view_transform(from, to, up);
+        let mut rolled = Camera::new(11, 11, FRAC_PI_2);
+        rolled.transform = level.transform;
+        rolled.set_roll(FRAC_PI_2);
+
+        assert_eq!(
+            rolled.ray_for_pixel(5, 5).direction,
+            level.ray_for_pixel(5, 5).direction
+        );
+        //a quarter turn carries the top left corner onto the bottom left one
+        assert_eq!(
+            rolled.ray_for_pixel(0, 0).direction,
+            level.ray_for_pixel(0, 10).direction
+        );
+        assert_ne!(
+            rolled.ray_for_pixel(0, 0).direction,
+            level.ray_for_pixel(0, 0).direction
+        );
+    }
+
+    #[test]
+    fn autofocus_finds_distance_to_center_object() {
+        let mut c = Camera::new(10, 10, FRAC_PI_2);
+        let from = RayTuple::point(0.0, 0.0, -5.0);
+        let to = RayTuple::point(0.0, 0.0, 0.0);
+        let up = RayTuple::vector(0.0, 1.0, 0.0);
+        c.transform = Matrix::view_transform(from, to, up);
+        let w = World::default_world();
+
+        assert_eq!(c.autofocus(&w), Some(4.0));
+
+        c.transform =
+            Matrix::view_transform(from, RayTuple::point(0.0, 5.0, -5.0), up.cross(to - from));
+        assert_eq!(c.autofocus(&w), None);
+    }
+
+    #[test]
+    fn render_into_reuses_a_matching_canvas() {
+        let mut c = Camera::new(11, 11, FRAC_PI_2);
+        let from = RayTuple::point(0.0, 0.0, -5.0);
+        let to = RayTuple::point(0.0, 0.0, 0.0);
+        let up = RayTuple::vector(0.0, 1.0, 0.0);
+        c.transform = Matrix::view_transform(from, to, up);
+        let w = World::default_world();
+
+        let mut canvas = Canvas::new(11, 11);
+        assert!(c.render_into(&w, &mut canvas).is_ok());
+
+        let mut wrong = Canvas::new(10, 11);
+        assert!(c.render_into(&w, &mut wrong).is_err());
+
+        assert_eq!(canvas, c.render(w));
+    }
+
+    #[test]
+    fn open_shutter_spreads_ray_times() {
+        let mut c = Camera::new(11, 11, FRAC_PI_2);
+        assert_eq!(c.ray_for_pixel(3, 4).time, 0.0);
+
+        c.shutter = 0.5;
+        let times: Vec<f64> = (0..11).map(|x| c.ray_for_pixel(x, 4).time).collect();
+        assert!(times.iter().all(|t| (0.0..0.5).contains(t)));
+        assert!(times.iter().any(|t| *t != times[0]));
+        assert_eq!(c.ray_for_pixel(3, 4).time, times[3]);
+    }
+
+    #[test]
+    fn framed_scene_fits_in_the_image() {
+        let mut w = World::default_world();
+        let mut far = Shape::cube();
+        far.transform = Matrix::translation(6.0, 2.0, -3.0) * Matrix::scaling(0.5, 2.0, 0.5);
+        w.objects.push(far);
+        let mut without_floor = Camera::new(160, 90, FRAC_PI_3);
+        without_floor.frame_scene(&w, RayTuple::vector(0.0, 0.0, 1.0));
+        //a floor under it all doesn't change the framing of the rest
+        let mut floor = Shape::plane();
+        floor.transform = Matrix::translation(0.0, -1.0, 0.0);
+        w.objects.push(floor);
+        let mut with_floor = Camera::new(160, 90, FRAC_PI_3);
+        with_floor.frame_scene(&w, RayTuple::vector(0.0, 0.0, 1.0));
+        assert_eq!(with_floor.transform, without_floor.transform);
+
+        for direction in [
+            RayTuple::vector(0.0, 0.0, 1.0),
+            RayTuple::vector(1.0, -1.0, 0.5),
+            RayTuple::vector(0.0, -1.0, 0.0),
+        ] {
+            let mut c = Camera::new(160, 90, FRAC_PI_3);
+            c.frame_scene(&w, direction);
+
+            for o in &w.objects {
+                let b = o.bounds().transform(o.transform);
+                if !b.is_finite() {
+                    continue;
+                }
+                for x in [b.min.x, b.max.x] {
+                    for y in [b.min.y, b.max.y] {
+                        for z in [b.min.z, b.max.z] {
+                            let p = c.transform * RayTuple::point(x, y, z);
+                            assert!(p.z < 0.0);
+                            assert!((p.x / -p.z).abs() <= c.half_width());
+                            assert!((p.y / -p.z).abs() <= c.half_height());
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn path_traced_render_repeats_and_sees_the_scene() {
+        let mut c = Camera::new(5, 5, FRAC_PI_2);
+        let from = RayTuple::point(0.0, 0.0, -5.0);
+        let to = RayTuple::point(0.0, 0.0, 0.0);
+        let up = RayTuple::vector(0.0, 1.0, 0.0);
+        c.transform = Matrix::view_transform(from, to, up);
+        let mut c2 = Camera::new(5, 5, FRAC_PI_2);
+        c2.transform = c.transform;
+
+        let first = c.render_gi(World::default_world(), 4);
+        let second = c2.render_gi(World::default_world(), 4);
+        assert_eq!(first, second);
+        assert_ne!(first.pixel_at(2, 2), Color::new(0.0, 0.0, 0.0));
+        assert_eq!(first.pixel_at(0, 0), Color::new(0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn letterboxing_wide_camera_keeps_content_aspect() {
+        let w = World::default_world();
+        let view = Matrix::view_transform(
+            RayTuple::point(0.0, 0.0, -5.0),
+            RayTuple::point(0.0, 0.0, 0.0),
+            RayTuple::vector(0.0, 1.0, 0.0),
+        );
+
+        let mut wide = Camera::new(32, 18, FRAC_PI_2);
+        wide.transform = view;
+        wide.set_letterbox(4.0 / 3.0, Color::new(0.0, 0.0, 0.0));
+        let mut content = Camera::new(24, 18, FRAC_PI_2);
+        content.transform = view;
+
+        let outer = w.objects[0].id();
+        let boxed_only = w.render_only(&wide, outer);
+        let plain_only = w.render_only(&content, outer);
+        let boxed = wide.render(w.clone());
+        let plain = content.render(w);
+
+        //24 columns of content leave 4 column bars on each side
+        for y in 0..18 {
+            for x in 0..32 {
+                let (expected, expected_only) = if (4..28).contains(&x) {
+                    (plain.pixel_at(x - 4, y), plain_only.pixel_at(x - 4, y))
+                } else {
+                    (Color::new(0.0, 0.0, 0.0), (Color::new(0.0, 0.0, 0.0), 1.0))
+                };
+                assert_eq!(boxed.pixel_at(x, y), expected);
+                assert_eq!(boxed_only.pixel_at(x, y), expected_only);
+            }
+        }
+        assert_ne!(boxed.pixel_at(16, 9), Color::new(0.0, 0.0, 0.0));
+        assert_eq!(boxed_only.pixel_at(16, 9).1, 1.0);
+        assert_eq!(boxed_only.pixel_at(5, 0).1, 0.0);
+    }
+
+    #[test]
+    fn object_id_pass_marks_hits_and_background() {
+        let w = World::default_world();
+        let front = w.objects[0].id();
+        let mut c = Camera::new(11, 11, FRAC_PI_2);
+        c.transform = Matrix::view_transform(
+            RayTuple::point(0.0, 0.0, -5.0),
+            RayTuple::point(0.0, 0.0, 0.0),
+            RayTuple::vector(0.0, 1.0, 0.0),
+        );
+
+        let ids = c.render_object_ids(w);
+
+        assert_eq!(ids.len(), 121);
+        assert_eq!(ids[5 * 11 + 5], Some(front));
+        assert_eq!(ids[5 * 11 + 6], Some(front));
+        assert_eq!(ids[0], None);
+        assert_eq!(ids[10], None);
+        assert_eq!(ids[120], None);
+    }
+
+    #[test]
+    fn accumulated_passes_differ_and_converge() {
+        //a coarse area light half hidden behind a blocker, so the floor has a noisy penumbra
+        let mut w = World::new();
+        w.lights = vec![Light::area_light(
+            RayTuple::point(-1.0, 10.0, -1.0),
+            RayTuple::vector(2.0, 0.0, 0.0),
+            2,
+            RayTuple::vector(0.0, 0.0, 2.0),
+            2,
+            Color::new(1.0, 1.0, 1.0),
+        )];
+        w.objects.push(Shape::plane());
+        let mut blocker = Shape::cube();
+        blocker.transform = Matrix::translation(-10.0, 5.0, 0.0) * Matrix::scaling(10.0, 0.1, 10.0);
+        w.objects.push(blocker);
+
+        let accumulate = |passes: usize| {
+            let mut c = Camera::new(4, 4, FRAC_PI_2);
+            c.transform = Matrix::view_transform(
+                RayTuple::point(0.0, 1.0, 0.0),
+                RayTuple::point(0.0, 0.0, 0.0),
+                RayTuple::vector(0.0, 0.0, 1.0),
+            );
+            c.render_accumulate(w.clone(), passes)
+        };
+        let difference = |a: &Canvas, b: &Canvas| {
+            let mut total = 0.0;
+            for y in 0..4 {
+                for x in 0..4 {
+                    total += (a.pixel_at(x, y).luminance() - b.pixel_at(x, y).luminance()).abs();
+                }
+            }
+            total
+        };
+
+        let one = accumulate(1);
+        let two = accumulate(2);
+        let many = accumulate(16);
+        let more = accumulate(32);
+        assert!(difference(&one, &two) > 0.0);
+        assert!(difference(&many, &more) < difference(&one, &more) / 2.0);
+    }
+
+    #[test]
+    fn accumulation_tone_maps_the_average() {
+        let camera = || {
+            let mut c = Camera::new(11, 11, FRAC_PI_2);
+            c.transform = Matrix::view_transform(
+                RayTuple::point(0.0, 0.0, -5.0),
+                RayTuple::point(0.0, 0.0, 0.0),
+                RayTuple::vector(0.0, 1.0, 0.0),
+            );
+            c
+        };
+        let linear = camera().render_accumulate(World::default_world(), 2);
+        let mapped =
+            camera().render_accumulate_tone_mapped(World::default_world(), 2, ToneMap::Reinhard);
+
+        let center = linear.pixel_at(5, 5);
+        assert_eq!(
+            mapped.pixel_at(5, 5),
+            Color::new(
+                center.red / (1.0 + center.red),
+                center.green / (1.0 + center.green),
+                center.blue / (1.0 + center.blue)
+            )
+        );
+    }
+
+    #[test]
+    fn antialiasing_blends_the_edges_of_objects() {
+        let mut c = Camera::new(11, 11, FRAC_PI_2);
+        c.transform = Matrix::view_transform(
+            RayTuple::point(0.0, 0.0, -5.0),
+            RayTuple::point(0.0, 0.0, 0.0),
+            RayTuple::vector(0.0, 1.0, 0.0),
+        );
+        let w = World::default_world();
+        let render = |c: &Camera| {
+            let mut canvas = Canvas::new(11, 11);
+            c.render_into(&w, &mut canvas).unwrap();
+            canvas
+        };
+
+        let single = render(&c);
+        c.samples = 0;
+        assert_eq!(render(&c), single);
+
+        c.samples = 16;
+        let smooth = render(&c);
+        assert_eq!(render(&c), smooth);
+
+        //a pixel the center ray misses but the sphere partly covers picks up some of its color
+        let black = Color::new(0.0, 0.0, 0.0);
+        let blended = (0..11).any(|x| {
+            (0..11).any(|y| single.pixel_at(x, y) == black && smooth.pixel_at(x, y) != black)
+        });
+        assert!(blended);
+        let center = smooth.pixel_at(5, 5) - single.pixel_at(5, 5);
+        assert!(center.red.abs() < 0.05 && center.green.abs() < 0.05);
+    }
+
+    #[test]
+    #[should_panic(expected = "at least one pass")]
+    fn accumulating_no_passes_panics() {
+        Camera::new(2, 2, FRAC_PI_2).render_accumulate(World::default_world(), 0);
+    }
 }
@@ -2,10 +2,20 @@
 use crate::color::Color;
 use crate::raytuple::RayTuple;
 use std::fs::File;
-use std::io::Write;
+use std::io::{self, BufWriter, Write};
 use std::mem;
+use std::path::Path;
+
+//What Canvas::save wrote, PpmFallback when the extension wasn't one it knows
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SaveFormat {
+    Ppm,
+    Pgm,
+    Png,
+    PpmFallback,
+}
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Canvas {
     width: i32,
     height: i32,
@@ -27,6 +37,50 @@ impl Canvas {
         }
     }
 
+    //Fills every pixel with f(x, y), rows first
+    pub fn from_fn(width: i32, height: i32, f: impl Fn(i32, i32) -> Color) -> Self {
+        let mut pixels = Vec::with_capacity((width * height) as usize);
+        for y in 0..height {
+            for x in 0..width {
+                pixels.push(f(x, y));
+            }
+        }
+
+        Self {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    //Canvas holding the given pixels, rows first. Fails unless there are exactly
+    //width * height of them.
+    pub fn from_colors(width: i32, height: i32, pixels: Vec<Color>) -> Result<Self, String> {
+        if width < 0 || height < 0 || pixels.len() != (width * height) as usize {
+            return Err(format!(
+                "{} colors can't fill a {}x{} canvas",
+                pixels.len(),
+                width,
+                height
+            ));
+        }
+
+        Ok(Self {
+            width,
+            height,
+            pixels,
+        })
+    }
+
+    //New canvas with f applied to every pixel, for gamma, exposure or tinting
+    pub fn map(&self, f: impl Fn(Color) -> Color) -> Canvas {
+        Canvas {
+            width: self.width,
+            height: self.height,
+            pixels: self.pixels.iter().map(|&c| f(c)).collect(),
+        }
+    }
+
     pub fn pixel_at(&self, x: i32, y: i32) -> Color {
         //convert x,y coords to index
         let i = (y * self.width + x) as usize;
@@ -40,53 +94,249 @@ impl Canvas {
     }
 
     pub fn to_ppm(&self) -> String {
+        let mut pixel_data = String::new();
+        for y in 0..self.height {
+            pixel_data += &self.ppm_row(y);
+        }
+
+        self.ppm_header() + &pixel_data
+    }
+
+    //Writes the same bytes as to_ppm one row at a time, so only a row is held in memory
+    pub fn write_ppm<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        out.write_all(self.ppm_header().as_bytes())?;
+        for y in 0..self.height {
+            out.write_all(self.ppm_row(y).as_bytes())?;
+        }
+        out.flush()
+    }
+
+    fn ppm_header(&self) -> String {
         let h1 = String::from("P3\n");
         let h2 = format!("{} {}\n", self.width, self.height);
         let h3 = String::from("255\n");
 
-        let mut pixel_data = String::new();
+        h1 + &h2 + &h3
+    }
+
+    //One row of pixel data, wrapped so no line is longer than 70 characters
+    fn ppm_row(&self, y: i32) -> String {
+        let values: Vec<u8> = (0..self.width)
+            .flat_map(|x| self.pixel_at(x, y).to_rgb8())
+            .collect();
+        wrap_values(&values)
+    }
 
-        let mut current_line = String::new();
+    //Every pixel replaced by its luminance in all three channels
+    pub fn to_grayscale(&self) -> Canvas {
+        self.map(|c| {
+            let l = c.luminance();
+            Color::new(l, l, l)
+        })
+    }
 
+    //Plain (P2) grayscale image of each pixel's luminance
+    pub fn to_pgm(&self) -> String {
+        let gray = self.to_grayscale();
+        let mut text = format!("P2\n{} {}\n255\n", self.width, self.height);
         for y in 0..self.height {
-            for x in 0..self.width {
-                let this_pixel = self.pixel_at(x, y);
-                let red = ((this_pixel.red * 255.0).round() as i32).clamp(0, 255);
-                let green = ((this_pixel.green * 255.0).round() as i32).clamp(0, 255);
-                let blue = ((this_pixel.blue * 255.0).round() as i32).clamp(0, 255);
-                let r_str = format!("{}", red);
-                let g_str = format!("{}", green);
-                let b_str = format!("{}", blue);
-
-                for slice in [r_str, g_str, b_str] {
-                    if current_line.len() + slice.len() > 68 {
-                        current_line += "\n";
-                        pixel_data += &current_line;
-                        current_line = String::new();
-                    } else if current_line.len() > 0 {
-                        current_line += &String::from(" ");
-                    }
-                    current_line += &slice;
-                }
+            let values: Vec<u8> = (0..self.width)
+                .map(|x| gray.pixel_at(x, y).to_rgb8()[0])
+                .collect();
+            text += &wrap_values(&values);
+        }
+        text
+    }
+
+    //Picks the format from the extension: .ppm, .pgm, or .png with the png feature.
+    //Anything else is written as a plain PPM, reported as PpmFallback.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<SaveFormat> {
+        let path = path.as_ref();
+        let extension = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_ascii_lowercase());
+
+        #[cfg(not(feature = "png"))]
+        if extension.as_deref() == Some("png") {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "PNG output needs the png feature",
+            ));
+        }
+
+        let mut writer = BufWriter::new(File::create(path)?);
+        match extension.as_deref() {
+            Some("ppm") => self.write_ppm(&mut writer).map(|_| SaveFormat::Ppm),
+            Some("pgm") => {
+                writer.write_all(self.to_pgm().as_bytes())?;
+                writer.flush().map(|_| SaveFormat::Pgm)
             }
-            current_line += "\n";
-            pixel_data += &current_line;
-            current_line = String::new();
+            #[cfg(feature = "png")]
+            Some("png") => self.write_png(&mut writer).map(|_| SaveFormat::Png),
+            _ => self.write_ppm(&mut writer).map(|_| SaveFormat::PpmFallback),
         }
+    }
 
-        h1 + &h2 + &h3 + &pixel_data
+    #[cfg(feature = "png")]
+    fn write_png<W: Write>(&self, out: W) -> io::Result<()> {
+        let mut encoder = png::Encoder::new(out, self.width as u32, self.height as u32);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+
+        let data: Vec<u8> = self.pixels.iter().flat_map(|c| c.to_rgb8()).collect();
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&data)?;
+        Ok(())
+    }
+
+    //Parses a plain (P3) PPM like the ones to_ppm writes, comments allowed.
+    //Channels are scaled by the file's maximum value back into 0..=1.
+    pub fn from_ppm(text: &str) -> Option<Canvas> {
+        let mut tokens = text
+            .lines()
+            .map(|line| line.split('#').next().unwrap_or(""))
+            .flat_map(|line| line.split_whitespace());
+
+        if tokens.next()? != "P3" {
+            return None;
+        }
+        let width: i32 = tokens.next()?.parse().ok()?;
+        let height: i32 = tokens.next()?.parse().ok()?;
+        let max: f64 = tokens.next()?.parse().ok()?;
+        if width <= 0 || height <= 0 || max <= 0.0 {
+            return None;
+        }
+
+        let mut canvas = Canvas::new(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                let mut channel =
+                    || -> Option<f64> { Some(tokens.next()?.parse::<f64>().ok()? / max) };
+                let color = Color::new(channel()?, channel()?, channel()?);
+                canvas.write_pixel(x, y, color);
+            }
+        }
+        Some(canvas)
+    }
+
+    pub fn load_ppm(filename: &str) -> io::Result<Canvas> {
+        let text = std::fs::read_to_string(filename)?;
+        Canvas::from_ppm(&text).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{filename} is not a plain PPM"),
+            )
+        })
     }
 
     pub fn save_ppm(&self, filename: &'static str) {
-        let mut file = File::create(filename).unwrap();
-        let res = file.write_all(self.to_ppm().as_bytes());
+        let res = File::create(filename).and_then(|file| {
+            let mut writer = BufWriter::new(file);
+            self.write_ppm(&mut writer)
+        });
 
         match res {
             Ok(()) => println!("Canvas saved to {filename}"),
-            Err(e) => println!("Error saving file: {}", e.to_string()),
+            Err(e) => println!("Error saving file: {}", e),
+        }
+    }
+
+    pub fn get_width(&self) -> i32 {
+        self.width
+    }
+
+    pub fn get_height(&self) -> i32 {
+        self.height
+    }
+
+    pub fn draw_border(&mut self, thickness: i32, color: Color) {
+        for y in 0..self.height {
+            for x in 0..self.width {
+                if x < thickness
+                    || y < thickness
+                    || x >= self.width - thickness
+                    || y >= self.height - thickness
+                {
+                    self.write_pixel(x, y, color);
+                }
+            }
+        }
+    }
+
+    //Lays top over this canvas, top_alpha holds one coverage value per pixel in row order.
+    //The canvas itself is treated as an opaque background. Fails, leaving the canvas
+    //alone, unless top and top_alpha both match its size.
+    pub fn composite_over(&mut self, top: &Canvas, top_alpha: &[f64]) -> Result<(), String> {
+        if top.width != self.width || top.height != self.height {
+            return Err(format!(
+                "can't lay a {}x{} canvas over a {}x{} one",
+                top.width, top.height, self.width, self.height
+            ));
+        }
+        if top_alpha.len() != self.pixels.len() {
+            return Err(format!(
+                "{} alpha values for {} pixels",
+                top_alpha.len(),
+                self.pixels.len()
+            ));
+        }
+
+        for (i, pixel) in self.pixels.iter_mut().enumerate() {
+            let alpha = top_alpha[i];
+            *pixel = top.pixels[i] * alpha + *pixel * (1.0 - alpha);
+        }
+        Ok(())
+    }
+
+    //Stamps text with the built in 5x7 font, x and y are the top left of the first glyph.
+    //Pixels that fall outside the canvas are skipped.
+    pub fn draw_text(&mut self, x: i32, y: i32, text: &str, color: Color) {
+        for (i, c) in text.chars().enumerate() {
+            let left = x + i as i32 * (GLYPH_WIDTH + 1);
+            for (row, bits) in glyph(c).iter().enumerate() {
+                for col in 0..GLYPH_WIDTH {
+                    if bits & (1 << (GLYPH_WIDTH - 1 - col)) != 0 {
+                        let px = left + col;
+                        let py = y + row as i32;
+                        if px >= 0 && px < self.width && py >= 0 && py < self.height {
+                            self.write_pixel(px, py, color);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+//Canvas with a per pixel alpha for rendering layers and compositing them later.
+//Colors are stored unpremultiplied.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CanvasRgba {
+    width: i32,
+    height: i32,
+    pixels: Vec<(Color, f64)>,
+}
+
+impl CanvasRgba {
+    pub fn new(width: i32, height: i32) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![(Color::new(0.0, 0.0, 0.0), 0.0); (width * height) as usize],
         }
     }
 
+    pub fn pixel_at(&self, x: i32, y: i32) -> (Color, f64) {
+        let i = (y * self.width + x) as usize;
+        self.pixels[i]
+    }
+
+    pub fn write_pixel(&mut self, x: i32, y: i32, c: Color, alpha: f64) {
+        let i = (y * self.width + x) as usize;
+        self.pixels[i] = (c, alpha);
+    }
+
     pub fn get_width(&self) -> i32 {
         self.width
     }
@@ -94,6 +344,153 @@ impl Canvas {
     pub fn get_height(&self) -> i32 {
         self.height
     }
+
+    //Porter-Duff source over: self is drawn on top of background
+    pub fn over(&self, background: &CanvasRgba) -> CanvasRgba {
+        let mut result = CanvasRgba::new(self.width, self.height);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let (src, src_a) = self.pixel_at(x, y);
+                let (dst, dst_a) = background.pixel_at(x, y);
+                let out_a = src_a + dst_a * (1.0 - src_a);
+                let out = if out_a > 0.0 {
+                    (src * src_a + dst * (dst_a * (1.0 - src_a))) * (1.0 / out_a)
+                } else {
+                    Color::new(0.0, 0.0, 0.0)
+                };
+                result.write_pixel(x, y, out, out_a);
+            }
+        }
+        result
+    }
+
+    //Drops the alpha, uncovered pixels fade to black
+    pub fn to_canvas(&self) -> Canvas {
+        let mut canvas = Canvas::new(self.width, self.height);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let (c, a) = self.pixel_at(x, y);
+                canvas.write_pixel(x, y, c * a);
+            }
+        }
+        canvas
+    }
+}
+
+//Space separated values broken into lines of at most 70 characters, as PPM and PGM expect
+fn wrap_values(values: &[u8]) -> String {
+    let mut text = String::new();
+    let mut current_line = String::new();
+
+    for value in values {
+        let slice = format!("{}", value);
+        if current_line.len() + slice.len() > 68 {
+            current_line += "\n";
+            text += &current_line;
+            current_line = String::new();
+        } else if !current_line.is_empty() {
+            current_line += &String::from(" ");
+        }
+        current_line += &slice;
+    }
+    current_line += "\n";
+    text += &current_line;
+
+    text
+}
+
+const GLYPH_WIDTH: i32 = 5;
+
+//Rows top to bottom, the high bit of the low five is the leftmost column.
+//Lowercase letters share the uppercase glyphs and unknown characters are blank.
+fn glyph(c: char) -> [u8; 7] {
+    match c.to_ascii_uppercase() {
+        '0' => [0x0E, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0E],
+        '1' => [0x04, 0x0C, 0x04, 0x04, 0x04, 0x04, 0x0E],
+        '2' => [0x0E, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1F],
+        '3' => [0x1F, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0E],
+        '4' => [0x02, 0x06, 0x0A, 0x12, 0x1F, 0x02, 0x02],
+        '5' => [0x1F, 0x10, 0x1E, 0x01, 0x01, 0x11, 0x0E],
+        '6' => [0x06, 0x08, 0x10, 0x1E, 0x11, 0x11, 0x0E],
+        '7' => [0x1F, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08],
+        '8' => [0x0E, 0x11, 0x11, 0x0E, 0x11, 0x11, 0x0E],
+        '9' => [0x0E, 0x11, 0x11, 0x0F, 0x01, 0x02, 0x0C],
+        'A' => [0x0E, 0x11, 0x11, 0x11, 0x1F, 0x11, 0x11],
+        'B' => [0x1E, 0x11, 0x11, 0x1E, 0x11, 0x11, 0x1E],
+        'C' => [0x0E, 0x11, 0x10, 0x10, 0x10, 0x11, 0x0E],
+        'D' => [0x1C, 0x12, 0x11, 0x11, 0x11, 0x12, 0x1C],
+        'E' => [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x1F],
+        'F' => [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x10],
+        'G' => [0x0E, 0x11, 0x10, 0x17, 0x11, 0x11, 0x0F],
+        'H' => [0x11, 0x11, 0x11, 0x1F, 0x11, 0x11, 0x11],
+        'I' => [0x0E, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0E],
+        'J' => [0x07, 0x02, 0x02, 0x02, 0x02, 0x12, 0x0C],
+        'K' => [0x11, 0x12, 0x14, 0x18, 0x14, 0x12, 0x11],
+        'L' => [0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x1F],
+        'M' => [0x11, 0x1B, 0x15, 0x15, 0x11, 0x11, 0x11],
+        'N' => [0x11, 0x11, 0x19, 0x15, 0x13, 0x11, 0x11],
+        'O' => [0x0E, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E],
+        'P' => [0x1E, 0x11, 0x11, 0x1E, 0x10, 0x10, 0x10],
+        'Q' => [0x0E, 0x11, 0x11, 0x11, 0x15, 0x12, 0x0D],
+        'R' => [0x1E, 0x11, 0x11, 0x1E, 0x14, 0x12, 0x11],
+        'S' => [0x0F, 0x10, 0x10, 0x0E, 0x01, 0x01, 0x1E],
+        'T' => [0x1F, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04],
+        'U' => [0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E],
+        'V' => [0x11, 0x11, 0x11, 0x11, 0x11, 0x0A, 0x04],
+        'W' => [0x11, 0x11, 0x11, 0x15, 0x15, 0x15, 0x0A],
+        'X' => [0x11, 0x11, 0x0A, 0x04, 0x0A, 0x11, 0x11],
+        'Y' => [0x11, 0x11, 0x11, 0x0A, 0x04, 0x04, 0x04],
+        'Z' => [0x1F, 0x01, 0x02, 0x04, 0x08, 0x10, 0x1F],
+        '.' => [0x00, 0x00, 0x00, 0x00, 0x00, 0x0C, 0x0C],
+        ':' => [0x00, 0x0C, 0x0C, 0x00, 0x0C, 0x0C, 0x00],
+        '-' => [0x00, 0x00, 0x00, 0x1F, 0x00, 0x00, 0x00],
+        '/' => [0x00, 0x01, 0x02, 0x04, 0x08, 0x10, 0x00],
+        _ => [0x00; 7],
+    }
+}
+
+//We adjust the chapter 1 cannon exercise and graph the points on a canvas, then save it to a .ppm file
+pub fn chapter_two_graph() {
+    fn tick(p: (RayTuple, RayTuple), e: (RayTuple, RayTuple)) -> (RayTuple, RayTuple) {
+        let new_position = p.0 + p.1;
+        let new_velocity = p.1 + e.0 + e.1;
+        (new_position, new_velocity)
+    }
+
+    let mut p = (
+        RayTuple::point(0.0, 1.0, 0.0), //initial point, y=1, x/z = 0
+        RayTuple::vector(1.0, 1.8, 0.0).normalize() * 11.25, //the starting velocity is x=1, y=1.8 with a magnitude of 11.25
+    );
+
+    let e = (
+        RayTuple::vector(0.0, -0.1, 0.0),  //gravity
+        RayTuple::vector(-0.01, 0.0, 0.0), //wind
+    );
+
+    let mut c = Canvas::new(900, 550);
+
+    let mut tick_count = 1;
+
+    println!("t\tx\ty\tz");
+
+    while p.0.y > 0.0 {
+        println!("{tick_count}\t{}\t{}\t{}", p.0.x, p.0.y, p.0.z);
+        //convert point coordinates to graph coordinates
+        let x_coord = (p.0.x.round() as i32).clamp(0, c.get_width() - 1);
+        let y_coord = (c.get_height() - (p.0.y.round() as i32)).clamp(0, c.get_height() - 1);
+
+        c.write_pixel(x_coord, y_coord, Color::new(0.8, 0.2, 0.2));
+
+        p = tick(p, e);
+        tick_count += 1;
+    }
+
+    println!("{tick_count}\t{}\t{}\t{}", p.0.x, p.0.y, p.0.z);
+    let x_coord = (p.0.x.round() as i32).clamp(0, c.get_width() - 1);
+    let y_coord = (c.get_height() - (p.0.y.round() as i32)).clamp(0, c.get_height() - 1);
+
+    c.write_pixel(x_coord, y_coord, Color::new(0.8, 0.2, 0.2));
+    c.save_ppm("chapter2.ppm");
 }
 
 #[cfg(test)]
@@ -163,48 +560,234 @@ mod tests {
         let len = ppm.len();
         assert_eq!(&ppm[len - 1..len], "\n");
     }
-}
 
-//We adjust the chapter 1 cannon exercise and graph the points on a canvas, then save it to a .ppm file
-pub fn chapter_two_graph() {
-    fn tick(p: (RayTuple, RayTuple), e: (RayTuple, RayTuple)) -> (RayTuple, RayTuple) {
-        let new_position = p.0 + p.1;
-        let new_velocity = p.1 + e.0 + e.1;
-        (new_position, new_velocity)
+    #[test]
+    fn draw_border_colors_only_the_edges() {
+        let mut c = Canvas::new(10, 8);
+        let red = Color::new(1.0, 0.0, 0.0);
+        c.draw_border(2, red);
+
+        assert_eq!(c.pixel_at(0, 0), red);
+        assert_eq!(c.pixel_at(1, 4), red);
+        assert_eq!(c.pixel_at(9, 7), red);
+        assert_eq!(c.pixel_at(5, 6), red);
+        assert_eq!(c.pixel_at(2, 2), Color::new(0.0, 0.0, 0.0));
+        assert_eq!(c.pixel_at(7, 5), Color::new(0.0, 0.0, 0.0));
     }
 
-    let mut p = (
-        RayTuple::point(0.0, 1.0, 0.0), //initial point, y=1, x/z = 0
-        RayTuple::vector(1.0, 1.8, 0.0).normalize() * 11.25, //the starting velocity is x=1, y=1.8 with a magnitude of 11.25
-    );
+    #[test]
+    fn draw_text_sets_glyph_pixels() {
+        let mut c = Canvas::new(10, 10);
+        let white = Color::new(1.0, 1.0, 1.0);
+        let black = Color::new(0.0, 0.0, 0.0);
+        c.draw_text(1, 1, "1", white);
+
+        //the stem of the 1 runs down the middle column with a serif at the bottom
+        for y in 1..8 {
+            assert_eq!(c.pixel_at(3, y), white);
+        }
+        assert_eq!(c.pixel_at(2, 2), white);
+        assert_eq!(c.pixel_at(1, 1), black);
+        assert_eq!(c.pixel_at(2, 7), white);
+        assert_eq!(c.pixel_at(4, 7), white);
+        assert_eq!(c.pixel_at(5, 7), black);
+    }
 
-    let e = (
-        RayTuple::vector(0.0, -0.1, 0.0),  //gravity
-        RayTuple::vector(-0.01, 0.0, 0.0), //wind
-    );
+    #[test]
+    fn draw_text_off_canvas_is_a_no_op() {
+        let mut c = Canvas::new(10, 10);
+        let before = c.clone();
+        let white = Color::new(1.0, 1.0, 1.0);
+
+        c.draw_text(10, 0, "1", white);
+        c.draw_text(-6, 0, "1", white);
+        c.draw_text(0, 10, "12", white);
+        c.draw_text(0, -7, "12", white);
+
+        assert_eq!(c, before);
+
+        //a glyph hanging over the edge only draws its visible part
+        c.draw_text(-2, 0, "1", white);
+        assert_eq!(c.pixel_at(0, 0), white);
+        assert_eq!(c.pixel_at(0, 6), white);
+        assert_eq!(c.pixel_at(1, 6), white);
+        assert_eq!(c.pixel_at(3, 0), Color::new(0.0, 0.0, 0.0));
+    }
 
-    let mut c = Canvas::new(900, 550);
+    #[test]
+    fn compositing_half_covered_layer_over_background() {
+        let mut layer = CanvasRgba::new(2, 1);
+        layer.write_pixel(0, 0, Color::new(1.0, 0.0, 0.0), 0.5);
+        let mut background = CanvasRgba::new(2, 1);
+        background.write_pixel(0, 0, Color::new(0.0, 0.0, 1.0), 1.0);
+        background.write_pixel(1, 0, Color::new(0.0, 0.0, 1.0), 1.0);
 
-    let mut tick_count = 1;
+        let result = layer.over(&background);
 
-    println!("t\tx\ty\tz");
+        assert_eq!(result.pixel_at(0, 0), (Color::new(0.5, 0.0, 0.5), 1.0));
+        assert_eq!(result.pixel_at(1, 0), (Color::new(0.0, 0.0, 1.0), 1.0));
+    }
 
-    while p.0.y > 0.0 {
-        println!("{tick_count}\t{}\t{}\t{}", p.0.x, p.0.y, p.0.z);
-        //convert point coordinates to graph coordinates
-        let x_coord = (p.0.x.round() as i32).clamp(0, c.get_width() - 1);
-        let y_coord = (c.get_height() - (p.0.y.round() as i32)).clamp(0, c.get_height() - 1);
+    #[test]
+    fn streamed_ppm_matches_to_ppm() {
+        let mut c = Canvas::new(10, 3);
+        for y in 0..3 {
+            for x in 0..10 {
+                c.write_pixel(x, y, Color::new(x as f64 / 10.0, 0.8, y as f64 / 3.0));
+            }
+        }
 
-        c.write_pixel(x_coord, y_coord, Color::new(0.8, 0.2, 0.2));
+        let mut streamed: Vec<u8> = Vec::new();
+        c.write_ppm(&mut streamed).unwrap();
 
-        p = tick(p, e);
-        tick_count += 1;
+        assert_eq!(streamed, c.to_ppm().into_bytes());
     }
 
-    println!("{tick_count}\t{}\t{}\t{}", p.0.x, p.0.y, p.0.z);
-    let x_coord = (p.0.x.round() as i32).clamp(0, c.get_width() - 1);
-    let y_coord = (c.get_height() - (p.0.y.round() as i32)).clamp(0, c.get_height() - 1);
+    #[test]
+    fn composite_layer_over_background() {
+        let red = Color::new(1.0, 0.0, 0.0);
+        let blue = Color::new(0.0, 0.0, 1.0);
+        let mut top = Canvas::new(3, 1);
+        for x in 0..3 {
+            top.write_pixel(x, 0, red);
+        }
+        let mut background = Canvas::new(3, 1);
+        for x in 0..3 {
+            background.write_pixel(x, 0, blue);
+        }
 
-    c.write_pixel(x_coord, y_coord, Color::new(0.8, 0.2, 0.2));
-    c.save_ppm("chapter2.ppm");
+        background.composite_over(&top, &[1.0, 0.0, 0.5]).unwrap();
+        assert_eq!(background.pixel_at(0, 0), red);
+        assert_eq!(background.pixel_at(1, 0), blue);
+        assert_eq!(background.pixel_at(2, 0), Color::new(0.5, 0.0, 0.5));
+
+        let before = background.clone();
+        assert!(background
+            .composite_over(&Canvas::new(2, 1), &[1.0, 1.0])
+            .is_err());
+        assert!(background.composite_over(&top, &[1.0, 1.0]).is_err());
+        assert_eq!(background, before);
+    }
+
+    #[test]
+    fn canvas_from_fn_fills_each_pixel() {
+        let c = Canvas::from_fn(4, 4, |x, y| Color::new(x as f64, y as f64, 0.0));
+
+        assert_eq!(c.get_width(), 4);
+        assert_eq!(c.get_height(), 4);
+        for y in 0..4 {
+            for x in 0..4 {
+                assert_eq!(c.pixel_at(x, y), Color::new(x as f64, y as f64, 0.0));
+            }
+        }
+    }
+
+    #[test]
+    fn reading_ppm_back() {
+        let mut c = Canvas::new(3, 2);
+        c.write_pixel(0, 0, Color::new(1.0, 0.0, 0.0));
+        c.write_pixel(2, 1, Color::new(0.0, 0.2, 1.0));
+
+        let read = Canvas::from_ppm(&c.to_ppm()).unwrap();
+        assert_eq!(read.get_width(), 3);
+        assert_eq!(read.get_height(), 2);
+        assert_eq!(read.pixel_at(0, 0), Color::new(1.0, 0.0, 0.0));
+        assert_eq!(read.pixel_at(2, 1), Color::new(0.0, 0.2, 1.0));
+
+        let commented = "P3\n# a comment\n1 1\n10\n10 5 0 # trailing\n";
+        assert_eq!(
+            Canvas::from_ppm(commented).unwrap().pixel_at(0, 0),
+            Color::new(1.0, 0.5, 0.0)
+        );
+        assert!(Canvas::from_ppm("P6\n1 1\n255\n").is_none());
+        assert!(Canvas::from_ppm("P3\n2 1\n255\n0 0 0\n").is_none());
+    }
+
+    #[test]
+    fn mapping_a_canvas_halves_every_pixel() {
+        let c = Canvas::from_fn(3, 2, |x, y| Color::new(x as f64, y as f64, 1.0));
+        let half = c.map(|color| color * 0.5);
+
+        for y in 0..2 {
+            for x in 0..3 {
+                assert_eq!(half.pixel_at(x, y), c.pixel_at(x, y) * 0.5);
+            }
+        }
+        assert_eq!(half.get_width(), 3);
+        assert_eq!(half.get_height(), 2);
+    }
+
+    #[test]
+    fn save_picks_format_from_extension() {
+        let mut c = Canvas::new(2, 1);
+        c.write_pixel(0, 0, Color::new(1.0, 0.0, 0.0));
+        c.write_pixel(1, 0, Color::new(1.0, 1.0, 1.0));
+        let dir = std::env::temp_dir();
+
+        let path =
+            |extension: &str| dir.join(format!("ray_save_test_{}.{extension}", std::process::id()));
+
+        let ppm = path("ppm");
+        assert_eq!(c.save(&ppm).unwrap(), SaveFormat::Ppm);
+        let text = std::fs::read_to_string(&ppm).unwrap();
+        assert_eq!(text, c.to_ppm());
+        assert_eq!(Canvas::from_ppm(&text), Some(c.clone()));
+
+        let pgm = path("pgm");
+        assert_eq!(c.save(&pgm).unwrap(), SaveFormat::Pgm);
+        assert_eq!(
+            std::fs::read_to_string(&pgm).unwrap(),
+            "P2\n2 1\n255\n54 255\n"
+        );
+
+        let unknown = path("img");
+        assert_eq!(c.save(&unknown).unwrap(), SaveFormat::PpmFallback);
+        assert_eq!(std::fs::read_to_string(&unknown).unwrap(), c.to_ppm());
+
+        let png = path("png");
+        let result = c.save(&png);
+        if cfg!(feature = "png") {
+            assert_eq!(result.unwrap(), SaveFormat::Png);
+            assert_eq!(&std::fs::read(&png).unwrap()[..4], b"\x89PNG");
+        } else {
+            assert_eq!(result.unwrap_err().kind(), io::ErrorKind::Unsupported);
+        }
+
+        for path in [ppm, pgm, unknown, png] {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+
+    #[test]
+    fn grayscale_canvas_has_equal_channels() {
+        let c = Canvas::from_fn(3, 3, |x, y| Color::new(x as f64 / 2.0, y as f64 / 2.0, 0.3));
+        let gray = c.to_grayscale();
+
+        for y in 0..3 {
+            for x in 0..3 {
+                let p = gray.pixel_at(x, y);
+                assert_eq!(p.red, p.green);
+                assert_eq!(p.green, p.blue);
+                assert_eq!(p.red, c.pixel_at(x, y).luminance());
+            }
+        }
+    }
+
+    #[test]
+    fn from_colors_fills_rows_in_order() {
+        let red = Color::new(1.0, 0.0, 0.0);
+        let green = Color::new(0.0, 1.0, 0.0);
+        let blue = Color::new(0.0, 0.0, 1.0);
+        let white = Color::new(1.0, 1.0, 1.0);
+
+        let c = Canvas::from_colors(2, 2, vec![red, green, blue, white]).unwrap();
+        assert_eq!(c.pixel_at(0, 0), red);
+        assert_eq!(c.pixel_at(1, 0), green);
+        assert_eq!(c.pixel_at(0, 1), blue);
+        assert_eq!(c.pixel_at(1, 1), white);
+
+        assert!(Canvas::from_colors(2, 2, vec![red, green, blue]).is_err());
+        assert!(Canvas::from_colors(2, 1, vec![red, green, blue]).is_err());
+        assert!(Canvas::from_colors(-2, -2, vec![red, green, blue, white]).is_err());
+    }
 }
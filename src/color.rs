@@ -12,6 +12,41 @@ impl Color {
     pub fn new(red: f64, green: f64, blue: f64) -> Self {
         Self { red, green, blue }
     }
+
+    //Named alias for Color * Color, the componentwise (Hadamard/Schur) product
+    pub fn schur(&self, other: Color) -> Self {
+        *self * other
+    }
+
+    //Perceived brightness with Rec. 709 weights
+    pub fn luminance(&self) -> f64 {
+        0.2126 * self.red + 0.7152 * self.green + 0.0722 * self.blue
+    }
+
+    //Channels scaled to 0..=255 and clamped, the same conversion the PPM writer uses
+    pub fn to_rgb8(self) -> [u8; 3] {
+        let channel = |c: f64| (c * 255.0).round().clamp(0.0, 255.0) as u8;
+        [channel(self.red), channel(self.green), channel(self.blue)]
+    }
+
+    //Parses "#rrggbb" (the # is optional), None for anything else
+    pub fn from_hex(hex: &str) -> Option<Color> {
+        let digits = hex.strip_prefix('#').unwrap_or(hex);
+        if digits.len() != 6 || !digits.is_ascii() {
+            return None;
+        }
+        let channel = |i: usize| {
+            u8::from_str_radix(&digits[i..i + 2], 16)
+                .ok()
+                .map(|c| c as f64 / 255.0)
+        };
+        Some(Color::new(channel(0)?, channel(2)?, channel(4)?))
+    }
+
+    pub fn to_hex(self) -> String {
+        let [r, g, b] = self.to_rgb8();
+        format!("#{:02x}{:02x}{:02x}", r, g, b)
+    }
 }
 
 impl PartialEq for Color {
@@ -60,6 +95,9 @@ impl Mul<f64> for Color {
     }
 }
 
+//Multiplying two colors blends them channel by channel (red * red, green * green,
+//blue * blue). This is how a surface color filters a light's intensity; it is not
+//a dot product and the result is always another color.
 impl Mul for Color {
     type Output = Self;
 
@@ -72,6 +110,28 @@ impl Mul for Color {
     }
 }
 
+//How HDR values are brought into 0..1 before writing an image
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ToneMap {
+    //values pass through and get clamped on output
+    Linear,
+    //c / (1 + c) per channel, bright values roll off instead of clipping
+    Reinhard,
+}
+
+impl ToneMap {
+    pub fn apply(self, c: Color) -> Color {
+        match self {
+            ToneMap::Linear => c,
+            ToneMap::Reinhard => Color::new(
+                c.red / (1.0 + c.red),
+                c.green / (1.0 + c.green),
+                c.blue / (1.0 + c.blue),
+            ),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -111,4 +171,36 @@ mod tests {
         let c2 = Color::new(0.9, 1.0, 0.1);
         assert_eq!(c1 * c2, Color::new(0.9, 0.2, 0.04));
     }
+
+    #[test]
+    fn schur_product_is_componentwise() {
+        let c1 = Color::new(1.0, 0.2, 0.4);
+        let c2 = Color::new(0.9, 1.0, 0.1);
+        assert_eq!(c1.schur(c2), Color::new(0.9, 0.2, 0.04));
+        assert_eq!(c1.schur(c2), c1 * c2);
+    }
+
+    #[test]
+    fn hex_round_trip() {
+        let c = Color::new(1.0, 0.0, 0.5);
+        assert_eq!(c.to_hex(), "#ff0080");
+        assert_eq!(Color::new(2.0, -1.0, 0.0).to_hex(), "#ff0000");
+
+        let back = Color::from_hex(&c.to_hex()).unwrap();
+        assert!((back.red - c.red).abs() <= 0.5 / 255.0);
+        assert!((back.green - c.green).abs() <= 0.5 / 255.0);
+        assert!((back.blue - c.blue).abs() <= 0.5 / 255.0);
+
+        assert_eq!(Color::from_hex("00FF00"), Some(Color::new(0.0, 1.0, 0.0)));
+        assert_eq!(Color::from_hex("#12345"), None);
+        assert_eq!(Color::from_hex("#12345g"), None);
+    }
+
+    #[test]
+    fn luminance_uses_rec_709_weights() {
+        assert_eq!(Color::new(1.0, 0.0, 0.0).luminance(), 0.2126);
+        assert_eq!(Color::new(0.0, 1.0, 0.0).luminance(), 0.7152);
+        assert_eq!(Color::new(0.0, 0.0, 1.0).luminance(), 0.0722);
+        assert!((Color::new(1.0, 1.0, 1.0).luminance() - 1.0).abs() < 0.00001);
+    }
 }
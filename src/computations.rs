@@ -1,10 +1,11 @@
+#![allow(dead_code)]
 use crate::raytuple::RayTuple;
 use crate::shape::Shape;
 
 #[derive(Debug, Clone, Copy)]
-pub struct Computations {
+pub struct Computations<'a> {
     pub t: f64,
-    pub object: Shape,
+    pub object: &'a Shape,
     pub point: RayTuple,
     pub over_point: RayTuple,
     pub eyev: RayTuple,
@@ -14,12 +15,18 @@ pub struct Computations {
     pub n1: f64,
     pub n2: f64,
     pub under_point: RayTuple,
+    pub n1_channels: [f64; 3],
+    pub n2_channels: [f64; 3],
+    //time of the ray that was hit, carried on to secondary rays
+    pub time: f64,
 }
 
-impl Computations {
+impl<'a> Computations<'a> {
+    //one argument per field, prepare_computations is the only caller
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         t: f64,
-        object: Shape,
+        object: &'a Shape,
         point: RayTuple,
         over_point: RayTuple,
         eyev: RayTuple,
@@ -29,6 +36,8 @@ impl Computations {
         n1: f64,
         n2: f64,
         under_point: RayTuple,
+        n1_channels: [f64; 3],
+        n2_channels: [f64; 3],
     ) -> Self {
         Self {
             t,
@@ -42,6 +51,9 @@ impl Computations {
             n1,
             n2,
             under_point,
+            n1_channels,
+            n2_channels,
+            time: 0.0,
         }
     }
 }
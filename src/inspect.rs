@@ -0,0 +1,190 @@
+#![allow(dead_code)]
+use crate::camera::Camera;
+use crate::canvas::{Canvas, SaveFormat};
+use crate::matrix::Matrix;
+use crate::raytuple::RayTuple;
+use crate::world::World;
+use std::f64::consts::FRAC_PI_3;
+use std::io::{self, BufRead, Write};
+use uuid::Uuid;
+
+//One line typed at the inspector prompt
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    //which object the ray through pixel (x, y) hits first
+    Hit { x: i32, y: i32 },
+    //transform and material of the object with this id
+    Info(Uuid),
+    //render the whole scene and save it, the extension picks the format
+    Render(String),
+    Help,
+    Quit,
+}
+
+impl Command {
+    pub fn parse(line: &str) -> Result<Command, String> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.as_slice() {
+            ["hit", x, y] => {
+                let x = x.parse().map_err(|_| format!("bad x coordinate '{x}'"))?;
+                let y = y.parse().map_err(|_| format!("bad y coordinate '{y}'"))?;
+                Ok(Command::Hit { x, y })
+            }
+            ["info", id] => Uuid::parse_str(id)
+                .map(Command::Info)
+                .map_err(|_| format!("bad object id '{id}'")),
+            ["render", file] => Ok(Command::Render(file.to_string())),
+            ["help"] => Ok(Command::Help),
+            ["quit"] | ["exit"] => Ok(Command::Quit),
+            [] => Err(String::from("empty command")),
+            [name, ..] => Err(format!("can't understand '{name}' here, try help")),
+        }
+    }
+}
+
+const HELP: &str = "hit <x> <y>     object seen through a pixel
+info <id>       transform and material of an object
+render <file>   save a render, .ppm .pgm or .png
+quit";
+
+//Answers commands about one scene until quit or the end of the input. Bad commands
+//are reported and skipped, only failing to read or write stops the session.
+pub fn run(
+    w: &World,
+    camera: &Camera,
+    input: impl BufRead,
+    output: &mut impl Write,
+) -> io::Result<()> {
+    for line in input.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        match Command::parse(&line) {
+            Ok(Command::Quit) => break,
+            Ok(command) => execute(w, camera, command, output)?,
+            Err(message) => writeln!(output, "error: {message}")?,
+        }
+    }
+    Ok(())
+}
+
+fn execute(
+    w: &World,
+    camera: &Camera,
+    command: Command,
+    output: &mut impl Write,
+) -> io::Result<()> {
+    match command {
+        Command::Hit { x, y } => {
+            if x < 0 || x >= camera.hsize() || y < 0 || y >= camera.vsize() {
+                return writeln!(
+                    output,
+                    "error: pixel ({x}, {y}) is outside the {}x{} image",
+                    camera.hsize(),
+                    camera.vsize()
+                );
+            }
+            match w.nearest_hit(camera.ray_for_pixel(x, y)) {
+                Some(hit) => writeln!(output, "{} at t = {:.5}", hit.object.id(), hit.t),
+                None => writeln!(output, "nothing"),
+            }
+        }
+        Command::Info(id) => match w.objects.iter().find(|o| o.id() == id) {
+            Some(o) => writeln!(
+                output,
+                "transform: {:?}\nmaterial: {:?}",
+                o.transform.m, o.material
+            ),
+            None => writeln!(output, "error: no object {id}"),
+        },
+        Command::Render(file) => {
+            let mut canvas = Canvas::new(camera.hsize(), camera.vsize());
+            camera
+                .render_into(w, &mut canvas)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+            match canvas.save(&file) {
+                Ok(SaveFormat::PpmFallback) => writeln!(output, "saved {file} as ppm"),
+                Ok(_) => writeln!(output, "saved {file}"),
+                Err(e) => writeln!(output, "error: couldn't save {file}: {e}"),
+            }
+        }
+        Command::Help => writeln!(output, "{HELP}"),
+        Command::Quit => Ok(()),
+    }
+}
+
+//There is no scene file loader yet, so the inspector looks at the default world
+//through the camera the book uses for it
+pub fn inspect_default_scene() {
+    let w = World::default_world();
+    let mut camera = Camera::new(200, 100, FRAC_PI_3);
+    camera.transform = Matrix::view_transform(
+        RayTuple::point(0.0, 1.5, -5.0),
+        RayTuple::point(0.0, 0.0, 0.0),
+        RayTuple::vector(0.0, 1.0, 0.0),
+    );
+
+    let stdin = io::stdin();
+    let mut stdout = io::stdout();
+    if let Err(e) = run(&w, &camera, stdin.lock(), &mut stdout) {
+        eprintln!("inspector stopped: {e}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn parse_maps_lines_to_commands() {
+        assert_eq!(
+            Command::parse("hit 100 50"),
+            Ok(Command::Hit { x: 100, y: 50 })
+        );
+        assert_eq!(
+            Command::parse("  hit   3 4 "),
+            Ok(Command::Hit { x: 3, y: 4 })
+        );
+        assert_eq!(
+            Command::parse("render out.ppm"),
+            Ok(Command::Render(String::from("out.ppm")))
+        );
+        let id = Uuid::new_v4();
+        assert_eq!(Command::parse(&format!("info {id}")), Ok(Command::Info(id)));
+        assert_eq!(Command::parse("quit"), Ok(Command::Quit));
+
+        assert!(Command::parse("hit 100").is_err());
+        assert!(Command::parse("hit 100 50 7").is_err());
+        assert!(Command::parse("hit x 50").is_err());
+        assert!(Command::parse("hit 1.5 2").is_err());
+        assert!(Command::parse("info 42").is_err());
+        assert!(Command::parse("jump").is_err());
+        assert!(Command::parse("").is_err());
+    }
+
+    #[test]
+    fn session_reports_hits_and_errors() {
+        let w = World::default_world();
+        let mut camera = Camera::new(11, 11, FRAC_PI_3);
+        camera.transform = Matrix::view_transform(
+            RayTuple::point(0.0, 0.0, -5.0),
+            RayTuple::point(0.0, 0.0, 0.0),
+            RayTuple::vector(0.0, 1.0, 0.0),
+        );
+        let outer = w.objects[0].id();
+
+        let input = Cursor::new("hit 5 5\nhit 0 0\nhit 20 5\nbogus\nquit\nhit 5 5\n");
+        let mut output = Vec::new();
+        run(&w, &camera, input, &mut output).unwrap();
+        let text = String::from_utf8(output).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+
+        assert_eq!(lines.len(), 4);
+        assert_eq!(lines[0], format!("{outer} at t = 4.00000"));
+        assert_eq!(lines[1], "nothing");
+        assert!(lines[2].starts_with("error: pixel (20, 5)"));
+        assert!(lines[3].starts_with("error: can't understand 'bogus'"));
+    }
+}
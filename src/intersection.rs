@@ -1,26 +1,45 @@
 #![allow(dead_code)]
+use crate::bounds::BoundingBox;
 use crate::computations::Computations;
 use crate::ray::Ray;
-use crate::shape::Shape;
-
-#[derive(Debug, Clone, Copy)]
-pub struct Intersection {
+use crate::shape::{CubeFace, Shape};
+use std::borrow::Cow;
+use std::cmp::Ordering;
+
+//The object is borrowed from the scene. Only a moving shape hit at some time other
+//than 0 is copied, so the hit can carry the shape where it was at that moment.
+#[derive(Debug, Clone)]
+pub struct Intersection<'a> {
     pub t: f64,
-    pub object: Shape,
+    pub object: Cow<'a, Shape>,
+    pub face: Option<CubeFace>,
 }
 
-impl Intersection {
-    pub fn new(t: f64, object: Shape) -> Intersection {
-        Self { t, object }
+impl<'a> Intersection<'a> {
+    pub fn new(t: f64, object: &'a Shape) -> Self {
+        Self {
+            t,
+            object: Cow::Borrowed(object),
+            face: None,
+        }
+    }
+
+    //Cubes record which face the ray crossed so materials can vary per face
+    pub fn with_face(t: f64, object: &'a Shape, face: CubeFace) -> Self {
+        Self {
+            t,
+            object: Cow::Borrowed(object),
+            face: Some(face),
+        }
     }
 
-    pub fn hit(intersections: Vec<Intersection>) -> Option<Intersection> {
-        let mut lowest_positive_i: Option<Intersection> = None;
+    pub fn hit(intersections: Vec<Intersection<'a>>) -> Option<Intersection<'a>> {
+        let mut lowest_positive_i: Option<Intersection<'a>> = None;
         for i in intersections.into_iter() {
-            if i.t >= 0.0 && lowest_positive_i == None {
-                lowest_positive_i = Some(i);
+            if i.t >= 0.0 && lowest_positive_i.is_none() {
+                lowest_positive_i = Some(i.clone());
             }
-            match lowest_positive_i {
+            match &lowest_positive_i {
                 Some(intersection) => {
                     if i.t >= 0.0 && intersection.t > i.t {
                         lowest_positive_i = Some(i);
@@ -33,7 +52,33 @@ impl Intersection {
         lowest_positive_i
     }
 
-    pub fn prepare_computations(self, r: Ray, xs: Vec<Intersection>) -> Computations {
+    //Same hit as above along with its index in xs, so the caller can hand the
+    //surrounding intersections to prepare_computations without searching again
+    pub fn hit_with_context(xs: &[Intersection<'a>]) -> Option<(Intersection<'a>, usize)> {
+        let mut lowest: Option<usize> = None;
+        for (index, i) in xs.iter().enumerate() {
+            if i.t >= 0.0 && lowest.is_none_or(|l| xs[l].t > i.t) {
+                lowest = Some(index);
+            }
+        }
+
+        lowest.map(|index| (xs[index].clone(), index))
+    }
+
+    //Orders by t. Coincident hits put surfaces being entered before ones being left, then fall
+    //back to the shape id, so the refraction containers see the same sequence no matter what
+    //order the objects were added in.
+    pub fn order(r: Ray, a: &Intersection, b: &Intersection) -> Ordering {
+        let entering =
+            |i: &Intersection| i.object.normal_at(r.position(i.t)).dot(r.direction) < 0.0;
+
+        a.t.partial_cmp(&b.t)
+            .unwrap_or(Ordering::Equal)
+            .then_with(|| entering(b).cmp(&entering(a)))
+            .then_with(|| a.object.id().cmp(&b.object.id()))
+    }
+
+    pub fn prepare_computations(&self, r: Ray, xs: &[Intersection]) -> Computations<'_> {
         let p = r.position(self.t);
         let eyev = -r.direction;
         let mut normalv = self.object.normal_at(p);
@@ -43,42 +88,44 @@ impl Intersection {
             normalv = -normalv;
         }
         let over_point = p + normalv * 0.00001;
-        let under_point = p - normalv * 0.00001;
+        let under_point = p - normalv * self.object.surface_epsilon();
         let reflectv = r.direction.reflect(normalv);
 
-        let mut containers: Vec<Shape> = Vec::new();
+        //shapes the ray is inside of at each step, compared by id so no shape is cloned
+        let mut containers: Vec<&Shape> = Vec::new();
 
         let mut n1: f64 = 1.0;
         let mut n2: f64 = 1.0;
+        let mut n1_channels: [f64; 3] = [1.0; 3];
+        let mut n2_channels: [f64; 3] = [1.0; 3];
 
         for i in xs {
-            if self == i {
-                if containers.len() == 0 {
-                    n1 = 1.0;
-                } else {
-                    n1 = containers.last().unwrap().material.refractive_index;
+            let is_hit = i.t == self.t && i.object.id() == self.object.id();
+            if is_hit {
+                if let Some(shape) = containers.last() {
+                    n1 = shape.material.refractive_index;
+                    n1_channels = shape.material.channel_refractive_indices();
                 }
             }
 
-            if let Some(shape_index) = containers.iter().position(|&s| s == i.object) {
+            if let Some(shape_index) = containers.iter().position(|s| s.id() == i.object.id()) {
                 containers.remove(shape_index);
             } else {
-                containers.push(i.object);
+                containers.push(&i.object);
             }
 
-            if self == i {
-                if containers.len() == 0 {
-                    n2 = 1.0;
-                } else {
-                    n2 = containers.last().unwrap().material.refractive_index;
+            if is_hit {
+                if let Some(shape) = containers.last() {
+                    n2 = shape.material.refractive_index;
+                    n2_channels = shape.material.channel_refractive_indices();
                 }
                 break;
             }
         }
 
-        Computations::new(
+        let mut comps = Computations::new(
             self.t,
-            self.object,
+            &self.object,
             p,
             over_point,
             eyev,
@@ -88,7 +135,11 @@ impl Intersection {
             n1,
             n2,
             under_point,
-        )
+            n1_channels,
+            n2_channels,
+        );
+        comps.time = r.time;
+        comps
     }
 
     pub fn schlick(comps: Computations) -> f64 {
@@ -111,7 +162,95 @@ impl Intersection {
     }
 }
 
-impl PartialEq for Intersection {
+//The `capacity` nearest intersections in front of a ray, sorted by Intersection::order.
+//Shapes are offered one at a time. Once the list is full, shapes whose bounds start past
+//its farthest hit are skipped without being intersected, and farther hits are dropped.
+pub struct NearestHits<'a> {
+    r: Ray,
+    capacity: usize,
+    hits: Vec<Intersection<'a>>,
+    keep_behind: bool,
+    behind: Vec<Intersection<'a>>,
+}
+
+impl<'a> NearestHits<'a> {
+    pub fn new(r: Ray, capacity: usize) -> Self {
+        Self {
+            r,
+            capacity,
+            hits: Vec::with_capacity(capacity + 1),
+            keep_behind: false,
+            behind: Vec::new(),
+        }
+    }
+
+    //Also keeps every intersection behind the origin, which prepare_computations needs
+    //to know what the ray starts inside of. Shapes entirely behind it can still be
+    //skipped, the ray enters and leaves them before it starts.
+    pub fn with_behind(r: Ray, capacity: usize) -> Self {
+        Self {
+            keep_behind: true,
+            ..Self::new(r, capacity)
+        }
+    }
+
+    //Nothing farther along the ray than this can make it into the list
+    pub fn max_t(&self) -> f64 {
+        if self.capacity == 0 {
+            return f64::NEG_INFINITY;
+        }
+        match self.hits.last() {
+            Some(last) if self.hits.len() == self.capacity => last.t,
+            _ => f64::INFINITY,
+        }
+    }
+
+    //Whether anything inside the box could still make it into the list
+    pub fn reaches(&self, b: &BoundingBox) -> bool {
+        b.line_span(self.r)
+            .is_some_and(|(enter, exit)| exit >= 0.0 && enter <= self.max_t() + 0.00001)
+    }
+
+    pub fn offer(&mut self, shape: &'a Shape) {
+        let b = shape.bounds().transform(shape.transform_at(self.r.time));
+        if !self.reaches(&b) {
+            return;
+        }
+        let xs = shape.intersect_until(self.r, self.max_t());
+        #[cfg(feature = "logging")]
+        log::debug!("object {} intersections: {}", shape.id(), xs.len());
+        for i in xs {
+            self.insert(i);
+        }
+    }
+
+    fn insert(&mut self, i: Intersection<'a>) {
+        if i.t < 0.0 {
+            if self.keep_behind {
+                let index = self
+                    .behind
+                    .partition_point(|n| Intersection::order(self.r, n, &i) != Ordering::Greater);
+                self.behind.insert(index, i);
+            }
+            return;
+        }
+        let index = self
+            .hits
+            .partition_point(|n| Intersection::order(self.r, n, &i) != Ordering::Greater);
+        if index < self.capacity {
+            self.hits.insert(index, i);
+            self.hits.truncate(self.capacity);
+        }
+    }
+
+    //Any kept intersections behind the origin come first, everything in order
+    pub fn into_hits(mut self) -> Vec<Intersection<'a>> {
+        self.behind.append(&mut self.hits);
+        self.behind
+    }
+}
+
+impl PartialEq for Intersection<'_> {
     fn eq(&self, other: &Self) -> bool {
         self.t == other.t && self.object == other.object
     }
@@ -120,37 +259,35 @@ impl PartialEq for Intersection {
 #[macro_export]
 macro_rules! intersections {
     ( $( $x:expr ),* ) => {
-        {
-            let mut temp_vec: Vec<Intersection> = Vec::new();
-            $(
-                temp_vec.push($x);
-            )*
-            temp_vec
-        }
+        Vec::<Intersection>::from([$($x),*])
     };
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::camera::Camera;
+    use crate::color::Color;
     use crate::matrix::Matrix;
     use crate::raytuple::RayTuple;
     use crate::shape::{Shape, ShapeType};
+    use crate::world::World;
+    use std::f64::consts::FRAC_PI_3;
 
     #[test]
     fn intersection_encapsulates_time_and_object() {
         let s = Shape::new(ShapeType::Sphere);
-        let i = Intersection::new(3.5, s);
+        let i = Intersection::new(3.5, &s);
 
         assert_eq!(i.t, 3.5);
-        assert_eq!(i.object, s);
+        assert_eq!(*i.object, s);
     }
 
     #[test]
     fn aggregate_intersections() {
         let s = Shape::new(ShapeType::Sphere);
-        let i1 = Intersection::new(1.0, s);
-        let i2 = Intersection::new(2.0, s);
+        let i1 = Intersection::new(1.0, &s);
+        let i2 = Intersection::new(2.0, &s);
         let xs = intersections!(i1, i2);
 
         assert_eq!(xs.len(), 2);
@@ -161,9 +298,9 @@ mod tests {
     #[test]
     fn hit_with_all_positives() {
         let s = Shape::new(ShapeType::Sphere);
-        let i1 = Intersection::new(1.0, s);
-        let i2 = Intersection::new(2.0, s);
-        let xs = intersections!(i2, i1);
+        let i1 = Intersection::new(1.0, &s);
+        let i2 = Intersection::new(2.0, &s);
+        let xs = intersections!(i2.clone(), i1.clone());
 
         let i = Intersection::hit(xs).unwrap();
         assert_eq!(i, i1);
@@ -172,9 +309,9 @@ mod tests {
     #[test]
     fn hit_with_some_negatives() {
         let s = Shape::new(ShapeType::Sphere);
-        let i1 = Intersection::new(-1.0, s);
-        let i2 = Intersection::new(1.0, s);
-        let xs = intersections!(i2, i1);
+        let i1 = Intersection::new(-1.0, &s);
+        let i2 = Intersection::new(1.0, &s);
+        let xs = intersections!(i2.clone(), i1.clone());
 
         let i = Intersection::hit(xs).unwrap();
         assert_eq!(i, i2);
@@ -183,30 +320,44 @@ mod tests {
     #[test]
     fn hit_with_all_negatives() {
         let s = Shape::new(ShapeType::Sphere);
-        let i1 = Intersection::new(-2.0, s);
-        let i2 = Intersection::new(-1.0, s);
-        let xs = intersections!(i2, i1);
-
-        let i = Intersection::hit(xs);
-        match i {
-            Some(_) => panic!("Test Failed to return none"),
-            None => assert!(true),
-        }
+        let i1 = Intersection::new(-2.0, &s);
+        let i2 = Intersection::new(-1.0, &s);
+        let xs = intersections!(i2.clone(), i1.clone());
+
+        assert!(Intersection::hit(xs).is_none());
     }
 
     #[test]
     fn hit_is_always_lowest_positive() {
         let s = Shape::new(ShapeType::Sphere);
-        let i1 = Intersection::new(5.0, s);
-        let i2 = Intersection::new(7.0, s);
-        let i3 = Intersection::new(-3.0, s);
-        let i4 = Intersection::new(2.0, s);
-        let xs = intersections!(i1, i2, i3, i4);
+        let i1 = Intersection::new(5.0, &s);
+        let i2 = Intersection::new(7.0, &s);
+        let i3 = Intersection::new(-3.0, &s);
+        let i4 = Intersection::new(2.0, &s);
+        let xs = intersections!(i1, i2, i3, i4.clone());
 
         let i = Intersection::hit(xs).unwrap();
         assert_eq!(i, i4);
     }
 
+    #[test]
+    fn hit_with_context_gives_hit_and_its_index() {
+        let s = Shape::new(ShapeType::Sphere);
+        let xs = intersections!(
+            Intersection::new(5.0, &s),
+            Intersection::new(-3.0, &s),
+            Intersection::new(2.0, &s),
+            Intersection::new(7.0, &s)
+        );
+
+        let (hit, index) = Intersection::hit_with_context(&xs).unwrap();
+        assert_eq!(Some(hit), Intersection::hit(xs.clone()));
+        assert_eq!(index, 2);
+
+        let behind = intersections!(Intersection::new(-1.0, &s));
+        assert!(Intersection::hit_with_context(&behind).is_none());
+    }
+
     #[test]
     fn precomputing_intersection_state() {
         let r = Ray::new(
@@ -214,12 +365,12 @@ mod tests {
             RayTuple::vector(0.0, 0.0, 1.0),
         );
         let shape = Shape::new(ShapeType::Sphere);
-        let i = Intersection::new(4.0, shape);
+        let i = Intersection::new(4.0, &shape);
         let xs: Vec<Intersection> = Vec::new();
-        let comps = i.prepare_computations(r, xs);
+        let comps = i.prepare_computations(r, &xs);
 
         assert_eq!(comps.t, i.t);
-        assert_eq!(comps.object, i.object);
+        assert_eq!(*comps.object, *i.object);
         assert_eq!(comps.point, RayTuple::point(0.0, 0.0, -1.0));
         assert_eq!(comps.eyev, RayTuple::vector(0.0, 0.0, -1.0));
         assert_eq!(comps.normalv, RayTuple::vector(0.0, 0.0, -1.0));
@@ -232,11 +383,11 @@ mod tests {
             RayTuple::vector(0.0, 0.0, 1.0),
         );
         let shape = Shape::new(ShapeType::Sphere);
-        let i = Intersection::new(4.0, shape);
+        let i = Intersection::new(4.0, &shape);
         let xs: Vec<Intersection> = Vec::new();
-        let comps = i.prepare_computations(r, xs);
+        let comps = i.prepare_computations(r, &xs);
 
-        assert_eq!(comps.inside, false);
+        assert!(!comps.inside);
     }
 
     #[test]
@@ -246,9 +397,9 @@ mod tests {
             RayTuple::vector(0.0, 0.0, 1.0),
         );
         let shape = Shape::new(ShapeType::Sphere);
-        let i = Intersection::new(1.0, shape);
+        let i = Intersection::new(1.0, &shape);
         let xs: Vec<Intersection> = Vec::new();
-        let comps = i.prepare_computations(r, xs);
+        let comps = i.prepare_computations(r, &xs);
 
         assert_eq!(comps.point, RayTuple::point(0.0, 0.0, 1.0));
         assert_eq!(comps.eyev, RayTuple::vector(0.0, 0.0, -1.0));
@@ -264,9 +415,9 @@ mod tests {
         );
         let mut shape = Shape::new(ShapeType::Sphere);
         shape.transform = Matrix::translation(0.0, 0.0, 1.0);
-        let i = Intersection::new(5.0, shape);
+        let i = Intersection::new(5.0, &shape);
         let xs: Vec<Intersection> = Vec::new();
-        let comps = i.prepare_computations(r, xs);
+        let comps = i.prepare_computations(r, &xs);
 
         assert!(comps.over_point.z < (-f64::EPSILON / 2.0));
         assert!(comps.point.z > comps.over_point.z);
@@ -279,9 +430,9 @@ mod tests {
             RayTuple::point(0.0, 1.0, -1.0),
             RayTuple::vector(0.0, -2.0_f64.sqrt() / 2.0, 2.0_f64.sqrt() / 2.0),
         );
-        let i = Intersection::new(2.0_f64.sqrt(), shape);
+        let i = Intersection::new(2.0_f64.sqrt(), &shape);
         let xs: Vec<Intersection> = Vec::new();
-        let comps = i.prepare_computations(r, xs);
+        let comps = i.prepare_computations(r, &xs);
 
         assert_eq!(
             comps.reflectv,
@@ -308,12 +459,12 @@ mod tests {
             RayTuple::vector(0.0, 0.0, 1.0),
         );
         let xs1 = intersections!(
-            Intersection::new(2.0, a),
-            Intersection::new(2.75, b),
-            Intersection::new(3.25, c),
-            Intersection::new(4.75, b),
-            Intersection::new(5.25, c),
-            Intersection::new(6.0, a)
+            Intersection::new(2.0, &a),
+            Intersection::new(2.75, &b),
+            Intersection::new(3.25, &c),
+            Intersection::new(4.75, &b),
+            Intersection::new(5.25, &c),
+            Intersection::new(6.0, &a)
         );
 
         let xs2 = xs1.clone();
@@ -322,27 +473,27 @@ mod tests {
         let xs5 = xs1.clone();
         let xs6 = xs1.clone();
 
-        let comps1 = xs1[0].prepare_computations(r, xs1);
+        let comps1 = xs1[0].prepare_computations(r, &xs1);
         assert_eq!(comps1.n1, 1.0);
         assert_eq!(comps1.n2, 1.5);
 
-        let comps2 = xs2[1].prepare_computations(r, xs2);
+        let comps2 = xs2[1].prepare_computations(r, &xs2);
         assert_eq!(comps2.n1, 1.5);
         assert_eq!(comps2.n2, 2.0);
 
-        let comps3 = xs3[2].prepare_computations(r, xs3);
+        let comps3 = xs3[2].prepare_computations(r, &xs3);
         assert_eq!(comps3.n1, 2.0);
         assert_eq!(comps3.n2, 2.5);
 
-        let comps4 = xs4[3].prepare_computations(r, xs4);
+        let comps4 = xs4[3].prepare_computations(r, &xs4);
         assert_eq!(comps4.n1, 2.5);
         assert_eq!(comps4.n2, 2.5);
 
-        let comps5 = xs5[4].prepare_computations(r, xs5);
+        let comps5 = xs5[4].prepare_computations(r, &xs5);
         assert_eq!(comps5.n1, 2.5);
         assert_eq!(comps5.n2, 1.5);
 
-        let comps6 = xs6[5].prepare_computations(r, xs6);
+        let comps6 = xs6[5].prepare_computations(r, &xs6);
         assert_eq!(comps6.n1, 1.5);
         assert_eq!(comps6.n2, 1.0);
     }
@@ -355,15 +506,73 @@ mod tests {
         );
         let mut shape = Shape::glass_sphere();
         shape.transform = Matrix::translation(0.0, 0.0, 1.0);
-        let i = Intersection::new(5.0, shape);
-        let xs = intersections!(i);
+        let i = Intersection::new(5.0, &shape);
+        let xs = intersections!(i.clone());
 
-        let comps = i.prepare_computations(r, xs);
+        let comps = i.prepare_computations(r, &xs);
         let epsilon: f64 = 0.00001;
         assert!(comps.under_point.z > epsilon / 2.0);
         assert!(comps.point.z < comps.under_point.z);
     }
 
+    #[test]
+    fn underpoint_stays_inside_a_thin_shell() {
+        //a hollow glass ball 1mm across whose wall is thinner than the default offset
+        let mut outer = Shape::glass_sphere();
+        outer.transform = Matrix::scaling(0.001, 0.001, 0.001);
+        let mut inner = Shape::glass_sphere();
+        inner.transform = Matrix::scaling(0.000995, 0.000995, 0.000995);
+        inner.material.refractive_index = 1.0;
+        let mut w = World::new();
+        w.objects.push(outer);
+        w.objects.push(inner);
+
+        let r = Ray::new(
+            RayTuple::point(0.0, 0.0, -5.0),
+            RayTuple::vector(0.0, 0.0, 1.0),
+        );
+        let xs = w.intersect_world(r);
+        let comps = xs[0].prepare_computations(r, &xs);
+        assert!(comps.under_point.z > -0.001 && comps.under_point.z < -0.000995);
+
+        //the refracted ray has to meet the inner wall instead of starting past it
+        let through = Ray::new(comps.under_point, r.direction);
+        let next = Intersection::hit(w.intersect_world(through)).unwrap();
+        assert_eq!(*next.object, w.objects[1]);
+    }
+
+    //Centre pixel of a glass shell glowing red around glowing green air, with no lights.
+    //Along the axis only the order of the surfaces matters, not how far apart they are.
+    fn render_shell(outer_transform: Matrix, inner_transform: Matrix) -> Color {
+        let mut outer = Shape::glass_sphere();
+        outer.transform = outer_transform;
+        outer.material.emission = Color::new(0.1, 0.0, 0.0);
+        let mut inner = Shape::glass_sphere();
+        inner.transform = inner_transform;
+        inner.material.refractive_index = 1.0;
+        inner.material.emission = Color::new(0.0, 0.1, 0.0);
+        let mut w = World::new();
+        w.lights.clear();
+        w.objects.push(outer);
+        w.objects.push(inner);
+
+        Camera::new(1, 1, FRAC_PI_3).render(w).pixel_at(0, 0)
+    }
+
+    #[test]
+    fn flattened_thin_shell_renders_without_leaking() {
+        //the wall is 1e-7 thick along the view, well under the offset an
+        //average (volume) scale would give the outer surface
+        let center = Matrix::translation(0.0, 0.0, -5.0);
+        let thin = render_shell(
+            center * Matrix::scaling(1.0, 1.0, 0.0001),
+            center * Matrix::scaling(0.99, 0.99, 0.0000999),
+        );
+        let thick = render_shell(center, center * Matrix::scaling(0.5, 0.5, 0.5));
+
+        assert_eq!(thin, thick);
+    }
+
     #[test]
     fn schlick_under_total_internal() {
         let shape = Shape::glass_sphere();
@@ -372,10 +581,10 @@ mod tests {
             RayTuple::vector(0.0, 1.0, 0.0),
         );
         let xs = intersections!(
-            Intersection::new(-2.0_f64.sqrt() / 2.0, shape),
-            Intersection::new(2.0_f64.sqrt() / 2.0, shape)
+            Intersection::new(-2.0_f64.sqrt() / 2.0, &shape),
+            Intersection::new(2.0_f64.sqrt() / 2.0, &shape)
         );
-        let comps = xs[1].prepare_computations(r, xs);
+        let comps = xs[1].prepare_computations(r, &xs);
         let reflectance = Intersection::schlick(comps);
 
         assert_eq!(reflectance, 1.0);
@@ -389,10 +598,10 @@ mod tests {
             RayTuple::vector(0.0, 1.0, 0.0),
         );
         let xs = intersections!(
-            Intersection::new(-1.0, shape),
-            Intersection::new(1.0, shape)
+            Intersection::new(-1.0, &shape),
+            Intersection::new(1.0, &shape)
         );
-        let comps = xs[1].prepare_computations(r, xs);
+        let comps = xs[1].prepare_computations(r, &xs);
         let reflectance = Intersection::schlick(comps);
 
         assert_eq!(reflectance, 0.04000000000000001);
@@ -405,10 +614,31 @@ mod tests {
             RayTuple::point(0.0, 0.99, -2.0),
             RayTuple::vector(0.0, 0.0, 1.0),
         );
-        let xs = intersections!(Intersection::new(1.8589, shape));
-        let comps = xs[0].prepare_computations(r, xs);
+        let xs = intersections!(Intersection::new(1.8589, &shape));
+        let comps = xs[0].prepare_computations(r, &xs);
         let reflectance = Intersection::schlick(comps);
 
         assert_eq!(reflectance, 0.48873081012212183);
     }
+
+    #[test]
+    fn containers_match_moved_copies_of_the_same_shape() {
+        let mut a = Shape::glass_sphere();
+        a.transform = Matrix::scaling(2.0, 2.0, 2.0);
+        a.material.refractive_index = 1.5;
+
+        //motion blur stores hits on copies carrying the moved transform
+        let mut moved = a.clone();
+        moved.transform = Matrix::translation(0.0, 0.0, 0.1) * a.transform;
+
+        let r = Ray::new(
+            RayTuple::point(0.0, 0.0, -4.0),
+            RayTuple::vector(0.0, 0.0, 1.0),
+        );
+        let xs = intersections!(Intersection::new(2.0, &a), Intersection::new(6.0, &moved));
+
+        let comps = xs[1].prepare_computations(r, &xs);
+        assert_eq!(comps.n1, 1.5);
+        assert_eq!(comps.n2, 1.0);
+    }
 }
@@ -1,12 +1,41 @@
 #![allow(dead_code)]
 
 use crate::color::Color;
+use crate::matrix::Matrix;
 use crate::raytuple::RayTuple;
+use crate::rng::Rng;
+use crate::sampling;
+use std::f64::consts::PI;
 
-#[derive(Debug)]
+//Number of points a spotlight's source disc is sampled with
+const DISC_SAMPLES: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LightType {
+    Point,
+    //angle is the half angle of the cone, radius the size of the emitting disc
+    Spot {
+        direction: RayTuple,
+        angle: f64,
+        radius: f64,
+    },
+    //rectangle spanned from corner by usteps cells of uvec and vsteps cells of vvec;
+    //stratified puts one jittered sample in each cell instead of scattering them at random
+    Area {
+        corner: RayTuple,
+        uvec: RayTuple,
+        usteps: usize,
+        vvec: RayTuple,
+        vsteps: usize,
+        stratified: bool,
+    },
+}
+
+#[derive(Debug, Clone, Copy)]
 pub struct Light {
     pub position: RayTuple,
     pub intensity: Color,
+    pub light_type: LightType,
 }
 
 impl Light {
@@ -14,13 +43,188 @@ impl Light {
         Self {
             position,
             intensity,
+            light_type: LightType::Point,
+        }
+    }
+
+    pub fn spot_light(
+        position: RayTuple,
+        direction: RayTuple,
+        angle: f64,
+        radius: f64,
+        intensity: Color,
+    ) -> Self {
+        Self {
+            position,
+            intensity,
+            light_type: LightType::Spot {
+                direction: direction.normalize(),
+                angle,
+                radius,
+            },
+        }
+    }
+
+    //The light's position is the centre of the rectangle, used for specular highlights
+    pub fn area_light(
+        corner: RayTuple,
+        full_uvec: RayTuple,
+        usteps: usize,
+        full_vvec: RayTuple,
+        vsteps: usize,
+        intensity: Color,
+    ) -> Self {
+        Self {
+            position: corner + full_uvec * 0.5 + full_vvec * 0.5,
+            intensity,
+            light_type: LightType::Area {
+                corner,
+                uvec: full_uvec / usteps as f64,
+                usteps,
+                vvec: full_vvec / vsteps as f64,
+                vsteps,
+                stratified: false,
+            },
+        }
+    }
+
+    //Switches an area light between stratified and uniform random sampling
+    pub fn set_stratified(&mut self, on: bool) {
+        if let LightType::Area { stratified, .. } = &mut self.light_type {
+            *stratified = on;
+        }
+    }
+
+    pub fn set_position(&mut self, position: RayTuple) {
+        self.position = position;
+    }
+
+    pub fn set_intensity(&mut self, intensity: Color) {
+        self.intensity = intensity;
+    }
+
+    //Copy of the light moved by a transform, a spotlight's aim turns with it
+    pub fn transformed(&self, by: Matrix) -> Self {
+        let mut light = *self;
+        light.position = by * self.position;
+        match self.light_type {
+            LightType::Spot {
+                direction,
+                angle,
+                radius,
+            } => {
+                light.light_type = LightType::Spot {
+                    direction: (by * direction).normalize(),
+                    angle,
+                    radius,
+                };
+            }
+            LightType::Area {
+                corner,
+                uvec,
+                usteps,
+                vvec,
+                vsteps,
+                stratified,
+            } => {
+                light.light_type = LightType::Area {
+                    corner: by * corner,
+                    uvec: by * uvec,
+                    usteps,
+                    vvec: by * vvec,
+                    vsteps,
+                    stratified,
+                };
+            }
+            LightType::Point => {}
+        }
+        light
+    }
+
+    //Whether the point falls inside the light's cone, always true for point lights
+    pub fn illuminates(&self, point: RayTuple) -> bool {
+        match self.light_type {
+            LightType::Point | LightType::Area { .. } => true,
+            LightType::Spot {
+                direction, angle, ..
+            } => (point - self.position).normalize().dot(direction) >= angle.cos(),
+        }
+    }
+
+    //Half the angle the light's source covers as seen from the point, 0 for point lights
+    pub fn angular_radius(&self, point: RayTuple) -> f64 {
+        let radius = match self.light_type {
+            LightType::Point => return 0.0,
+            LightType::Spot { radius, .. } => radius,
+            LightType::Area {
+                uvec,
+                usteps,
+                vvec,
+                vsteps,
+                ..
+            } => (uvec * usteps as f64 + vvec * vsteps as f64).magnitude() / 2.0,
+        };
+        let distance = (self.position - point).magnitude();
+        radius.atan2(distance)
+    }
+
+    //Vectors from the point to each sample on the light's source; their length is the
+    //distance to that sample, so they can be used directly for shadow rays.
+    //Only area lights draw from rng, spotlight discs use a fixed spiral.
+    pub fn sample_directions(&self, point: RayTuple, rng: &mut Rng) -> Vec<RayTuple> {
+        match self.light_type {
+            LightType::Spot {
+                direction, radius, ..
+            } if radius > 0.0 => {
+                let (tangent, bitangent) = sampling::orthonormal_basis(direction);
+
+                //golden angle spiral spreads the samples evenly over the disc
+                let golden_angle = PI * (3.0 - 5.0_f64.sqrt());
+                (0..DISC_SAMPLES)
+                    .map(|i| {
+                        let r = radius * ((i as f64 + 0.5) / DISC_SAMPLES as f64).sqrt();
+                        let theta = i as f64 * golden_angle;
+                        let sample = self.position
+                            + tangent * (r * theta.cos())
+                            + bitangent * (r * theta.sin());
+                        sample - point
+                    })
+                    .collect()
+            }
+            LightType::Area {
+                corner,
+                uvec,
+                usteps,
+                vvec,
+                vsteps,
+                stratified,
+            } => {
+                let mut directions = Vec::with_capacity(usteps * vsteps);
+                for v in 0..vsteps {
+                    for u in 0..usteps {
+                        let (su, sv) = if stratified {
+                            (u as f64 + rng.next_f64(), v as f64 + rng.next_f64())
+                        } else {
+                            (
+                                rng.next_f64() * usteps as f64,
+                                rng.next_f64() * vsteps as f64,
+                            )
+                        };
+                        directions.push(corner + uvec * su + vvec * sv - point);
+                    }
+                }
+                directions
+            }
+            _ => vec![self.position - point],
         }
     }
 }
 
 impl PartialEq for Light {
     fn eq(&self, other: &Self) -> bool {
-        self.position == other.position && self.intensity == other.intensity
+        self.position == other.position
+            && self.intensity == other.intensity
+            && self.light_type == other.light_type
     }
 }
 
@@ -37,4 +241,77 @@ mod tests {
         assert_eq!(light.intensity, intensity);
         assert_eq!(light.position, position);
     }
+
+    #[test]
+    fn spot_light_only_illuminates_inside_cone() {
+        let light = Light::spot_light(
+            RayTuple::point(0.0, 10.0, 0.0),
+            RayTuple::vector(0.0, -1.0, 0.0),
+            PI / 4.0,
+            0.0,
+            Color::new(1.0, 1.0, 1.0),
+        );
+
+        assert!(light.illuminates(RayTuple::point(5.0, 0.0, 0.0)));
+        assert!(!light.illuminates(RayTuple::point(15.0, 0.0, 0.0)));
+    }
+
+    #[test]
+    fn spot_light_samples_its_disc() {
+        let light = Light::spot_light(
+            RayTuple::point(0.0, 10.0, 0.0),
+            RayTuple::vector(0.0, -1.0, 0.0),
+            PI / 4.0,
+            1.0,
+            Color::new(1.0, 1.0, 1.0),
+        );
+        let point = RayTuple::point(0.0, 0.0, 0.0);
+        let directions = light.sample_directions(point, &mut Rng::new(0));
+
+        assert_eq!(directions.len(), DISC_SAMPLES);
+        for d in directions {
+            let sample = point + d;
+            assert!((sample.y - 10.0).abs() < 0.00001);
+            assert!((sample.x.powf(2.0) + sample.z.powf(2.0)).sqrt() <= 1.0);
+        }
+    }
+
+    #[test]
+    fn stratified_area_light_puts_one_sample_in_each_cell() {
+        let mut light = Light::area_light(
+            RayTuple::point(0.0, 0.0, 0.0),
+            RayTuple::vector(2.0, 0.0, 0.0),
+            4,
+            RayTuple::vector(0.0, 0.0, 1.0),
+            2,
+            Color::new(1.0, 1.0, 1.0),
+        );
+        light.set_stratified(true);
+        assert_eq!(light.position, RayTuple::point(1.0, 0.0, 0.5));
+
+        let point = RayTuple::point(0.0, -5.0, 0.0);
+        let directions = light.sample_directions(point, &mut Rng::new(7));
+
+        assert_eq!(directions.len(), 8);
+        for (i, d) in directions.iter().enumerate() {
+            let sample = point + *d;
+            let (u, v) = (i % 4, i / 4);
+            assert!(sample.x >= u as f64 * 0.5 && sample.x < (u + 1) as f64 * 0.5);
+            assert!(sample.z >= v as f64 * 0.5 && sample.z < (v + 1) as f64 * 0.5);
+        }
+    }
+
+    #[test]
+    fn translating_a_light_moves_its_position() {
+        let mut light =
+            Light::point_light(RayTuple::point(0.0, 0.0, 0.0), Color::new(1.0, 1.0, 1.0));
+        light.set_intensity(Color::new(0.5, 0.5, 0.5));
+
+        let moved = light.transformed(Matrix::translation(1.0, 0.0, 0.0));
+        assert_eq!(moved.position, RayTuple::point(1.0, 0.0, 0.0));
+        assert_eq!(moved.intensity, Color::new(0.5, 0.5, 0.5));
+
+        light.set_position(RayTuple::point(0.0, 2.0, 0.0));
+        assert_eq!(light.position, RayTuple::point(0.0, 2.0, 0.0));
+    }
 }
@@ -1,20 +1,35 @@
 use std::time::Instant;
 
+mod bounds;
+mod bvh;
 mod camera;
 mod canvas;
 mod color;
 mod computations;
+mod inspect;
 mod intersection;
 mod light;
 mod material;
 mod matrix;
+mod noise;
 mod pattern;
 mod ray;
 mod raytuple;
+mod rng;
+mod sampling;
+mod settings;
 mod shape;
+#[cfg(feature = "spectral")]
+mod spectrum;
 mod world;
 
 fn main() {
+    //`ray inspect` answers questions about a scene instead of rendering one
+    if std::env::args().nth(1).as_deref() == Some("inspect") {
+        inspect::inspect_default_scene();
+        return;
+    }
+
     let start_time = Instant::now();
 
     shape::chapter_thirteen_cylinders();
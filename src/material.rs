@@ -6,17 +6,48 @@ use crate::pattern::Pattern;
 use crate::raytuple::RayTuple;
 use crate::shape::Shape;
 
-#[derive(Debug, Clone, Copy)]
+//Separate terms of the Phong model, kept apart for AOV passes
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LightingResult {
+    pub ambient: Color,
+    pub diffuse: Color,
+    pub specular: Color,
+}
+
+impl LightingResult {
+    pub fn total(&self) -> Color {
+        self.ambient + self.diffuse + self.specular
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct Material {
     pub color: Color,
     pub ambient: f64,
     pub diffuse: f64,
     pub specular: f64,
     pub shininess: f64,
+    //tints highlights, e.g. colored metals; None keeps the light's color
+    pub specular_color: Option<Color>,
+    //light given off by the surface itself, added once whatever the lights are
+    pub emission: Color,
+    //a pattern replaces color entirely while use_pattern is set, clear the flag to
+    //fall back to color without throwing the pattern away
     pub pattern: Option<Pattern>,
+    pub use_pattern: bool,
     pub reflective: f64,
+    //reflections fade by exp(-reflective_falloff * distance) to what they show,
+    //0 keeps them at full strength however far away
+    pub reflective_falloff: f64,
     pub transparency: f64,
     pub refractive_index: f64,
+    //fraction of each channel left after light travels one unit through the inside,
+    //white is perfectly clear glass
+    pub transmission_color: Color,
+    pub ior_r: Option<f64>,
+    pub ior_g: Option<f64>,
+    pub ior_b: Option<f64>,
+    pub back_material: Option<Box<Material>>,
 }
 
 impl Material {
@@ -27,26 +58,83 @@ impl Material {
             diffuse: 0.9,
             specular: 0.9,
             shininess: 200.0,
+            specular_color: None,
+            emission: Color::new(0.0, 0.0, 0.0),
             pattern: None,
+            use_pattern: true,
             reflective: 0.0,
+            reflective_falloff: 0.0,
             transparency: 0.0,
             refractive_index: 1.0,
+            transmission_color: Color::new(1.0, 1.0, 1.0),
+            ior_r: None,
+            ior_g: None,
+            ior_b: None,
+            back_material: None,
         }
     }
 
+    //Per channel indices for dispersion, falling back to refractive_index when unset
+    pub fn channel_refractive_indices(&self) -> [f64; 3] {
+        [
+            self.ior_r.unwrap_or(self.refractive_index),
+            self.ior_g.unwrap_or(self.refractive_index),
+            self.ior_b.unwrap_or(self.refractive_index),
+        ]
+    }
+
+    //Beer's law, what's left after `distance` units inside is exp(-absorption * distance)
+    //with absorption = -ln(transmission_color), so each unit multiplies by the color again
+    pub fn transmittance(&self, distance: f64) -> Color {
+        let t = self.transmission_color;
+        Color::new(
+            t.red.powf(distance),
+            t.green.powf(distance),
+            t.blue.powf(distance),
+        )
+    }
+
     pub fn lighting(
-        self,
-        shape: Shape,
+        &self,
+        shape: &Shape,
         light: &Light,
         point: RayTuple,
         eyev: RayTuple,
         normalv: RayTuple,
         in_shadow: bool,
     ) -> Color {
-        let pattern_color = match self.pattern {
-            Some(p) => p.pattern_at_shape(shape, point),
-            None => self.color,
-        };
+        self.lighting_components(shape, light, point, eyev, normalv, in_shadow)
+            .total()
+    }
+
+    //The pattern's color at the point when there is one in use, otherwise the plain color
+    pub fn surface_color(
+        &self,
+        shape: &Shape,
+        point: RayTuple,
+        eyev: RayTuple,
+        normalv: RayTuple,
+    ) -> Color {
+        match &self.pattern {
+            Some(p) if self.use_pattern => {
+                p.pattern_at_shape_normal(shape, point, Pattern::view_space_normal(normalv, eyev))
+            }
+            _ => self.color,
+        }
+    }
+
+    //Same as lighting but returns the ambient, diffuse and specular terms separately
+    pub fn lighting_components(
+        &self,
+        shape: &Shape,
+        light: &Light,
+        point: RayTuple,
+        eyev: RayTuple,
+        normalv: RayTuple,
+        in_shadow: bool,
+    ) -> LightingResult {
+        let black = Color::new(0.0, 0.0, 0.0);
+        let pattern_color = self.surface_color(shape, point, eyev, normalv);
 
         //combine the surface color with the light's color/intensity
         let effective_color = pattern_color * light.intensity;
@@ -63,10 +151,13 @@ impl Material {
         let light_dot_normal = lightv.dot(normalv);
 
         if light_dot_normal < 0.0 || in_shadow {
-            //diffuse and specular are black, so no need to even add or return them
+            //diffuse and specular are black
             // OR if point is in shadow then we only use ambient
-            //we can simply return the ambient
-            return ambient;
+            LightingResult {
+                ambient,
+                diffuse: black,
+                specular: black,
+            }
         } else {
             //compute the diffuse contribution
             let diffuse = effective_color * self.diffuse * light_dot_normal;
@@ -78,14 +169,35 @@ impl Material {
             let reflect_dot_eye = reflectv.dot(eyev);
 
             if reflect_dot_eye <= 0.0 {
-                //specular is black, so return just the ambient + diffuse contributions
-                return ambient + diffuse;
+                //specular is black
+                LightingResult {
+                    ambient,
+                    diffuse,
+                    specular: black,
+                }
             } else {
-                //compute the specular contribution
-                let factor = f64::powf(reflect_dot_eye, self.shininess);
-                let specular = light.intensity * self.specular * factor;
-
-                ambient + diffuse + specular
+                //compute the specular contribution. A light with size blurs the highlight:
+                //the lobe is widened by the light's angular radius and scaled down to
+                //keep about the same energy, so point lights are left unchanged.
+                let spread = light.angular_radius(point);
+                let factor = if spread > 0.0 {
+                    let shininess = 2.0 / (2.0 / self.shininess + spread * spread);
+                    f64::powf(reflect_dot_eye, shininess) * (shininess + 2.0)
+                        / (self.shininess + 2.0)
+                } else {
+                    f64::powf(reflect_dot_eye, self.shininess)
+                };
+                let highlight = match self.specular_color {
+                    Some(tint) => light.intensity * tint,
+                    None => light.intensity,
+                };
+                let specular = highlight * self.specular * factor;
+
+                LightingResult {
+                    ambient,
+                    diffuse,
+                    specular,
+                }
             }
         }
     }
@@ -98,6 +210,15 @@ impl PartialEq for Material {
             && self.diffuse == other.diffuse
             && self.specular == other.specular
             && self.shininess == other.shininess
+            && self.specular_color == other.specular_color
+            && self.emission == other.emission
+            && self.use_pattern == other.use_pattern
+            && self.transmission_color == other.transmission_color
+            && self.reflective_falloff == other.reflective_falloff
+            && self.ior_r == other.ior_r
+            && self.ior_g == other.ior_g
+            && self.ior_b == other.ior_b
+            && self.back_material == other.back_material
     }
 }
 
@@ -128,7 +249,7 @@ mod tests {
         let normalv = RayTuple::vector(0.0, 0.0, -1.0);
         let light = Light::point_light(RayTuple::point(0.0, 0.0, -10.0), Color::new(1.0, 1.0, 1.0));
 
-        let result = m.lighting(Shape::test_shape(), &light, position, eyev, normalv, false);
+        let result = m.lighting(&Shape::test_shape(), &light, position, eyev, normalv, false);
         assert_eq!(result, Color::new(1.9, 1.9, 1.9));
     }
 
@@ -142,7 +263,7 @@ mod tests {
         let normalv = RayTuple::vector(0.0, 0.0, -1.0);
         let light = Light::point_light(RayTuple::point(0.0, 0.0, -10.0), Color::new(1.0, 1.0, 1.0));
 
-        let result = m.lighting(Shape::test_shape(), &light, position, eyev, normalv, false);
+        let result = m.lighting(&Shape::test_shape(), &light, position, eyev, normalv, false);
         assert_eq!(result, Color::new(1.0, 1.0, 1.0));
     }
 
@@ -157,7 +278,7 @@ mod tests {
         let light =
             Light::point_light(RayTuple::point(0.0, 10.0, -10.0), Color::new(1.0, 1.0, 1.0));
 
-        let result = m.lighting(Shape::test_shape(), &light, position, eyev, normalv, false);
+        let result = m.lighting(&Shape::test_shape(), &light, position, eyev, normalv, false);
         assert_eq!(result, Color::new(0.7364, 0.7364, 0.7364));
     }
 
@@ -172,7 +293,7 @@ mod tests {
         let light =
             Light::point_light(RayTuple::point(0.0, 10.0, -10.0), Color::new(1.0, 1.0, 1.0));
 
-        let result = m.lighting(Shape::test_shape(), &light, position, eyev, normalv, false);
+        let result = m.lighting(&Shape::test_shape(), &light, position, eyev, normalv, false);
         assert_eq!(result, Color::new(1.6364, 1.6364, 1.6364));
     }
 
@@ -186,7 +307,7 @@ mod tests {
         let normalv = RayTuple::vector(0.0, 0.0, -1.0);
         let light = Light::point_light(RayTuple::point(0.0, 0.0, 10.0), Color::new(1.0, 1.0, 1.0));
 
-        let result = m.lighting(Shape::test_shape(), &light, position, eyev, normalv, false);
+        let result = m.lighting(&Shape::test_shape(), &light, position, eyev, normalv, false);
         assert_eq!(result, Color::new(0.1, 0.1, 0.1));
     }
 
@@ -200,7 +321,7 @@ mod tests {
         let position = RayTuple::point(0.0, 0.0, 0.0);
 
         let result = m.lighting(
-            Shape::test_shape(),
+            &Shape::test_shape(),
             &light,
             position,
             eyev,
@@ -224,7 +345,7 @@ mod tests {
         let normalv = RayTuple::vector(0.0, 0.0, -1.0);
         let light = Light::point_light(RayTuple::point(0.0, 0.0, -10.0), Color::new(1.0, 1.0, 1.0));
         let c1 = m.lighting(
-            Shape::test_shape(),
+            &Shape::test_shape(),
             &light,
             RayTuple::point(0.9, 0.0, 0.0),
             eyev,
@@ -232,7 +353,7 @@ mod tests {
             false,
         );
         let c2 = m.lighting(
-            Shape::test_shape(),
+            &Shape::test_shape(),
             &light,
             RayTuple::point(1.1, 0.0, 0.0),
             eyev,
@@ -256,4 +377,173 @@ mod tests {
         assert_eq!(m.transparency, 0.0);
         assert_eq!(m.refractive_index, 1.0);
     }
+
+    #[test]
+    fn channel_indices_default_to_refractive_index() {
+        let mut m = Material::new();
+        m.refractive_index = 1.5;
+        assert_eq!(m.channel_refractive_indices(), [1.5, 1.5, 1.5]);
+
+        m.ior_b = Some(1.53);
+        assert_eq!(m.channel_refractive_indices(), [1.5, 1.5, 1.53]);
+    }
+
+    #[test]
+    fn lighting_components_eye_between() {
+        let m = Material::new();
+        let position = RayTuple::point(0.0, 0.0, 0.0);
+
+        let eyev = RayTuple::vector(0.0, 0.0, -1.0);
+        let normalv = RayTuple::vector(0.0, 0.0, -1.0);
+        let light = Light::point_light(RayTuple::point(0.0, 0.0, -10.0), Color::new(1.0, 1.0, 1.0));
+
+        let result =
+            m.lighting_components(&Shape::test_shape(), &light, position, eyev, normalv, false);
+        assert_eq!(result.ambient, Color::new(0.1, 0.1, 0.1));
+        assert_eq!(result.diffuse, Color::new(0.9, 0.9, 0.9));
+        assert_eq!(result.specular, Color::new(0.9, 0.9, 0.9));
+        assert_eq!(result.total(), Color::new(1.9, 1.9, 1.9));
+    }
+
+    #[test]
+    fn lighting_components_sum_to_lighting() {
+        let m = Material::new();
+        let position = RayTuple::point(0.0, 0.0, 0.0);
+
+        let eyev = RayTuple::vector(0.0, 2.0_f64.sqrt() / 2.0, -(2.0_f64.sqrt() / 2.0));
+        let normalv = RayTuple::vector(0.0, 0.0, -1.0);
+        let light =
+            Light::point_light(RayTuple::point(0.0, 10.0, -10.0), Color::new(1.0, 1.0, 1.0));
+
+        let result =
+            m.lighting_components(&Shape::test_shape(), &light, position, eyev, normalv, false);
+        let lit = m.lighting(&Shape::test_shape(), &light, position, eyev, normalv, false);
+        assert_eq!(result.ambient + result.diffuse + result.specular, lit);
+    }
+
+    #[test]
+    fn specular_color_tints_the_highlight() {
+        let mut m = Material::new();
+        let position = RayTuple::point(0.0, 0.0, 0.0);
+
+        let eyev = RayTuple::vector(0.0, -(2.0_f64.sqrt() / 2.0), -(2.0_f64.sqrt() / 2.0));
+        let normalv = RayTuple::vector(0.0, 0.0, -1.0);
+        let light =
+            Light::point_light(RayTuple::point(0.0, 10.0, -10.0), Color::new(1.0, 1.0, 1.0));
+
+        let white =
+            m.lighting_components(&Shape::test_shape(), &light, position, eyev, normalv, false);
+        assert_eq!(white.specular, Color::new(0.9, 0.9, 0.9));
+
+        m.specular_color = Some(Color::new(1.0, 0.0, 0.0));
+        let red =
+            m.lighting_components(&Shape::test_shape(), &light, position, eyev, normalv, false);
+        assert_eq!(red.specular, Color::new(0.9, 0.0, 0.0));
+        assert_eq!(red.diffuse, white.diffuse);
+    }
+
+    #[test]
+    fn pattern_takes_priority_over_color() {
+        let mut m = Material::new();
+        m.color = Color::new(0.2, 0.4, 0.6);
+        m.pattern = Some(Pattern::stripe_pattern(
+            Color::new(1.0, 1.0, 1.0),
+            Color::new(0.0, 0.0, 0.0),
+        ));
+        m.ambient = 1.0;
+        m.diffuse = 0.0;
+        m.specular = 0.0;
+        let eyev = RayTuple::vector(0.0, 0.0, -1.0);
+        let normalv = RayTuple::vector(0.0, 0.0, -1.0);
+        let light = Light::point_light(RayTuple::point(0.0, 0.0, -10.0), Color::new(1.0, 1.0, 1.0));
+        let point = RayTuple::point(0.5, 0.0, 0.0);
+
+        let lit =
+            |m: &Material| m.lighting(&Shape::test_shape(), &light, point, eyev, normalv, false);
+        assert_eq!(lit(&m), Color::new(1.0, 1.0, 1.0));
+
+        m.use_pattern = false;
+        assert_eq!(lit(&m), Color::new(0.2, 0.4, 0.6));
+
+        m.use_pattern = true;
+        m.pattern = None;
+        assert_eq!(lit(&m), Color::new(0.2, 0.4, 0.6));
+    }
+
+    #[test]
+    fn larger_area_light_gives_softer_highlight() {
+        let m = Material::new();
+        let point = RayTuple::point(0.0, 0.0, 0.0);
+        let normalv = RayTuple::vector(0.0, 0.0, -1.0);
+
+        //peak specular and how many of the sampled eye angles stay above a threshold
+        let highlight = |size: f64| {
+            let light = Light::area_light(
+                RayTuple::point(-size / 2.0, -size / 2.0, -10.0),
+                RayTuple::vector(size, 0.0, 0.0),
+                1,
+                RayTuple::vector(0.0, size, 0.0),
+                1,
+                Color::new(1.0, 1.0, 1.0),
+            );
+            let values: Vec<f64> = (0..60)
+                .map(|i| {
+                    let angle = i as f64 * 0.01;
+                    let eyev = RayTuple::vector(angle.sin(), 0.0, -angle.cos());
+                    m.lighting_components(&Shape::sphere(), &light, point, eyev, normalv, false)
+                        .specular
+                        .red
+                })
+                .collect();
+            let peak = values.iter().cloned().fold(0.0, f64::max);
+            let wide = values.iter().filter(|v| **v > 0.01).count();
+            (peak, wide)
+        };
+
+        let (small_peak, small_wide) = highlight(0.1);
+        let (large_peak, large_wide) = highlight(4.0);
+        assert!(large_peak < small_peak);
+        assert!(large_wide > small_wide);
+    }
+
+    #[test]
+    fn changing_later_fields_breaks_equality() {
+        let m = Material::new();
+        assert_eq!(m, m.clone());
+        assert_ne!(
+            m,
+            Material {
+                use_pattern: false,
+                ..m.clone()
+            }
+        );
+        assert_ne!(
+            m,
+            Material {
+                transmission_color: Color::new(0.5, 0.5, 0.5),
+                ..m.clone()
+            }
+        );
+        assert_ne!(
+            m,
+            Material {
+                reflective_falloff: 0.5,
+                ..m.clone()
+            }
+        );
+        assert_ne!(
+            m,
+            Material {
+                ior_b: Some(1.7),
+                ..m.clone()
+            }
+        );
+        assert_ne!(
+            m,
+            Material {
+                back_material: Some(Box::new(Material::new())),
+                ..m.clone()
+            }
+        );
+    }
 }
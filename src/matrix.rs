@@ -3,7 +3,7 @@ use crate::canvas::Canvas;
 use crate::color::Color;
 use crate::raytuple::RayTuple;
 use std::f64::consts::PI;
-use std::ops::{Index, IndexMut, Mul};
+use std::ops::{Index, IndexMut, Mul, MulAssign};
 
 #[derive(Debug, Clone, Copy)]
 pub struct Matrix {
@@ -47,6 +47,31 @@ impl Matrix {
         Self { size: 4, m: matrix }
     }
 
+    //Each tuple becomes one column, handy for building a change of basis
+    pub fn from_columns(c0: RayTuple, c1: RayTuple, c2: RayTuple, c3: RayTuple) -> Self {
+        Matrix::new_matrix4([
+            [c0.x, c1.x, c2.x, c3.x],
+            [c0.y, c1.y, c2.y, c3.y],
+            [c0.z, c1.z, c2.z, c3.z],
+            [c0.w, c1.w, c2.w, c3.w],
+        ])
+    }
+
+    fn product(&self, rhs: &Matrix) -> Matrix {
+        let mut ret_matrix = Matrix::new(self.size);
+
+        for r in 0_usize..4 {
+            for c in 0_usize..4 {
+                ret_matrix[r][c] = self[r][0] * rhs[0][c]
+                    + self[r][1] * rhs[1][c]
+                    + self[r][2] * rhs[2][c]
+                    + self[r][3] * rhs[3][c];
+            }
+        }
+
+        ret_matrix
+    }
+
     pub fn identity() -> Self {
         Self {
             size: 4,
@@ -83,7 +108,7 @@ impl Matrix {
             det = m[0][0] * m[1][1] - m[0][1] * m[1][0];
         } else {
             for col in 0_usize..m.size() as usize {
-                det = det + m[0][col] * m.cofactor(0, col);
+                det += m[0][col] * m.cofactor(0, col);
             }
         }
 
@@ -92,22 +117,19 @@ impl Matrix {
 
     //This will remove a row and column and reduce the Matrix dimensions
     pub fn submatrix(self, row: usize, col: usize) -> Matrix {
+        let size = self.size as usize;
+        assert!(
+            row < size && col < size,
+            "submatrix outside a {size}x{size} matrix"
+        );
         let mut ret_matrix = Matrix::new(self.size - 1);
 
-        let mut sh_i = 0;
-        for i in 0_usize..3 {
-            if i == row {
-                sh_i = i + 1;
-            }
-            let mut sh_j = 0;
-            for j in 0_usize..3 {
-                if j == col {
-                    sh_j = j + 1;
-                }
-                ret_matrix[i][j] = self[sh_i][sh_j];
-                sh_j += 1;
+        //copy every other row and column, closing up the gap left by the removed ones
+        //so entries past the smaller size stay zero
+        for (i, src_i) in (0..size).filter(|&i| i != row).enumerate() {
+            for (j, src_j) in (0..size).filter(|&j| j != col).enumerate() {
+                ret_matrix[i][j] = self[src_i][src_j];
             }
-            sh_i += 1;
         }
 
         ret_matrix
@@ -115,7 +137,7 @@ impl Matrix {
 
     //This uses submatrix and determinant
     pub fn minor(self, row: usize, col: usize) -> f64 {
-        let b = self.clone().submatrix(row, col);
+        let b = self.submatrix(row, col);
         Self::determinant(b)
     }
 
@@ -211,12 +233,10 @@ impl Matrix {
         let left = forward.cross(upn);
         let true_up = left.cross(forward);
 
-        let orientation = Matrix::new_matrix4([
-            [left.x, left.y, left.z, 0.0],
-            [true_up.x, true_up.y, true_up.z, 0.0],
-            [-forward.x, -forward.y, -forward.z, 0.0],
-            [0.0, 0.0, 0.0, 1.0],
-        ]);
+        //the basis is orthonormal, so its inverse is just the transpose
+        let orientation =
+            Matrix::from_columns(left, true_up, -forward, RayTuple::new(0.0, 0.0, 0.0, 1.0))
+                .transpose();
         orientation * Matrix::translation(-from.x, -from.y, -from.z)
     }
 }
@@ -262,18 +282,22 @@ impl Mul for Matrix {
     type Output = Self;
 
     fn mul(self, rhs: Self) -> Self {
-        let mut ret_matrix = Matrix::new(self.size);
+        self.product(&rhs)
+    }
+}
 
-        for r in 0_usize..4 {
-            for c in 0_usize..4 {
-                ret_matrix[r][c] = self[r][0] * rhs[0][c]
-                    + self[r][1] * rhs[1][c]
-                    + self[r][2] * rhs[2][c]
-                    + self[r][3] * rhs[3][c];
-            }
-        }
+impl MulAssign for Matrix {
+    fn mul_assign(&mut self, rhs: Self) {
+        *self = self.product(&rhs);
+    }
+}
 
-        ret_matrix
+//Multiplies through references so long transform chains don't copy both operands
+impl Mul for &Matrix {
+    type Output = Matrix;
+
+    fn mul(self, rhs: Self) -> Matrix {
+        self.product(rhs)
     }
 }
 
@@ -296,6 +320,78 @@ impl Mul<RayTuple> for Matrix {
     }
 }
 
+pub fn chapter_three_matrix() {
+    //what happens when you invert the identity matrix?
+    let identity = Matrix::identity();
+    println!("Inverted identity matrix: {:?}", identity.inverse());
+    //A: It doesn't change
+
+    //what do you get when you multiply a matrix by it's inverse?
+    let a = Matrix::new_matrix4([
+        [1.0, 2.0, 3.0, 4.0],
+        [5.0, 6.0, 7.0, 8.0],
+        [9.0, -8.0, 7.0, -6.0],
+        [5.0, 4.0, -3.0, 2.0],
+    ]);
+    let b = a.inverse().unwrap();
+    let c = a * b;
+    println!("A multiplied by it's inverse, a * a^-1 = {:?}", c);
+    //A: When you multiply a matrix by it's inverse you get the identity matrix
+
+    //Is there any difference between the inverse of the transpose, and the transpose of the inverse of a matrix?
+    let a = Matrix::new_matrix4([
+        [-2.0, 5.0, 0.0, 6.0],
+        [6.0, -4.0, -3.0, -3.0],
+        [17.0, 1.0, -2.0, 8.0],
+        [0.0, 0.0, 3.0, -4.0],
+    ]);
+    let it = a.inverse().unwrap().transpose();
+    let ti = a.transpose().inverse().unwrap();
+
+    println!("There is no difference between the inverse of the transpose and the transpose of the inverse: {}", it==ti);
+
+    //Given multiplying the identity matrix by a tuple gives the tuple unchanged, what happens when you change
+    //any single element of the identity matrix prior to multiplying?
+    let r = RayTuple::new(1.0, 1.0, 1.0, 1.0);
+    let mut i = Matrix::identity();
+
+    assert_eq!(RayTuple::new(1.0, 1.0, 1.0, 1.0), i * r);
+
+    i[1][3] = 2.0;
+    let ir = i * r;
+
+    println!("i * r = {:?}", ir);
+    //A: It appears that altering other elements in the identity matrix adds to the tuple in some way
+    //this is likely useful for translation? changing the second row, fourth column to 2.0 ended up adding 2
+    //to the y coordinate in the tuple.
+}
+
+pub fn chapter_four_clockpoints() {
+    let mut can = Canvas::new(800, 800);
+    let radial_interval = PI / 6.0;
+    let clock_radius: f64 = (can.get_width() / 3) as f64;
+    let x_offset = (can.get_width() / 2) as f64;
+    let y_offset = (can.get_height() / 2) as f64;
+    let plot_color = Color::new(1.0, 1.0, 1.0);
+
+    let mut rotation_angle = 0.0;
+    let mut plot_point = RayTuple::point(0.0, clock_radius, 0.0);
+    while rotation_angle <= (2.0 * PI) {
+        let x: i32 = (x_offset + plot_point.x).round() as i32;
+        let y: i32 = (y_offset + plot_point.y).round() as i32;
+        can.write_pixel(x, y, plot_color);
+        println!(
+            "Rotation angle: {}, plotting point {},{}",
+            rotation_angle, plot_point.x, plot_point.y
+        );
+
+        rotation_angle += radial_interval;
+        let t = Matrix::rotation_z(radial_interval);
+        plot_point = t * plot_point;
+    }
+    can.save_ppm("clockface.ppm");
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -818,6 +914,38 @@ mod tests {
         assert_eq!(t * p, RayTuple::point(15.0, 0.0, 7.0));
     }
 
+    #[test]
+    fn from_columns_of_standard_basis_is_identity() {
+        let m = Matrix::from_columns(
+            RayTuple::vector(1.0, 0.0, 0.0),
+            RayTuple::vector(0.0, 1.0, 0.0),
+            RayTuple::vector(0.0, 0.0, 1.0),
+            RayTuple::new(0.0, 0.0, 0.0, 1.0),
+        );
+        assert_eq!(m, Matrix::identity());
+
+        let t = Matrix::from_columns(
+            RayTuple::vector(1.0, 0.0, 0.0),
+            RayTuple::vector(0.0, 1.0, 0.0),
+            RayTuple::vector(0.0, 0.0, 1.0),
+            RayTuple::point(2.0, 3.0, 4.0),
+        );
+        assert_eq!(t, Matrix::translation(2.0, 3.0, 4.0));
+    }
+
+    #[test]
+    fn reference_and_assigning_multiplication_match_mul() {
+        let a = Matrix::rotation_x(PI / 3.0) * Matrix::translation(1.0, -2.0, 3.0);
+        let b = Matrix::scaling(2.0, 0.5, 4.0) * Matrix::rotation_z(PI / 5.0);
+
+        let (ra, rb) = (&a, &b);
+        assert_eq!(ra * rb, a * b);
+
+        let mut c = a;
+        c *= b;
+        assert_eq!(c, a * b);
+    }
+
     #[test]
     fn view_transform_default() {
         let from = RayTuple::point(0.0, 0.0, 0.0);
@@ -863,76 +991,52 @@ mod tests {
         ]);
         assert_eq!(t, m);
     }
-}
-
-pub fn chapter_three_matrix() {
-    //what happens when you invert the identity matrix?
-    let identity = Matrix::identity();
-    println!("Inverted identity matrix: {:?}", identity.inverse());
-    //A: It doesn't change
-
-    //what do you get when you multiply a matrix by it's inverse?
-    let a = Matrix::new_matrix4([
-        [1.0, 2.0, 3.0, 4.0],
-        [5.0, 6.0, 7.0, 8.0],
-        [9.0, -8.0, 7.0, -6.0],
-        [5.0, 4.0, -3.0, 2.0],
-    ]);
-    let b = a.inverse().unwrap();
-    let c = a * b;
-    println!("A multiplied by it's inverse, a * a^-1 = {:?}", c);
-    //A: When you multiply a matrix by it's inverse you get the identity matrix
-
-    //Is there any difference between the inverse of the transpose, and the transpose of the inverse of a matrix?
-    let a = Matrix::new_matrix4([
-        [-2.0, 5.0, 0.0, 6.0],
-        [6.0, -4.0, -3.0, -3.0],
-        [17.0, 1.0, -2.0, 8.0],
-        [0.0, 0.0, 3.0, -4.0],
-    ]);
-    let it = a.inverse().unwrap().transpose();
-    let ti = a.transpose().inverse().unwrap();
-
-    println!("There is no difference between the inverse of the transpose and the transpose of the inverse: {}", it==ti);
-
-    //Given multiplying the identity matrix by a tuple gives the tuple unchanged, what happens when you change
-    //any single element of the identity matrix prior to multiplying?
-    let r = RayTuple::new(1.0, 1.0, 1.0, 1.0);
-    let mut i = Matrix::identity();
-
-    assert_eq!(RayTuple::new(1.0, 1.0, 1.0, 1.0), i * r);
-
-    i[1][3] = 2.0;
-    let ir = i * r;
-
-    println!("i * r = {:?}", ir);
-    //A: It appears that altering other elements in the identity matrix adds to the tuple in some way
-    //this is likely useful for translation? changing the second row, fourth column to 2.0 ended up adding 2
-    //to the y coordinate in the tuple.
-}
-
-pub fn chapter_four_clockpoints() {
-    let mut can = Canvas::new(800, 800);
-    let radial_interval = PI / 6.0;
-    let clock_radius: f64 = (can.get_width() / 3) as f64;
-    let x_offset = (can.get_width() / 2) as f64;
-    let y_offset = (can.get_height() / 2) as f64;
-    let plot_color = Color::new(1.0, 1.0, 1.0);
 
-    let mut rotation_angle = 0.0;
-    let mut plot_point = RayTuple::point(0.0, clock_radius, 0.0);
-    while rotation_angle <= (2.0 * PI) {
-        let x: i32 = (x_offset + plot_point.x).round() as i32;
-        let y: i32 = (y_offset + plot_point.y).round() as i32;
-        can.write_pixel(x, y, plot_color);
-        println!(
-            "Rotation angle: {}, plotting point {},{}",
-            rotation_angle, plot_point.x, plot_point.y
+    #[test]
+    fn submatrix_drops_any_row_and_column() {
+        let a = Matrix::new_matrix4([
+            [1.0, 2.0, 3.0, 4.0],
+            [5.0, 6.0, 7.0, 8.0],
+            [9.0, 10.0, 11.0, 12.0],
+            [13.0, 14.0, 15.0, 16.0],
+        ]);
+        assert_eq!(
+            a.submatrix(0, 0),
+            Matrix::new_matrix3([[6.0, 7.0, 8.0], [10.0, 11.0, 12.0], [14.0, 15.0, 16.0]])
+        );
+        assert_eq!(
+            a.submatrix(3, 3),
+            Matrix::new_matrix3([[1.0, 2.0, 3.0], [5.0, 6.0, 7.0], [9.0, 10.0, 11.0]])
+        );
+        assert_eq!(
+            a.submatrix(3, 0),
+            Matrix::new_matrix3([[2.0, 3.0, 4.0], [6.0, 7.0, 8.0], [10.0, 11.0, 12.0]])
+        );
+        assert_eq!(
+            a.submatrix(1, 3),
+            Matrix::new_matrix3([[1.0, 2.0, 3.0], [9.0, 10.0, 11.0], [13.0, 14.0, 15.0]])
         );
 
-        rotation_angle += radial_interval;
-        let t = Matrix::rotation_z(radial_interval);
-        plot_point = t * plot_point;
+        let b = Matrix::new_matrix3([[1.0, 2.0, 3.0], [4.0, 5.0, 6.0], [7.0, 8.0, 9.0]]);
+        assert_eq!(
+            b.submatrix(0, 0),
+            Matrix::new_matrix2([[5.0, 6.0], [8.0, 9.0]])
+        );
+        assert_eq!(
+            b.submatrix(2, 2),
+            Matrix::new_matrix2([[1.0, 2.0], [4.0, 5.0]])
+        );
+        assert_eq!(
+            b.submatrix(2, 0),
+            Matrix::new_matrix2([[2.0, 3.0], [5.0, 6.0]])
+        );
+        assert_eq!(
+            b.submatrix(1, 1),
+            Matrix::new_matrix2([[1.0, 3.0], [7.0, 9.0]])
+        );
+        assert_eq!(
+            b.submatrix(0, 2),
+            Matrix::new_matrix2([[4.0, 5.0], [7.0, 8.0]])
+        );
     }
-    can.save_ppm("clockface.ppm");
 }
@@ -0,0 +1,139 @@
+#![allow(dead_code)]
+
+use crate::raytuple::RayTuple;
+use crate::rng::Rng;
+
+//Classic gradient (Perlin) noise. The permutation table is shuffled from a seed,
+//so the same seed always gives the same field.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Perlin {
+    perm: Vec<usize>,
+}
+
+impl Perlin {
+    pub fn new(seed: u64) -> Self {
+        let mut rng = Rng::new(seed);
+        let mut table: Vec<usize> = (0..256).collect();
+        for i in (1..256).rev() {
+            let j = (rng.next_u64() % (i as u64 + 1)) as usize;
+            table.swap(i, j);
+        }
+
+        //doubled so lookups of perm[i + 1] never need wrapping
+        let perm = table.iter().chain(table.iter()).copied().collect();
+        Self { perm }
+    }
+
+    //Roughly in [-1, 1], zero at every integer lattice point
+    pub fn noise(&self, p: RayTuple) -> f64 {
+        let xi = (p.x.floor() as i64).rem_euclid(256) as usize;
+        let yi = (p.y.floor() as i64).rem_euclid(256) as usize;
+        let zi = (p.z.floor() as i64).rem_euclid(256) as usize;
+        let x = p.x - p.x.floor();
+        let y = p.y - p.y.floor();
+        let z = p.z - p.z.floor();
+
+        let u = fade(x);
+        let v = fade(y);
+        let w = fade(z);
+
+        let a = self.perm[xi] + yi;
+        let aa = self.perm[a] + zi;
+        let ab = self.perm[a + 1] + zi;
+        let b = self.perm[xi + 1] + yi;
+        let ba = self.perm[b] + zi;
+        let bb = self.perm[b + 1] + zi;
+
+        lerp(
+            w,
+            lerp(
+                v,
+                lerp(
+                    u,
+                    grad(self.perm[aa], x, y, z),
+                    grad(self.perm[ba], x - 1.0, y, z),
+                ),
+                lerp(
+                    u,
+                    grad(self.perm[ab], x, y - 1.0, z),
+                    grad(self.perm[bb], x - 1.0, y - 1.0, z),
+                ),
+            ),
+            lerp(
+                v,
+                lerp(
+                    u,
+                    grad(self.perm[aa + 1], x, y, z - 1.0),
+                    grad(self.perm[ba + 1], x - 1.0, y, z - 1.0),
+                ),
+                lerp(
+                    u,
+                    grad(self.perm[ab + 1], x, y - 1.0, z - 1.0),
+                    grad(self.perm[bb + 1], x - 1.0, y - 1.0, z - 1.0),
+                ),
+            ),
+        )
+    }
+
+    //Sum of octaves of |noise| at doubling frequency and halving weight
+    pub fn turbulence(&self, p: RayTuple, octaves: usize) -> f64 {
+        let mut sum = 0.0;
+        let mut frequency = 1.0;
+        for _ in 0..octaves {
+            let scaled = RayTuple::point(p.x * frequency, p.y * frequency, p.z * frequency);
+            sum += self.noise(scaled).abs() / frequency;
+            frequency *= 2.0;
+        }
+        sum
+    }
+}
+
+fn fade(t: f64) -> f64 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+fn lerp(t: f64, a: f64, b: f64) -> f64 {
+    a + t * (b - a)
+}
+
+//Picks one of twelve edge directions of a cube from the hash
+fn grad(hash: usize, x: f64, y: f64, z: f64) -> f64 {
+    let h = hash & 15;
+    let u = if h < 8 { x } else { y };
+    let v = if h < 4 {
+        y
+    } else if h == 12 || h == 14 {
+        x
+    } else {
+        z
+    };
+    (if h & 1 == 0 { u } else { -u }) + (if h & 2 == 0 { v } else { -v })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn same_seed_gives_same_noise() {
+        let a = Perlin::new(11);
+        let b = Perlin::new(11);
+        let c = Perlin::new(12);
+        let p = RayTuple::point(1.3, -2.7, 0.45);
+
+        assert_eq!(a.noise(p), b.noise(p));
+        assert_ne!(a, c);
+    }
+
+    #[test]
+    fn noise_is_zero_on_lattice_and_bounded_elsewhere() {
+        let perlin = Perlin::new(0);
+        assert_eq!(perlin.noise(RayTuple::point(3.0, -1.0, 7.0)), 0.0);
+
+        for i in 0..100 {
+            let f = i as f64 * 0.173;
+            let n = perlin.noise(RayTuple::point(f, f * 0.5 - 3.0, 2.0 - f));
+            assert!((-1.0..=1.0).contains(&n));
+        }
+    }
+}
@@ -1,22 +1,34 @@
 #![allow(dead_code)]
 use crate::camera::Camera;
+use crate::canvas::Canvas;
 use crate::color::Color;
 use crate::matrix::Matrix;
-use crate::raytuple::RayTuple;
+use crate::noise::Perlin;
+use crate::raytuple::{Axis, RayTuple};
 use crate::shape::Shape;
 use crate::world::World;
-use std::f64::consts::{FRAC_PI_2, FRAC_PI_3, FRAC_PI_8};
+use std::f64::consts::{FRAC_PI_2, FRAC_PI_3, FRAC_PI_8, PI};
+use std::sync::Arc;
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum PatternType {
-    Stripe,
+    Stripe(Axis),
     Gradient,
     Test,
     Ring,
     Checker,
+    RingGradient,
+    Matcap(Arc<Canvas>),
+    SphericalTexture(Arc<Canvas>),
+    Marble(Arc<Perlin>),
+    Wood(Arc<Perlin>),
+    Grid(f64),
 }
 
-#[derive(Debug, PartialEq, Clone, Copy)]
+//Noise patterns are built from this seed so they look the same every render
+const NOISE_SEED: u64 = 0;
+
+#[derive(Debug, PartialEq, Clone)]
 pub struct Pattern {
     pattern_type: PatternType,
     pub a: Color,
@@ -26,8 +38,13 @@ pub struct Pattern {
 
 impl Pattern {
     pub fn stripe_pattern(a: Color, b: Color) -> Self {
+        Self::stripe_axis(a, b, Axis::X)
+    }
+
+    //Stripes that alternate along the given axis instead of x, no rotation needed
+    pub fn stripe_axis(a: Color, b: Color, axis: Axis) -> Self {
         Self {
-            pattern_type: PatternType::Stripe,
+            pattern_type: PatternType::Stripe(axis),
             a,
             b,
             transform: Matrix::identity(),
@@ -70,10 +87,109 @@ impl Pattern {
         }
     }
 
+    //Smooth version of the ring pattern, fades from a to b across each ring
+    pub fn ring_gradient(a: Color, b: Color) -> Self {
+        Self {
+            pattern_type: PatternType::RingGradient,
+            a,
+            b,
+            transform: Matrix::identity(),
+        }
+    }
+
+    //Lines on the integer boundaries in x and z, a is the line color and b the background
+    pub fn grid(line_color: Color, background: Color, line_width: f64) -> Self {
+        Self {
+            pattern_type: PatternType::Grid(line_width),
+            a: line_color,
+            b: background,
+            transform: Matrix::identity(),
+        }
+    }
+
+    //Veins from a sine wave along x pushed around by turbulence, blends a into b
+    pub fn marble(a: Color, b: Color) -> Self {
+        Self {
+            pattern_type: PatternType::Marble(Arc::new(Perlin::new(NOISE_SEED))),
+            a,
+            b,
+            transform: Matrix::identity(),
+        }
+    }
+
+    //Rings around the y axis like ring_gradient, wobbled by noise so they look grown
+    pub fn wood(a: Color, b: Color) -> Self {
+        Self {
+            pattern_type: PatternType::Wood(Arc::new(Perlin::new(NOISE_SEED))),
+            a,
+            b,
+            transform: Matrix::identity(),
+        }
+    }
+
+    //A matcap ("material capture") colors a surface purely from the direction
+    //its normal faces relative to the viewer, ignoring the scene's lights
+    pub fn matcap_pattern(image: Canvas) -> Self {
+        Self {
+            pattern_type: PatternType::Matcap(Arc::new(image)),
+            a: Color::new(0.0, 0.0, 0.0),
+            b: Color::new(1.0, 1.0, 1.0),
+            transform: Matrix::identity(),
+        }
+    }
+
+    //Wraps an image around a unit sphere, u runs around the equator and v from bottom to top
+    pub fn spherical_texture(image: Canvas) -> Self {
+        Self {
+            pattern_type: PatternType::SphericalTexture(Arc::new(image)),
+            a: Color::new(0.0, 0.0, 0.0),
+            b: Color::new(1.0, 1.0, 1.0),
+            transform: Matrix::identity(),
+        }
+    }
+
+    //The origin has no direction to map, so it lands in the middle of the texture
+    pub fn spherical_map(point: RayTuple) -> (f64, f64) {
+        let theta = point.x.atan2(point.z);
+        let radius = RayTuple::vector(point.x, point.y, point.z).magnitude();
+        if !radius.is_normal() {
+            return (0.5, 0.5);
+        }
+        let phi = (point.y / radius).clamp(-1.0, 1.0).acos();
+        let raw_u = theta / (2.0 * PI);
+
+        (1.0 - (raw_u + 0.5), 1.0 - phi / PI)
+    }
+
+    //Bilinear lookup that wraps around horizontally, so u near 0 and near 1 blend across the seam
+    pub fn pattern_at_uv(&self, u: f64, v: f64) -> Color {
+        match &self.pattern_type {
+            PatternType::SphericalTexture(image) => {
+                let width = image.get_width();
+                let height = image.get_height();
+
+                let x = u * width as f64 - 0.5;
+                let y = (1.0 - v) * height as f64 - 0.5;
+                let fx = x - x.floor();
+                let fy = (y - y.floor()).clamp(0.0, 1.0);
+
+                let x0 = (x.floor() as i32).rem_euclid(width);
+                let x1 = (x0 + 1) % width;
+                let y0 = (y.floor() as i32).clamp(0, height - 1);
+                let y1 = (y0 + 1).min(height - 1);
+
+                let top = image.pixel_at(x0, y0) * (1.0 - fx) + image.pixel_at(x1, y0) * fx;
+                let bottom = image.pixel_at(x0, y1) * (1.0 - fx) + image.pixel_at(x1, y1) * fx;
+                top * (1.0 - fy) + bottom * fy
+            }
+            _ => self.a,
+        }
+    }
+
     pub fn pattern_at(&self, point: RayTuple) -> Color {
-        match self.pattern_type {
-            PatternType::Stripe => {
-                if point.x.floor() % 2.0 == 0.0 {
+        match &self.pattern_type {
+            PatternType::Stripe(axis) => {
+                if axis.of(point).floor() % 2.0 == 0.0 {
                     self.a
                 } else {
                     self.b
@@ -95,22 +211,108 @@ impl Pattern {
                 }
             }
             PatternType::Checker => {
-                let distance = (point.x.floor() + point.y.floor() + point.z.floor()) % 2.0;
+                //nudge before flooring so points on a cell boundary (including -0.0 and
+                //tiny negatives from float error) land in the same cell
+                let epsilon: f64 = 0.00001;
+                let distance = ((point.x + epsilon).floor()
+                    + (point.y + epsilon).floor()
+                    + (point.z + epsilon).floor())
+                .rem_euclid(2.0);
                 if distance == 0.0 {
                     self.a
                 } else {
                     self.b
                 }
             }
+            PatternType::RingGradient => {
+                let distance = self.b - self.a;
+                let radius = (point.x.powf(2.0) + point.z.powf(2.0)).sqrt();
+                let fraction = radius - radius.floor();
+
+                self.a + distance * fraction
+            }
+            PatternType::Grid(line_width) => {
+                if (point.x - point.x.round()).abs() <= *line_width
+                    || (point.z - point.z.round()).abs() <= *line_width
+                {
+                    self.a
+                } else {
+                    self.b
+                }
+            }
+            PatternType::Marble(perlin) => {
+                let turbulence = perlin.turbulence(point, 5);
+                let fraction = ((point.x + 4.0 * turbulence) * PI).sin() * 0.5 + 0.5;
+
+                self.a + (self.b - self.a) * fraction
+            }
+            PatternType::Wood(perlin) => {
+                let radius =
+                    (point.x.powf(2.0) + point.z.powf(2.0)).sqrt() + 0.4 * perlin.noise(point);
+                let fraction = (radius * 2.0 * PI).sin() * 0.5 + 0.5;
+
+                self.a + (self.b - self.a) * fraction
+            }
+            PatternType::SphericalTexture(_) => {
+                let (u, v) = Self::spherical_map(point);
+                self.pattern_at_uv(u, v)
+            }
+            //without a normal to look up there is nothing better than the image center
+            PatternType::Matcap(_) => self.pattern_at_normal(RayTuple::vector(0.0, 0.0, 1.0)),
         }
     }
 
-    pub fn pattern_at_shape(&self, object: Shape, world_point: RayTuple) -> Color {
+    pub fn pattern_at_shape(&self, object: &Shape, world_point: RayTuple) -> Color {
         let object_point = object.transform.inverse().unwrap() * world_point;
         let pattern_point = self.transform.inverse().unwrap() * object_point;
 
         self.pattern_at(pattern_point)
     }
+
+    //Same as pattern_at_shape, but normal driven patterns (matcap) sample using
+    //the view space normal instead of the surface point
+    pub fn pattern_at_shape_normal(
+        &self,
+        object: &Shape,
+        world_point: RayTuple,
+        view_normal: RayTuple,
+    ) -> Color {
+        match self.pattern_type {
+            PatternType::Matcap(_) => self.pattern_at_normal(view_normal),
+            _ => self.pattern_at_shape(object, world_point),
+        }
+    }
+
+    //Maps the view space normal's x and y from [-1,1] onto the image, with +y at the top row
+    pub fn pattern_at_normal(&self, view_normal: RayTuple) -> Color {
+        match &self.pattern_type {
+            PatternType::Matcap(image) => {
+                let width = image.get_width();
+                let height = image.get_height();
+                let u = (view_normal.x + 1.0) / 2.0;
+                let v = (view_normal.y + 1.0) / 2.0;
+
+                let px = ((u * width as f64).floor() as i32).clamp(0, width - 1);
+                let py = (((1.0 - v) * height as f64).floor() as i32).clamp(0, height - 1);
+
+                image.pixel_at(px, py)
+            }
+            _ => self.a,
+        }
+    }
+
+    //Expresses a world normal in view space: x to the viewer's right, y up, z toward the viewer
+    pub fn view_space_normal(normalv: RayTuple, eyev: RayTuple) -> RayTuple {
+        let forward = -eyev;
+        let mut up = RayTuple::vector(0.0, 1.0, 0.0);
+        if forward.cross(up).magnitude() < 0.00001 {
+            up = RayTuple::vector(0.0, 0.0, 1.0);
+        }
+        let right = up.cross(forward).normalize();
+        let true_up = forward.cross(right);
+
+        RayTuple::vector(normalv.dot(right), normalv.dot(true_up), normalv.dot(eyev))
+    }
 }
 
 pub fn chapter_ten_patterns() {
@@ -181,6 +383,7 @@ mod tests {
     use crate::color::Color;
     use crate::matrix::Matrix;
     use crate::shape::Shape;
+    use std::f64::consts::FRAC_1_SQRT_2;
 
     #[test]
     fn create_striped_pattern() {
@@ -237,7 +440,7 @@ mod tests {
         let black = Color::new(0.0, 0.0, 0.0);
 
         let p = Pattern::stripe_pattern(white, black);
-        let c = p.pattern_at_shape(object, RayTuple::point(1.5, 0.0, 0.0));
+        let c = p.pattern_at_shape(&object, RayTuple::point(1.5, 0.0, 0.0));
         assert_eq!(c, white);
     }
 
@@ -250,7 +453,7 @@ mod tests {
 
         let mut p = Pattern::stripe_pattern(white, black);
         p.transform = Matrix::scaling(2.0, 2.0, 2.0);
-        let c = p.pattern_at_shape(object, RayTuple::point(1.5, 0.0, 0.0));
+        let c = p.pattern_at_shape(&object, RayTuple::point(1.5, 0.0, 0.0));
         assert_eq!(c, white);
     }
 
@@ -264,7 +467,7 @@ mod tests {
 
         let mut p = Pattern::stripe_pattern(white, black);
         p.transform = Matrix::translation(0.5, 0.0, 0.0);
-        let c = p.pattern_at_shape(object, RayTuple::point(2.5, 0.0, 0.0));
+        let c = p.pattern_at_shape(&object, RayTuple::point(2.5, 0.0, 0.0));
         assert_eq!(c, white);
     }
 
@@ -286,7 +489,7 @@ mod tests {
         let mut shape = Shape::sphere();
         shape.transform = Matrix::scaling(2.0, 2.0, 2.0);
         let pattern = Pattern::test_pattern();
-        let c = pattern.pattern_at_shape(shape, RayTuple::point(2.0, 3.0, 4.0));
+        let c = pattern.pattern_at_shape(&shape, RayTuple::point(2.0, 3.0, 4.0));
         assert_eq!(c, Color::new(1.0, 1.5, 2.0));
     }
 
@@ -295,7 +498,7 @@ mod tests {
         let shape = Shape::sphere();
         let mut pattern = Pattern::test_pattern();
         pattern.transform = Matrix::scaling(2.0, 2.0, 2.0);
-        let c = pattern.pattern_at_shape(shape, RayTuple::point(2.0, 3.0, 4.0));
+        let c = pattern.pattern_at_shape(&shape, RayTuple::point(2.0, 3.0, 4.0));
         assert_eq!(c, Color::new(1.0, 1.5, 2.0));
     }
 
@@ -305,7 +508,7 @@ mod tests {
         shape.transform = Matrix::scaling(2.0, 2.0, 2.0);
         let mut pattern = Pattern::test_pattern();
         pattern.transform = Matrix::translation(0.5, 1.0, 1.5);
-        let c = pattern.pattern_at_shape(shape, RayTuple::point(2.5, 3.0, 3.5));
+        let c = pattern.pattern_at_shape(&shape, RayTuple::point(2.5, 3.0, 3.5));
         assert_eq!(c, Color::new(0.75, 0.5, 0.25));
     }
 
@@ -382,4 +585,196 @@ mod tests {
         assert_eq!(pattern.pattern_at(RayTuple::point(0.0, 0.0, 0.99)), white);
         assert_eq!(pattern.pattern_at(RayTuple::point(0.0, 0.0, 1.01)), black);
     }
+
+    #[test]
+    fn matcap_samples_image_by_view_normal() {
+        let mut image = Canvas::new(3, 3);
+        for y in 0..3 {
+            for x in 0..3 {
+                image.write_pixel(x, y, Color::new(x as f64 / 2.0, y as f64 / 2.0, 0.0));
+            }
+        }
+        let pattern = Pattern::matcap_pattern(image);
+
+        assert_eq!(
+            pattern.pattern_at_normal(RayTuple::vector(0.0, 0.0, 1.0)),
+            Color::new(0.5, 0.5, 0.0)
+        );
+        assert_eq!(
+            pattern.pattern_at_normal(RayTuple::vector(FRAC_1_SQRT_2, FRAC_1_SQRT_2, 0.0)),
+            Color::new(1.0, 0.0, 0.0)
+        );
+        assert_eq!(
+            pattern.pattern_at_normal(RayTuple::vector(-1.0, -1.0, 0.0)),
+            Color::new(0.0, 1.0, 0.0)
+        );
+    }
+
+    #[test]
+    fn view_space_normal_facing_viewer() {
+        let eyev = RayTuple::vector(0.0, 0.0, -1.0);
+
+        assert_eq!(
+            Pattern::view_space_normal(RayTuple::vector(0.0, 0.0, -1.0), eyev),
+            RayTuple::vector(0.0, 0.0, 1.0)
+        );
+        assert_eq!(
+            Pattern::view_space_normal(RayTuple::vector(0.0, 1.0, 0.0), eyev),
+            RayTuple::vector(0.0, 1.0, 0.0)
+        );
+    }
+
+    #[test]
+    fn checkers_ignore_negative_zero_on_boundaries() {
+        let white = Color::new(1.0, 1.0, 1.0);
+        let black = Color::new(0.0, 0.0, 0.0);
+
+        let pattern = Pattern::checkers_pattern(white, black);
+
+        assert_eq!(
+            pattern.pattern_at(RayTuple::point(0.0, -0.0, 2.0)),
+            pattern.pattern_at(RayTuple::point(0.0, 0.0, 2.0))
+        );
+        assert_eq!(
+            pattern.pattern_at(RayTuple::point(0.5, -0.0000000001, 0.5)),
+            white
+        );
+        assert_eq!(pattern.pattern_at(RayTuple::point(-0.5, 0.0, 0.5)), black);
+    }
+
+    #[test]
+    fn ring_gradient_interpolates_by_radius() {
+        let white = Color::new(1.0, 1.0, 1.0);
+        let black = Color::new(0.0, 0.0, 0.0);
+
+        let pattern = Pattern::ring_gradient(white, black);
+
+        assert_eq!(pattern.pattern_at(RayTuple::point(0.0, 0.0, 0.0)), white);
+        assert_eq!(
+            pattern.pattern_at(RayTuple::point(0.3, 0.0, 0.4)),
+            Color::new(0.5, 0.5, 0.5)
+        );
+        let near_edge = pattern.pattern_at(RayTuple::point(0.0, 0.0, 0.99999));
+        assert!(near_edge.red < 0.0001);
+    }
+
+    #[test]
+    fn spherical_texture_is_continuous_across_the_seam() {
+        let mut image = Canvas::new(4, 2);
+        for y in 0..2 {
+            image.write_pixel(0, y, Color::new(1.0, 0.0, 0.0));
+            image.write_pixel(1, y, Color::new(0.0, 1.0, 0.0));
+            image.write_pixel(2, y, Color::new(0.0, 0.0, 1.0));
+            image.write_pixel(3, y, Color::new(1.0, 1.0, 0.0));
+        }
+        let pattern = Pattern::spherical_texture(image);
+
+        let below_one = pattern.pattern_at_uv(0.9999, 0.5);
+        let above_zero = pattern.pattern_at_uv(0.0001, 0.5);
+
+        assert!((below_one.red - above_zero.red).abs() < 0.01);
+        assert!((below_one.green - above_zero.green).abs() < 0.01);
+        assert!((below_one.blue - above_zero.blue).abs() < 0.01);
+    }
+
+    #[test]
+    fn spherical_map_on_unit_sphere() {
+        assert_eq!(
+            Pattern::spherical_map(RayTuple::point(0.0, 0.0, -1.0)),
+            (0.0, 0.5)
+        );
+        assert_eq!(
+            Pattern::spherical_map(RayTuple::point(1.0, 0.0, 0.0)),
+            (0.25, 0.5)
+        );
+        assert_eq!(
+            Pattern::spherical_map(RayTuple::point(0.0, 1.0, 0.0)),
+            (0.5, 1.0)
+        );
+    }
+
+    #[test]
+    fn spherical_map_at_origin_is_finite() {
+        assert_eq!(
+            Pattern::spherical_map(RayTuple::point(0.0, 0.0, 0.0)),
+            (0.5, 0.5)
+        );
+    }
+
+    #[test]
+    fn noise_patterns_are_deterministic_bounded_and_smooth() {
+        let a = Color::new(1.0, 1.0, 1.0);
+        let b = Color::new(0.2, 0.1, 0.0);
+        let patterns = [
+            (Pattern::marble(a, b), Pattern::marble(a, b)),
+            (Pattern::wood(a, b), Pattern::wood(a, b)),
+        ];
+
+        for (first, second) in patterns {
+            for i in 0..200 {
+                let f = i as f64 * 0.037;
+                let p = RayTuple::point(f, 0.5 - f * 0.3, f * 0.7 - 1.0);
+                let c = first.pattern_at(p);
+                assert_eq!(c, second.pattern_at(p));
+
+                //every channel stays between the two endpoint colors
+                assert!(c.red >= b.red - 0.00001 && c.red <= a.red + 0.00001);
+                assert!(c.green >= b.green - 0.00001 && c.green <= a.green + 0.00001);
+                assert!(c.blue >= b.blue - 0.00001 && c.blue <= a.blue + 0.00001);
+
+                let near = first.pattern_at(p + RayTuple::vector(0.0001, 0.0, 0.0));
+                assert!((near.red - c.red).abs() < 0.01);
+            }
+        }
+    }
+
+    #[test]
+    fn grid_draws_lines_at_integer_boundaries() {
+        let line = Color::new(0.0, 0.0, 0.0);
+        let background = Color::new(1.0, 1.0, 1.0);
+        let pattern = Pattern::grid(line, background, 0.05);
+
+        assert_eq!(pattern.pattern_at(RayTuple::point(1.02, 0.0, 0.5)), line);
+        assert_eq!(pattern.pattern_at(RayTuple::point(0.5, 0.0, -2.97)), line);
+        assert_eq!(pattern.pattern_at(RayTuple::point(-0.96, 3.0, 0.5)), line);
+        assert_eq!(
+            pattern.pattern_at(RayTuple::point(0.5, 0.0, 0.5)),
+            background
+        );
+        assert_eq!(
+            pattern.pattern_at(RayTuple::point(1.2, 7.0, -0.3)),
+            background
+        );
+    }
+
+    #[test]
+    fn y_axis_stripe_alternates_along_y() {
+        let white = Color::new(1.0, 1.0, 1.0);
+        let black = Color::new(0.0, 0.0, 0.0);
+
+        let p = Pattern::stripe_axis(white, black, Axis::Y);
+        assert_eq!(p.pattern_at(RayTuple::point(0.0, 0.0, 0.0)), white);
+        assert_eq!(p.pattern_at(RayTuple::point(1.0, 0.0, 0.0)), white);
+        assert_eq!(p.pattern_at(RayTuple::point(2.0, 0.0, 0.0)), white);
+        assert_eq!(p.pattern_at(RayTuple::point(0.0, 0.0, 1.0)), white);
+        assert_eq!(p.pattern_at(RayTuple::point(0.0, 0.0, 2.0)), white);
+
+        assert_eq!(p.pattern_at(RayTuple::point(0.0, 0.9, 0.0)), white);
+        assert_eq!(p.pattern_at(RayTuple::point(0.0, 1.0, 0.0)), black);
+        assert_eq!(p.pattern_at(RayTuple::point(0.0, -0.1, 0.0)), black);
+        assert_eq!(p.pattern_at(RayTuple::point(0.0, -1.0, 0.0)), black);
+        assert_eq!(p.pattern_at(RayTuple::point(0.0, -1.1, 0.0)), white);
+    }
+
+    #[test]
+    fn z_axis_stripe_alternates_along_z() {
+        let white = Color::new(1.0, 1.0, 1.0);
+        let black = Color::new(0.0, 0.0, 0.0);
+
+        let p = Pattern::stripe_axis(white, black, Axis::Z);
+        assert_eq!(p.pattern_at(RayTuple::point(1.0, 1.0, 0.0)), white);
+        assert_eq!(p.pattern_at(RayTuple::point(0.0, 0.0, 1.0)), black);
+        assert_eq!(p.pattern_at(RayTuple::point(5.0, -3.0, 1.5)), black);
+        assert_eq!(p.pattern_at(RayTuple::point(0.0, 0.0, -1.1)), white);
+    }
 }
@@ -12,11 +12,27 @@ use crate::shape::{Shape, ShapeType};
 pub struct Ray {
     pub origin: RayTuple,
     pub direction: RayTuple,
+    //moment within the shutter the ray samples, moving shapes are placed for it
+    pub time: f64,
 }
 
 impl Ray {
     pub fn new(origin: RayTuple, direction: RayTuple) -> Self {
-        Self { origin, direction }
+        Self {
+            origin,
+            direction,
+            time: 0.0,
+        }
+    }
+
+    pub fn with_time(mut self, time: f64) -> Self {
+        self.time = time;
+        self
+    }
+
+    //Ray starting at `from` with a unit direction pointing at `to`
+    pub fn from_points(from: RayTuple, to: RayTuple) -> Self {
+        Self::new(from, (to - from).normalize())
     }
 
     pub fn position(self, t: f64) -> RayTuple {
@@ -27,70 +43,16 @@ impl Ray {
         Self {
             origin: m * self.origin,
             direction: m * self.direction,
+            time: self.time,
         }
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    #[test]
-    fn create_and_query_ray() {
-        let r = Ray::new(
-            RayTuple::point(1.0, 2.0, 3.0),
-            RayTuple::vector(4.0, 5.0, 6.0),
-        );
-
-        assert_eq!(r.origin, RayTuple::point(1.0, 2.0, 3.0));
-        assert_eq!(r.direction, RayTuple::vector(4.0, 5.0, 6.0));
-    }
-
-    #[test]
-    fn compute_point_from_distance() {
-        let r = Ray::new(
-            RayTuple::point(2.0, 3.0, 4.0),
-            RayTuple::vector(1.0, 0.0, 0.0),
-        );
-
-        assert_eq!(r.position(0.0), RayTuple::point(2.0, 3.0, 4.0));
-        assert_eq!(r.position(1.0), RayTuple::point(3.0, 3.0, 4.0));
-        assert_eq!(r.position(-1.0), RayTuple::point(1.0, 3.0, 4.0));
-        assert_eq!(r.position(2.5), RayTuple::point(4.5, 3.0, 4.0));
-    }
-
-    #[test]
-    fn translate_ray() {
-        let r = Ray::new(
-            RayTuple::point(1.0, 2.0, 3.0),
-            RayTuple::vector(0.0, 1.0, 0.0),
-        );
-        let m = Matrix::translation(3.0, 4.0, 5.0);
-        let r2 = r.transform(m);
-
-        assert_eq!(r2.origin, RayTuple::point(4.0, 6.0, 8.0));
-        assert_eq!(r2.direction, RayTuple::vector(0.0, 1.0, 0.0));
-    }
-
-    #[test]
-    fn scale_ray() {
-        let r = Ray::new(
-            RayTuple::point(1.0, 2.0, 3.0),
-            RayTuple::vector(0.0, 1.0, 0.0),
-        );
-        let m = Matrix::scaling(2.0, 3.0, 4.0);
-        let r2 = r.transform(m);
-
-        assert_eq!(r2.origin, RayTuple::point(2.0, 6.0, 12.0));
-        assert_eq!(r2.direction, RayTuple::vector(0.0, 3.0, 0.0));
-    }
-}
-
 pub fn chapter_five_raysphere() {
     let canvas_pixels = 100;
     let mut canvas = Canvas::new(canvas_pixels, canvas_pixels);
     let color = Color::new(1.0, 0.0, 0.0);
-    let mut shape = Shape::new(ShapeType::Sphere);
+    let shape = Shape::new(ShapeType::Sphere);
 
     //test shape transforms NOTE: change let shape to a mutable Sphere for these.
     //shape.set_transform(Matrix::scaling(1.0, 0.5, 1.0));
@@ -168,7 +130,7 @@ pub fn chapter_six_lighting() {
                     let normal = inter.object.normal_at(point);
                     let eye = -r.direction;
                     let color = inter.object.material.lighting(
-                        inter.object,
+                        &inter.object,
                         &light,
                         point,
                         eye,
@@ -183,3 +145,69 @@ pub fn chapter_six_lighting() {
     }
     canvas.save_ppm("chapter6sphere.ppm");
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn create_and_query_ray() {
+        let r = Ray::new(
+            RayTuple::point(1.0, 2.0, 3.0),
+            RayTuple::vector(4.0, 5.0, 6.0),
+        );
+
+        assert_eq!(r.origin, RayTuple::point(1.0, 2.0, 3.0));
+        assert_eq!(r.direction, RayTuple::vector(4.0, 5.0, 6.0));
+    }
+
+    #[test]
+    fn ray_between_two_points() {
+        let r = Ray::from_points(
+            RayTuple::point(0.0, 0.0, 0.0),
+            RayTuple::point(0.0, 0.0, -5.0),
+        );
+
+        assert_eq!(r.origin, RayTuple::point(0.0, 0.0, 0.0));
+        assert_eq!(r.direction, RayTuple::vector(0.0, 0.0, -1.0));
+    }
+
+    #[test]
+    fn compute_point_from_distance() {
+        let r = Ray::new(
+            RayTuple::point(2.0, 3.0, 4.0),
+            RayTuple::vector(1.0, 0.0, 0.0),
+        );
+
+        assert_eq!(r.position(0.0), RayTuple::point(2.0, 3.0, 4.0));
+        assert_eq!(r.position(1.0), RayTuple::point(3.0, 3.0, 4.0));
+        assert_eq!(r.position(-1.0), RayTuple::point(1.0, 3.0, 4.0));
+        assert_eq!(r.position(2.5), RayTuple::point(4.5, 3.0, 4.0));
+    }
+
+    #[test]
+    fn translate_ray() {
+        let r = Ray::new(
+            RayTuple::point(1.0, 2.0, 3.0),
+            RayTuple::vector(0.0, 1.0, 0.0),
+        );
+        let m = Matrix::translation(3.0, 4.0, 5.0);
+        let r2 = r.transform(m);
+
+        assert_eq!(r2.origin, RayTuple::point(4.0, 6.0, 8.0));
+        assert_eq!(r2.direction, RayTuple::vector(0.0, 1.0, 0.0));
+    }
+
+    #[test]
+    fn scale_ray() {
+        let r = Ray::new(
+            RayTuple::point(1.0, 2.0, 3.0),
+            RayTuple::vector(0.0, 1.0, 0.0),
+        );
+        let m = Matrix::scaling(2.0, 3.0, 4.0);
+        let r2 = r.transform(m);
+
+        assert_eq!(r2.origin, RayTuple::point(2.0, 6.0, 12.0));
+        assert_eq!(r2.direction, RayTuple::vector(0.0, 3.0, 0.0));
+    }
+}
@@ -1,5 +1,7 @@
 #![allow(dead_code)]
 use core::panic;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
 use std::ops::{Add, Div, Mul, Neg, Sub};
 
 #[derive(Debug, Clone, Copy)]
@@ -12,12 +14,7 @@ pub struct RayTuple {
 
 impl RayTuple {
     pub fn new(x: f64, y: f64, z: f64, w: f64) -> Self {
-        Self {
-            x: x,
-            y: y,
-            z: z,
-            w: w,
-        }
+        Self { x, y, z, w }
     }
 
     pub fn zero() -> Self {
@@ -45,12 +42,25 @@ impl RayTuple {
         self.w == 0.0
     }
 
+    //Componentwise comparison with the given tolerance, w included
+    pub fn approx_eq(&self, other: Self, epsilon: f64) -> bool {
+        f64::abs(self.x - other.x) < epsilon
+            && f64::abs(self.y - other.y) < epsilon
+            && f64::abs(self.z - other.z) < epsilon
+            && f64::abs(self.w - other.w) < epsilon
+    }
+
     pub fn magnitude(&self) -> f64 {
         (self.x.powf(2.0) + self.y.powf(2.0) + self.z.powf(2.0) + self.w.powf(2.0)).sqrt()
     }
 
+    //A zero (or too small to divide by) tuple has no direction, so it normalizes to
+    //the zero tuple rather than NaNs. Callers that need a direction check for zero.
     pub fn normalize(&self) -> Self {
         let magnitude = self.magnitude();
+        if !magnitude.is_normal() {
+            return Self::zero();
+        }
 
         Self {
             x: self.x / magnitude,
@@ -80,15 +90,89 @@ impl RayTuple {
 
         self - normal * 2.0 * self.dot(normal)
     }
+
+    //Component of self parallel to other. A zero length other has no direction to
+    //project onto, so the projection is zero and the rejection is all of self.
+    pub fn project_onto(&self, other: Self) -> Self {
+        let length_squared = other.dot(other);
+        if !length_squared.is_normal() {
+            return Self::zero();
+        }
+        other * (self.dot(other) / length_squared)
+    }
+
+    //Component of self perpendicular to other
+    pub fn reject_from(&self, other: Self) -> Self {
+        *self - self.project_onto(other)
+    }
+
+    //Reads back the output of to_string: point(x, y, z), vector(x, y, z) or tuple(x, y, z, w)
+    pub fn parse(s: &str) -> Option<Self> {
+        let s = s.trim();
+        let open = s.find('(')?;
+        let inner = s[open + 1..].strip_suffix(')')?;
+        let values: Vec<f64> = inner
+            .split(',')
+            .map(|v| v.trim().parse::<f64>())
+            .collect::<Result<_, _>>()
+            .ok()?;
+
+        match (&s[..open], values.as_slice()) {
+            ("point", [x, y, z]) => Some(Self::point(*x, *y, *z)),
+            ("vector", [x, y, z]) => Some(Self::vector(*x, *y, *z)),
+            ("tuple", [x, y, z, w]) => Some(Self::new(*x, *y, *z, *w)),
+            _ => None,
+        }
+    }
+}
+
+//One of the three coordinate axes, for things that work along a chosen direction
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Axis {
+    X,
+    Y,
+    Z,
+}
+
+impl Axis {
+    pub fn of(self, point: RayTuple) -> f64 {
+        match self {
+            Axis::X => point.x,
+            Axis::Y => point.y,
+            Axis::Z => point.z,
+        }
+    }
+}
+
+impl fmt::Display for RayTuple {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_a_point() {
+            write!(f, "point({}, {}, {})", self.x, self.y, self.z)
+        } else if self.is_a_vector() {
+            write!(f, "vector({}, {}, {})", self.x, self.y, self.z)
+        } else {
+            write!(f, "tuple({}, {}, {}, {})", self.x, self.y, self.z, self.w)
+        }
+    }
+}
+
+//Scene files store tuples as plain [x, y, z, w] arrays
+impl Serialize for RayTuple {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        [self.x, self.y, self.z, self.w].serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for RayTuple {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let [x, y, z, w] = <[f64; 4]>::deserialize(deserializer)?;
+        Ok(RayTuple::new(x, y, z, w))
+    }
 }
 
 impl PartialEq for RayTuple {
     fn eq(&self, other: &Self) -> bool {
-        let epsilon: f64 = 0.00001;
-
-        f64::abs(self.x - other.x) < epsilon
-            && f64::abs(self.y - other.y) < epsilon
-            && f64::abs(self.z - other.z) < epsilon
+        self.approx_eq(*other, 0.00001)
     }
 }
 
@@ -157,6 +241,47 @@ impl Div<f64> for RayTuple {
     }
 }
 
+//CHAPTER ONE Cannon Exercise
+//projectile has a position point and a velocity vector
+//environment has a gravity vector and a wind vector
+
+//the tick function accepts an environment and projectile, it updates the position based on the projectile\ts velocity,
+//and also applies the velocity vectors to the projectiles velocity each tick
+
+//the program initializes the projectile and velocity and runs the tick function until the projectile
+//hits the ground (y pos <= 0)
+//report the projectile's position after each tick
+//report the number of ticks run until the projectile hits the ground
+pub fn chapter_one_cannon() {
+    fn tick(p: (RayTuple, RayTuple), e: (RayTuple, RayTuple)) -> (RayTuple, RayTuple) {
+        let new_position = p.0 + p.1;
+        let new_velocity = p.1 + e.0 + e.1;
+        (new_position, new_velocity)
+    }
+
+    let mut p = (
+        RayTuple::point(0.0, 1.0, 0.0), //initial point, y=1, x/z = 0
+        RayTuple::vector(1.0, 1.0, 0.0).normalize(), //the starting velocity is x=1, y=1 with a magnitude of sqrt(2)
+    );
+
+    let e = (
+        RayTuple::vector(0.0, -0.1, 0.0),  //gravity
+        RayTuple::vector(-0.01, 0.0, 0.0), //wind
+    );
+
+    let mut tick_count = 1;
+
+    println!("t\tx\ty\tz");
+
+    while p.0.y > 0.0 {
+        println!("{tick_count}\t{}\t{}\t{}", p.0.x, p.0.y, p.0.z);
+        p = tick(p, e);
+        tick_count += 1;
+    }
+
+    println!("{tick_count}\t{}\t{}\t{}", p.0.x, p.0.y, p.0.z);
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -337,6 +462,21 @@ mod tests {
         assert_eq!(n.magnitude(), 1.0);
     }
 
+    #[test]
+    fn normalizing_zero_vector_gives_zero_vector() {
+        let n = RayTuple::vector(0.0, 0.0, 0.0).normalize();
+        assert!(n.x.is_finite() && n.y.is_finite() && n.z.is_finite() && n.w.is_finite());
+        assert_eq!(n, RayTuple::vector(0.0, 0.0, 0.0));
+
+        let tiny = RayTuple::vector(1e-320, 0.0, 0.0).normalize();
+        assert_eq!(tiny, RayTuple::vector(0.0, 0.0, 0.0));
+
+        let a = RayTuple::vector(4.0, 0.0, 0.0);
+        assert_eq!(a.normalize(), RayTuple::vector(1.0, 0.0, 0.0));
+        let b = RayTuple::vector(1.0, 2.0, 3.0);
+        assert_eq!(b.normalize(), b / b.magnitude());
+    }
+
     //Dot Product test
     #[test]
     fn dot_product() {
@@ -351,8 +491,8 @@ mod tests {
         let a = RayTuple::vector(1.0, 2.0, 3.0);
         let b = RayTuple::vector(2.0, 3.0, 4.0);
 
-        let x = a.clone();
-        let y = b.clone();
+        let x = a;
+        let y = b;
         assert_eq!(a.cross(b), RayTuple::vector(-1.0, 2.0, -1.0));
         assert_eq!(y.cross(x), RayTuple::vector(1.0, -2.0, 1.0));
     }
@@ -374,45 +514,80 @@ mod tests {
 
         assert_eq!(r, RayTuple::vector(1.0, 0.0, 0.0));
     }
-}
 
-//CHAPTER ONE Cannon Exercise
-//projectile has a position point and a velocity vector
-//environment has a gravity vector and a wind vector
+    #[test]
+    fn point_round_trips_through_string() {
+        let p = RayTuple::point(1.0, 2.0, 3.0);
+        assert_eq!(p.to_string(), "point(1, 2, 3)");
 
-//the tick function accepts an environment and projectile, it updates the position based on the projectile\ts velocity,
-//and also applies the velocity vectors to the projectiles velocity each tick
+        let parsed = RayTuple::parse(&p.to_string()).unwrap();
+        assert_eq!(parsed, p);
+        assert!(parsed.is_a_point());
+    }
 
-//the program initializes the projectile and velocity and runs the tick function until the projectile
-//hits the ground (y pos <= 0)
-//report the projectile's position after each tick
-//report the number of ticks run until the projectile hits the ground
-pub fn chapter_one_cannon() {
-    fn tick(p: (RayTuple, RayTuple), e: (RayTuple, RayTuple)) -> (RayTuple, RayTuple) {
-        let new_position = p.0 + p.1;
-        let new_velocity = p.1 + e.0 + e.1;
-        (new_position, new_velocity)
+    #[test]
+    fn vector_round_trips_through_string() {
+        let v = RayTuple::vector(-0.5, 0.0, 1.25);
+        let parsed = RayTuple::parse(&v.to_string()).unwrap();
+
+        assert_eq!(parsed, v);
+        assert!(parsed.is_a_vector());
+        assert_eq!(RayTuple::parse("point(1, 2)"), None);
     }
 
-    let mut p = (
-        RayTuple::point(0.0, 1.0, 0.0), //initial point, y=1, x/z = 0
-        RayTuple::vector(1.0, 1.0, 0.0).normalize(), //the starting velocity is x=1, y=1 with a magnitude of sqrt(2)
-    );
+    #[test]
+    fn project_and_reject_decompose_a_vector() {
+        let v = RayTuple::vector(2.0, 2.0, 0.0);
+        let axis = RayTuple::vector(1.0, 0.0, 0.0);
 
-    let e = (
-        RayTuple::vector(0.0, -0.1, 0.0),  //gravity
-        RayTuple::vector(-0.01, 0.0, 0.0), //wind
-    );
+        let parallel = v.project_onto(axis);
+        let perpendicular = v.reject_from(axis);
 
-    let mut tick_count = 1;
+        assert_eq!(parallel, RayTuple::vector(2.0, 0.0, 0.0));
+        assert_eq!(perpendicular, RayTuple::vector(0.0, 2.0, 0.0));
+        assert_eq!(parallel + perpendicular, v);
+    }
 
-    println!("t\tx\ty\tz");
+    #[test]
+    fn projecting_onto_zero_vector_gives_zero() {
+        let v = RayTuple::vector(2.0, 2.0, 0.0);
 
-    while p.0.y > 0.0 {
-        println!("{tick_count}\t{}\t{}\t{}", p.0.x, p.0.y, p.0.z);
-        p = tick(p, e);
-        tick_count += 1;
+        assert_eq!(v.project_onto(RayTuple::zero()), RayTuple::zero());
+        assert_eq!(v.reject_from(RayTuple::zero()), v);
     }
 
-    println!("{tick_count}\t{}\t{}\t{}", p.0.x, p.0.y, p.0.z);
+    #[test]
+    fn approx_eq_with_custom_epsilon() {
+        let a = RayTuple::vector(1.0, 2.0, 3.0);
+        let b = RayTuple::vector(1.0004, 2.0, 2.9997);
+
+        assert_ne!(a, b);
+        assert!(a.approx_eq(b, 0.001));
+        assert!(!a.approx_eq(b, 0.0001));
+        assert!(!a.approx_eq(RayTuple::point(1.0, 2.0, 3.0), 0.001));
+    }
+
+    #[test]
+    fn point_round_trips_through_serde() {
+        let p = RayTuple::point(1.5, -2.0, 3.0);
+        let json = serde_json::to_string(&p).unwrap();
+        assert_eq!(json, "[1.5,-2.0,3.0,1.0]");
+
+        let back: RayTuple = serde_json::from_str(&json).unwrap();
+        assert_eq!(back, p);
+        assert!(back.is_a_point());
+        assert!(serde_json::from_str::<RayTuple>("[1.0,2.0,3.0]").is_err());
+    }
+
+    #[test]
+    fn display_labels_points_and_vectors() {
+        assert_eq!(
+            RayTuple::new(1.0, 2.0, 3.0, 1.0).to_string(),
+            "point(1, 2, 3)"
+        );
+        assert_eq!(
+            RayTuple::new(1.0, 2.0, 3.0, 0.0).to_string(),
+            "vector(1, 2, 3)"
+        );
+    }
 }
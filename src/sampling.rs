@@ -0,0 +1,79 @@
+#![allow(dead_code)]
+
+use crate::raytuple::RayTuple;
+use crate::rng::Rng;
+use std::f64::consts::PI;
+
+//Two unit vectors perpendicular to the normal and to each other
+pub fn orthonormal_basis(normal: RayTuple) -> (RayTuple, RayTuple) {
+    let helper = if normal.x.abs() < 0.9 {
+        RayTuple::vector(1.0, 0.0, 0.0)
+    } else {
+        RayTuple::vector(0.0, 0.0, 1.0)
+    };
+    let tangent = helper.cross(normal).normalize();
+    let bitangent = normal.cross(tangent);
+    (tangent, bitangent)
+}
+
+//Unit direction around the normal, more likely near the normal in proportion to cos(theta)
+pub fn cosine_weighted_hemisphere(normal: RayTuple, rng: &mut Rng) -> RayTuple {
+    let r = rng.next_f64().sqrt();
+    let phi = 2.0 * PI * rng.next_f64();
+    let up = (1.0 - r * r).max(0.0).sqrt();
+
+    from_local(normal, r * phi.cos(), r * phi.sin(), up)
+}
+
+//Unit direction spread evenly over the hemisphere around the normal
+pub fn uniform_hemisphere(normal: RayTuple, rng: &mut Rng) -> RayTuple {
+    let up = rng.next_f64();
+    let r = (1.0 - up * up).max(0.0).sqrt();
+    let phi = 2.0 * PI * rng.next_f64();
+
+    from_local(normal, r * phi.cos(), r * phi.sin(), up)
+}
+
+fn from_local(normal: RayTuple, x: f64, y: f64, up: f64) -> RayTuple {
+    let (tangent, bitangent) = orthonormal_basis(normal);
+    (tangent * x + bitangent * y + normal * up).normalize()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hemisphere_samples_are_unit_and_face_the_normal() {
+        let normal = RayTuple::vector(1.0, 2.0, -0.5).normalize();
+        let mut rng = Rng::new(5);
+
+        for _ in 0..500 {
+            for sample in [
+                cosine_weighted_hemisphere(normal, &mut rng),
+                uniform_hemisphere(normal, &mut rng),
+            ] {
+                assert!(sample.dot(normal) >= 0.0);
+                assert!((sample.magnitude() - 1.0).abs() < 0.00001);
+            }
+        }
+    }
+
+    #[test]
+    fn fixed_seed_repeats_samples() {
+        let normal = RayTuple::vector(0.0, 1.0, 0.0);
+        let mut a = Rng::new(9);
+        let mut b = Rng::new(9);
+
+        for _ in 0..10 {
+            assert_eq!(
+                cosine_weighted_hemisphere(normal, &mut a),
+                cosine_weighted_hemisphere(normal, &mut b)
+            );
+            assert_eq!(
+                uniform_hemisphere(normal, &mut a),
+                uniform_hemisphere(normal, &mut b)
+            );
+        }
+    }
+}
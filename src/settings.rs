@@ -0,0 +1,144 @@
+#![allow(dead_code)]
+
+use crate::camera::Camera;
+use crate::canvas::{Canvas, SaveFormat};
+use crate::world::World;
+use serde::{Deserialize, Serialize};
+use std::io;
+
+//Everything about how a scene was rendered, so a scene file can reproduce it exactly
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RenderSettings {
+    pub width: i32,
+    pub height: i32,
+    pub field_of_view: f64,
+    //antialiasing rays per pixel
+    pub samples: usize,
+    pub max_reflections: i32,
+    pub max_refractions: i32,
+    //workers the rows are split across, 0 uses every core
+    pub threads: usize,
+    //file the image is saved to, the extension picks the format
+    pub output: String,
+}
+
+impl RenderSettings {
+    //Camera with the settings' resolution, field of view and antialiasing, its transform
+    //still the identity. Rays start with the larger depth and configure caps each kind.
+    pub fn camera(&self) -> Camera {
+        let mut camera = Camera::new(self.width, self.height, self.field_of_view);
+        camera.samples = self.samples;
+        camera.max_depth = self.max_reflections.max(self.max_refractions);
+        camera
+    }
+
+    pub fn configure(&self, w: &mut World) {
+        w.max_reflection_depth = self.max_reflections;
+        w.max_refraction_depth = self.max_refractions;
+    }
+
+    //Renders through a camera from self.camera() once its view is set up
+    pub fn render(&self, camera: Camera, mut w: World) -> Canvas {
+        self.configure(&mut w);
+        camera.render_parallel(w, self.threads)
+    }
+
+    pub fn render_and_save(&self, camera: Camera, w: World) -> io::Result<SaveFormat> {
+        self.render(camera, w).save(&self.output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matrix::Matrix;
+    use crate::raytuple::RayTuple;
+    use crate::shape::Shape;
+    use std::f64::consts::FRAC_PI_3;
+
+    fn settings() -> RenderSettings {
+        RenderSettings {
+            width: 320,
+            height: 200,
+            field_of_view: FRAC_PI_3,
+            samples: 16,
+            max_reflections: 3,
+            max_refractions: 7,
+            threads: 4,
+            output: String::from("out.ppm"),
+        }
+    }
+
+    #[test]
+    fn settings_configure_camera_and_world() {
+        let s = settings();
+        let c = s.camera();
+        assert_eq!(c.hsize(), 320);
+        assert_eq!(c.vsize(), 200);
+        assert_eq!(c.field_of_view(), FRAC_PI_3);
+        assert_eq!(c.samples, 16);
+        assert_eq!(c.max_depth, 7);
+
+        let mut w = World::default_world();
+        s.configure(&mut w);
+        assert_eq!(w.max_reflection_depth, 3);
+        assert_eq!(w.max_refraction_depth, 7);
+    }
+
+    #[test]
+    fn settings_round_trip_through_json() {
+        let s = settings();
+        let json = serde_json::to_string(&s).unwrap();
+        let back: RenderSettings = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(back, s);
+    }
+
+    #[test]
+    fn settings_render_with_their_depth_threads_and_output() {
+        //a corridor of facing mirrors, every extra bounce adds light
+        let mut w = World::new();
+        w.lights[0].set_position(RayTuple::point(0.0, 0.0, 0.0));
+        for y in [-1.0, 1.0] {
+            let mut mirror = Shape::plane();
+            mirror.material.reflective = 0.5;
+            mirror.transform = Matrix::translation(0.0, y, 0.0);
+            w.objects.push(mirror);
+        }
+        let mut s = RenderSettings {
+            width: 1,
+            height: 1,
+            samples: 1,
+            max_reflections: 7,
+            max_refractions: 2,
+            threads: 2,
+            ..settings()
+        };
+        let camera = |s: &RenderSettings| {
+            let mut c = s.camera();
+            c.transform = Matrix::view_transform(
+                RayTuple::point(0.0, 0.0, 0.0),
+                RayTuple::point(0.0, 1.0, 1.0),
+                RayTuple::vector(0.0, 1.0, 0.0),
+            );
+            c
+        };
+
+        let mut configured = w.clone();
+        s.configure(&mut configured);
+        let ray = camera(&s).ray_for_pixel(0, 0);
+        let image = s.render(camera(&s), w.clone());
+        assert_eq!(image.pixel_at(0, 0), configured.color_at(ray, 7));
+        assert_ne!(image.pixel_at(0, 0), configured.color_at(ray, 5));
+
+        s.output = std::env::temp_dir()
+            .join(format!("ray_settings_test_{}.ppm", std::process::id()))
+            .to_string_lossy()
+            .into_owned();
+        let format = s.render_and_save(camera(&s), w);
+        assert_eq!(format.unwrap(), SaveFormat::Ppm);
+        let saved = std::fs::read_to_string(&s.output).unwrap();
+        let _ = std::fs::remove_file(&s.output);
+        assert_eq!(saved, image.to_ppm());
+    }
+}
@@ -1,4 +1,5 @@
 #![allow(dead_code)]
+use crate::bounds::BoundingBox;
 use crate::camera::Camera;
 use crate::color::Color;
 use crate::intersection::Intersection;
@@ -8,10 +9,29 @@ use crate::pattern::Pattern;
 use crate::ray::Ray;
 use crate::raytuple::RayTuple;
 use crate::world::World;
-use std::f64::consts::FRAC_PI_3;
+use std::borrow::Cow;
+use std::f64::consts::{FRAC_PI_3, PI};
+use std::fmt;
+use std::sync::Arc;
 use uuid::Uuid;
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+//Scalar field for implicit surfaces, shared so cloning a shape stays cheap
+#[derive(Clone)]
+pub struct ImplicitFunction(pub Arc<dyn Fn(RayTuple) -> f64 + Send + Sync>);
+
+impl fmt::Debug for ImplicitFunction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "ImplicitFunction")
+    }
+}
+
+impl PartialEq for ImplicitFunction {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum ShapeType {
     Sphere,
     Plane,
@@ -19,66 +39,160 @@ pub enum ShapeType {
     Cube,
     Cylinder,
     Cone,
+    Group,
+    //the surface is where function(point) == iso, with the inside above iso
+    Implicit {
+        function: ImplicitFunction,
+        iso: f64,
+    },
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CubeFace {
+    PosX,
+    NegX,
+    PosY,
+    NegY,
+    PosZ,
+    NegZ,
+}
+
+#[derive(Debug, Clone)]
 pub struct Shape {
     id: Uuid,
     shape_type: ShapeType,
     pub transform: Matrix,
     pub material: Material,
-    pub saved_ray: Ray,
     pub minimum: f64,
     pub maximum: f64,
     pub closed: bool,
+    pub parent: Option<usize>,
+    //flips normal_at so the surface faces inward, e.g. a cube used as a room
+    pub inverted: bool,
+    //distance moved per unit of ray time, for motion blur
+    pub velocity: RayTuple,
+    normal_cache: Option<NormalCache>,
+    ops: &'static ShapeOps,
+}
+
+//Inverse and inverse transpose of the transform they were built from, so static
+//shapes don't redo that work for every normal
+#[derive(Debug, Clone, Copy)]
+struct NormalCache {
+    transform: Matrix,
+    inverse: Matrix,
+    inverse_transpose: Matrix,
+}
+
+type LocalIntersect = fn(&Shape, Ray, &mut dyn FnMut(f64, Option<CubeFace>) -> bool);
+type LocalNormal = fn(&Shape, RayTuple) -> RayTuple;
+
+//The object space math for one shape type. Shapes point at their table from the
+//moment they're built, so intersect and normal_at make one indirect call instead
+//of matching on the type for every ray.
+#[derive(Debug)]
+struct ShapeOps {
+    intersect: LocalIntersect,
+    normal: LocalNormal,
+}
+
+static SPHERE_OPS: ShapeOps = ShapeOps {
+    intersect: Shape::sphere_intersect,
+    normal: Shape::sphere_normal,
+};
+static PLANE_OPS: ShapeOps = ShapeOps {
+    intersect: Shape::plane_intersect,
+    normal: Shape::plane_normal,
+};
+static TEST_OPS: ShapeOps = ShapeOps {
+    intersect: Shape::no_intersect,
+    normal: Shape::test_normal,
+};
+static CUBE_OPS: ShapeOps = ShapeOps {
+    intersect: Shape::cube_intersect,
+    normal: Shape::cube_normal,
+};
+static CYLINDER_OPS: ShapeOps = ShapeOps {
+    intersect: Shape::cylinder_intersect,
+    normal: Shape::cylinder_normal,
+};
+static CONE_OPS: ShapeOps = ShapeOps {
+    intersect: Shape::cone_intersect,
+    normal: Shape::cone_normal,
+};
+static GROUP_OPS: ShapeOps = ShapeOps {
+    intersect: Shape::no_intersect,
+    normal: Shape::group_normal,
+};
+static IMPLICIT_OPS: ShapeOps = ShapeOps {
+    intersect: Shape::implicit_intersect,
+    normal: Shape::implicit_normal,
+};
+
+impl ShapeOps {
+    fn for_type(shape_type: &ShapeType) -> &'static ShapeOps {
+        match shape_type {
+            ShapeType::Sphere => &SPHERE_OPS,
+            ShapeType::Plane => &PLANE_OPS,
+            ShapeType::Test => &TEST_OPS,
+            ShapeType::Cube => &CUBE_OPS,
+            ShapeType::Cylinder => &CYLINDER_OPS,
+            ShapeType::Cone => &CONE_OPS,
+            ShapeType::Group => &GROUP_OPS,
+            ShapeType::Implicit { .. } => &IMPLICIT_OPS,
+        }
+    }
 }
 
 impl Shape {
     pub fn new(shape_type: ShapeType) -> Self {
         Self {
             id: Uuid::new_v4(),
+            ops: ShapeOps::for_type(&shape_type),
             shape_type,
             transform: Matrix::identity(),
             material: Material::new(),
-            saved_ray: Ray::new(
-                RayTuple::point(0.0, 0.0, 0.0),
-                RayTuple::vector(0.0, 0.0, 0.0),
-            ),
             minimum: f64::NEG_INFINITY,
             maximum: f64::INFINITY,
             closed: false,
+            parent: None,
+            inverted: false,
+            velocity: RayTuple::vector(0.0, 0.0, 0.0),
+            normal_cache: None,
         }
     }
 
     pub fn test_shape() -> Self {
         Self {
             id: Uuid::new_v4(),
+            ops: &TEST_OPS,
             shape_type: ShapeType::Test,
             transform: Matrix::identity(),
             material: Material::new(),
-            saved_ray: Ray::new(
-                RayTuple::point(0.0, 0.0, 0.0),
-                RayTuple::vector(0.0, 0.0, 0.0),
-            ),
             minimum: f64::NEG_INFINITY,
             maximum: f64::INFINITY,
             closed: false,
+            parent: None,
+            inverted: false,
+            velocity: RayTuple::vector(0.0, 0.0, 0.0),
+            normal_cache: None,
         }
     }
 
     pub fn sphere() -> Self {
         Self {
             id: Uuid::new_v4(),
+            ops: &SPHERE_OPS,
             shape_type: ShapeType::Sphere,
             transform: Matrix::identity(),
             material: Material::new(),
-            saved_ray: Ray::new(
-                RayTuple::point(0.0, 0.0, 0.0),
-                RayTuple::vector(0.0, 0.0, 0.0),
-            ),
             minimum: f64::NEG_INFINITY,
             maximum: f64::INFINITY,
             closed: false,
+            parent: None,
+            inverted: false,
+            velocity: RayTuple::vector(0.0, 0.0, 0.0),
+            normal_cache: None,
         }
     }
 
@@ -93,304 +207,784 @@ impl Shape {
     pub fn plane() -> Self {
         Self {
             id: Uuid::new_v4(),
+            ops: &PLANE_OPS,
             shape_type: ShapeType::Plane,
             transform: Matrix::identity(),
             material: Material::new(),
-            saved_ray: Ray::new(
-                RayTuple::point(0.0, 0.0, 0.0),
-                RayTuple::vector(0.0, 0.0, 0.0),
-            ),
             minimum: f64::NEG_INFINITY,
             maximum: f64::INFINITY,
             closed: false,
+            parent: None,
+            inverted: false,
+            velocity: RayTuple::vector(0.0, 0.0, 0.0),
+            normal_cache: None,
         }
     }
 
+    //Builds the half-space boundary of all points p where p.dot(normal) == distance
+    //by rotating the default xz plane onto the normal and pushing it out along it
+    pub fn plane_from(normal: RayTuple, distance: f64) -> Self {
+        let n = normal.normalize();
+        let helper = if n.x.abs() < 0.9 {
+            RayTuple::vector(1.0, 0.0, 0.0)
+        } else {
+            RayTuple::vector(0.0, 0.0, 1.0)
+        };
+        let tangent = helper.cross(n).normalize();
+        let bitangent = n.cross(tangent);
+
+        let orientation = Matrix::new_matrix4([
+            [tangent.x, n.x, bitangent.x, 0.0],
+            [tangent.y, n.y, bitangent.y, 0.0],
+            [tangent.z, n.z, bitangent.z, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ]);
+
+        let mut p = Self::plane();
+        p.transform =
+            Matrix::translation(n.x * distance, n.y * distance, n.z * distance) * orientation;
+
+        p
+    }
+
+    //Walls across the x or z axis facing back toward the origin. Planes in any other
+    //orientation are built with plane_from or a transform.
+    pub fn wall_x(x: f64) -> Self {
+        Self::plane_from(RayTuple::vector(-x.signum(), 0.0, 0.0), -x.abs())
+    }
+
+    pub fn wall_z(z: f64) -> Self {
+        Self::plane_from(RayTuple::vector(0.0, 0.0, -z.signum()), -z.abs())
+    }
+
+    //Implicit surfaces are marched inside the unit cube, scale the transform to fit the field
+    pub fn implicit<F>(function: F, iso: f64) -> Self
+    where
+        F: Fn(RayTuple) -> f64 + Send + Sync + 'static,
+    {
+        Self::new(ShapeType::Implicit {
+            function: ImplicitFunction(Arc::new(function)),
+            iso,
+        })
+    }
+
+    //Sum of gaussian blobs, each given by its center and radius
+    pub fn metaballs(balls: Vec<(RayTuple, f64)>, iso: f64) -> Self {
+        Self::implicit(
+            move |p| {
+                balls
+                    .iter()
+                    .map(|(center, radius)| {
+                        let d = p - *center;
+                        (-d.dot(d) / radius.powf(2.0)).exp()
+                    })
+                    .sum()
+            },
+            iso,
+        )
+    }
+
     pub fn cube() -> Self {
         Self {
             id: Uuid::new_v4(),
+            ops: &CUBE_OPS,
             shape_type: ShapeType::Cube,
             transform: Matrix::identity(),
             material: Material::new(),
-            saved_ray: Ray::new(
-                RayTuple::point(0.0, 0.0, 0.0),
-                RayTuple::vector(0.0, 0.0, 0.0),
-            ),
             minimum: f64::NEG_INFINITY,
             maximum: f64::INFINITY,
             closed: false,
+            parent: None,
+            inverted: false,
+            velocity: RayTuple::vector(0.0, 0.0, 0.0),
+            normal_cache: None,
         }
     }
 
     pub fn cylinder() -> Self {
         Self {
             id: Uuid::new_v4(),
+            ops: &CYLINDER_OPS,
             shape_type: ShapeType::Cylinder,
             transform: Matrix::identity(),
             material: Material::new(),
-            saved_ray: Ray::new(
-                RayTuple::point(0.0, 0.0, 0.0),
-                RayTuple::vector(0.0, 0.0, 0.0),
-            ),
             minimum: f64::NEG_INFINITY,
             maximum: f64::INFINITY,
             closed: false,
+            parent: None,
+            inverted: false,
+            velocity: RayTuple::vector(0.0, 0.0, 0.0),
+            normal_cache: None,
         }
     }
 
     pub fn cone() -> Self {
         Self {
             id: Uuid::new_v4(),
+            ops: &CONE_OPS,
             shape_type: ShapeType::Cone,
             transform: Matrix::identity(),
             material: Material::new(),
-            saved_ray: Ray::new(
-                RayTuple::point(0.0, 0.0, 0.0),
-                RayTuple::vector(0.0, 0.0, 0.0),
-            ),
             minimum: f64::NEG_INFINITY,
             maximum: f64::INFINITY,
             closed: false,
+            parent: None,
+            inverted: false,
+            velocity: RayTuple::vector(0.0, 0.0, 0.0),
+            normal_cache: None,
         }
     }
 
-    pub fn id(self) -> Uuid {
+    //A group has no geometry of its own, it only carries a transform that its
+    //children (shapes whose parent is the group's index) inherit in world_to_object
+    //and normal_to_world. Intersection, normal_at and bounds use each shape's own
+    //transform, so a child's transform must already place it in the world.
+    pub fn group() -> Self {
+        Self::new(ShapeType::Group)
+    }
+
+    pub fn id(&self) -> Uuid {
         self.id
     }
 
-    pub fn intersect(&mut self, r: Ray) -> Vec<Intersection> {
-        let mut intersections: Vec<Intersection> = Vec::new();
-        let local_inverse_transform = self.transform.inverse();
-        if let None = local_inverse_transform {
-            return intersections;
-        }
-        self.saved_ray = r.transform(local_inverse_transform.unwrap());
+    pub fn material_mut(&mut self) -> &mut Material {
+        &mut self.material
+    }
 
-        match self.shape_type {
-            ShapeType::Sphere => {
-                let sphere_to_ray = self.saved_ray.origin - RayTuple::point(0.0, 0.0, 0.0);
-                let a = self.saved_ray.direction.dot(self.saved_ray.direction);
-                let b = 2.0 * self.saved_ray.direction.dot(sphere_to_ray);
-                let c = sphere_to_ray.dot(sphere_to_ray) - 1.0;
-
-                let discriminant = b.powf(2.0) - 4.0 * a * c;
-                if discriminant < 0.0 {
-                    return intersections;
-                }
+    pub fn set_color(&mut self, c: Color) {
+        self.material.color = c;
+    }
 
-                let t1 = (-b - discriminant.sqrt()) / (2.0 * a);
-                let t2 = (-b + discriminant.sqrt()) / (2.0 * a);
-                intersections.push(Intersection::new(t1, *self));
-                intersections.push(Intersection::new(t2, *self));
+    pub fn set_pattern(&mut self, p: Pattern) {
+        self.material.pattern = Some(p);
+    }
 
-                intersections
-            }
-            ShapeType::Plane => {
-                let epsilon: f64 = 0.00001;
-                if self.saved_ray.direction.y.abs() < epsilon {
-                    return intersections;
-                }
+    //Same as == but ignores the id, so separately built shapes can be compared
+    pub fn equivalent(&self, other: &Shape) -> bool {
+        self.shape_type == other.shape_type
+            && self.transform == other.transform
+            && self.material == other.material
+            && self.minimum == other.minimum
+            && self.maximum == other.maximum
+            && self.closed == other.closed
+            && self.inverted == other.inverted
+            && self.velocity == other.velocity
+            && self.parent == other.parent
+    }
 
-                let t = -self.saved_ray.origin.y / self.saved_ray.direction.y;
+    pub fn intersect(&self, r: Ray) -> Vec<Intersection<'_>> {
+        self.intersect_until(r, f64::INFINITY)
+    }
+
+    //Only the intersections at or before max_t
+    pub fn intersect_until(&self, r: Ray, max_t: f64) -> Vec<Intersection<'_>> {
+        let transform = self.transform_at(r.time);
+        let local_ray = match transform.inverse() {
+            Some(inverse) => r.transform(inverse),
+            None => return Vec::new(),
+        };
 
-                intersections.push(Intersection::new(t, *self));
-                intersections
+        let mut found = Vec::new();
+        self.local_intersect(local_ray, &mut |t, face| {
+            if t <= max_t {
+                found.push((t, face));
             }
-            ShapeType::Test => intersections,
-            ShapeType::Cube => {
-                let xaxis: (f64, f64) =
-                    Self::check_axis(self.saved_ray.origin.x, self.saved_ray.direction.x);
-                let yaxis: (f64, f64) =
-                    Self::check_axis(self.saved_ray.origin.y, self.saved_ray.direction.y);
-                let zaxis: (f64, f64) =
-                    Self::check_axis(self.saved_ray.origin.z, self.saved_ray.direction.z);
-
-                let tmin = if xaxis.0 > yaxis.0 {
-                    if xaxis.0 > zaxis.0 {
-                        xaxis.0
-                    } else {
-                        zaxis.0
-                    }
-                } else {
-                    if yaxis.0 > zaxis.0 {
-                        yaxis.0
-                    } else {
-                        zaxis.0
-                    }
-                };
-                let tmax = if xaxis.1 < yaxis.1 {
-                    if xaxis.1 < zaxis.1 {
-                        xaxis.1
-                    } else {
-                        zaxis.1
-                    }
-                } else {
-                    if yaxis.1 < zaxis.1 {
-                        yaxis.1
-                    } else {
-                        zaxis.1
-                    }
+            false
+        });
+
+        //hits borrow the shape, except that a moving one is copied to where it was at
+        //the ray's time so normals line up
+        let moved = (transform.m != self.transform.m).then(|| {
+            let mut object = self.clone();
+            object.transform = transform;
+            object
+        });
+        found
+            .into_iter()
+            .map(|(t, face)| {
+                let mut hit = match face {
+                    Some(face) => Intersection::with_face(t, self, face),
+                    None => Intersection::new(t, self),
                 };
-
-                if tmin > tmax {
-                    return intersections;
+                if let Some(object) = &moved {
+                    hit.object = Cow::Owned(object.clone());
                 }
+                hit
+            })
+            .collect()
+    }
+
+    //The world ray in this shape's object space, as intersect sees it. Panics on a
+    //transform that can't be inverted.
+    pub fn local_ray(&self, r: Ray) -> Ray {
+        self.try_local_ray(r).unwrap()
+    }
 
-                intersections.push(Intersection::new(tmin, *self));
-                intersections.push(Intersection::new(tmax, *self));
-                intersections
+    fn try_local_ray(&self, r: Ray) -> Option<Ray> {
+        self.transform_at(r.time)
+            .inverse()
+            .map(|inverse| r.transform(inverse))
+    }
+
+    //The transform with the shape moved along its velocity for the given time
+    pub fn transform_at(&self, time: f64) -> Matrix {
+        if time == 0.0 || self.velocity == RayTuple::vector(0.0, 0.0, 0.0) {
+            return self.transform;
+        }
+        let offset = self.velocity * time;
+        Matrix::translation(offset.x, offset.y, offset.z) * self.transform
+    }
+
+    //Occlusion test that stops at the first intersection inside (0, max_t)
+    //without building any Intersection values
+    pub fn is_hit(&self, r: Ray, max_t: f64) -> bool {
+        let local_ray = match self.try_local_ray(r) {
+            Some(local_ray) => local_ray,
+            None => return false,
+        };
+
+        let mut hit = false;
+        self.local_intersect(local_ray, &mut |t, _| {
+            hit = t > 0.0 && t < max_t;
+            hit
+        });
+        hit
+    }
+
+    //Reports every t where the object space ray crosses the surface, along with the
+    //cube face when there is one. Stops early once `found` returns true.
+    fn local_intersect(&self, ray: Ray, found: &mut dyn FnMut(f64, Option<CubeFace>) -> bool) {
+        (self.ops.intersect)(self, ray, found)
+    }
+
+    fn sphere_intersect(&self, ray: Ray, found: &mut dyn FnMut(f64, Option<CubeFace>) -> bool) {
+        let sphere_to_ray = ray.origin - RayTuple::point(0.0, 0.0, 0.0);
+        let a = ray.direction.dot(ray.direction);
+        let b = 2.0 * ray.direction.dot(sphere_to_ray);
+        let c = sphere_to_ray.dot(sphere_to_ray) - 1.0;
+
+        let discriminant = b.powf(2.0) - 4.0 * a * c;
+        if discriminant < 0.0 {
+            return;
+        }
+
+        let t1 = (-b - discriminant.sqrt()) / (2.0 * a);
+        let t2 = (-b + discriminant.sqrt()) / (2.0 * a);
+        if found(t1, None) {
+            return;
+        }
+        found(t2, None);
+    }
+
+    fn plane_intersect(&self, ray: Ray, found: &mut dyn FnMut(f64, Option<CubeFace>) -> bool) {
+        let epsilon: f64 = 0.00001;
+        if ray.direction.y.abs() < epsilon {
+            return;
+        }
+
+        let t = -ray.origin.y / ray.direction.y;
+
+        found(t, None);
+    }
+
+    fn cube_intersect(&self, ray: Ray, found: &mut dyn FnMut(f64, Option<CubeFace>) -> bool) {
+        let xaxis: (f64, f64) = Self::check_axis(ray.origin.x, ray.direction.x);
+        let yaxis: (f64, f64) = Self::check_axis(ray.origin.y, ray.direction.y);
+        let zaxis: (f64, f64) = Self::check_axis(ray.origin.z, ray.direction.z);
+
+        let tmin = if xaxis.0 > yaxis.0 {
+            if xaxis.0 > zaxis.0 {
+                xaxis.0
+            } else {
+                zaxis.0
             }
-            ShapeType::Cylinder => {
-                let epsilon: f64 = 0.00001;
-                let a = self.saved_ray.direction.x.powf(2.0) + self.saved_ray.direction.z.powf(2.0);
+        } else {
+            if yaxis.0 > zaxis.0 {
+                yaxis.0
+            } else {
+                zaxis.0
+            }
+        };
+        let tmax = if xaxis.1 < yaxis.1 {
+            if xaxis.1 < zaxis.1 {
+                xaxis.1
+            } else {
+                zaxis.1
+            }
+        } else {
+            if yaxis.1 < zaxis.1 {
+                yaxis.1
+            } else {
+                zaxis.1
+            }
+        };
 
-                if a <= epsilon {
-                    Self::intersect_caps(&self, self.saved_ray, &mut intersections);
-                    return intersections;
-                }
+        if tmin > tmax {
+            return;
+        }
 
-                let b = 2.0 * self.saved_ray.origin.x * self.saved_ray.direction.x
-                    + 2.0 * self.saved_ray.origin.z * self.saved_ray.direction.z;
-                let c = self.saved_ray.origin.x.powf(2.0) + self.saved_ray.origin.z.powf(2.0) - 1.0;
+        let entry_face = Self::cube_face(ray.position(tmin));
+        let exit_face = Self::cube_face(ray.position(tmax));
+        if found(tmin, Some(entry_face)) {
+            return;
+        }
+        found(tmax, Some(exit_face));
+    }
 
-                let disc = b.powf(2.0) - 4.0 * a * c;
+    fn cylinder_intersect(&self, ray: Ray, found: &mut dyn FnMut(f64, Option<CubeFace>) -> bool) {
+        let epsilon: f64 = 0.00001;
+        let a = ray.direction.x.powf(2.0) + ray.direction.z.powf(2.0);
 
-                if disc < 0.0 {
-                    return intersections;
-                } else {
-                    let mut t0 = (-b - disc.sqrt()) / (2.0 * a);
-                    let mut t1 = (-b + disc.sqrt()) / (2.0 * a);
+        //parallel to the axis the walls can't be hit, only the caps
+        if a <= epsilon {
+            self.intersect_caps(ray, found);
+            return;
+        }
 
-                    if t0 > t1 {
-                        (t0, t1) = (t1, t0);
-                    }
+        let b = 2.0 * ray.origin.x * ray.direction.x + 2.0 * ray.origin.z * ray.direction.z;
+        let c = ray.origin.x.powf(2.0) + ray.origin.z.powf(2.0) - 1.0;
 
-                    let y0 = self.saved_ray.origin.y + t0 * self.saved_ray.direction.y;
-                    if self.minimum < y0 && y0 < self.maximum {
-                        intersections.push(Intersection::new(t0, *self));
-                    }
+        let disc = b.powf(2.0) - 4.0 * a * c;
 
-                    let y1 = self.saved_ray.origin.y + t1 * self.saved_ray.direction.y;
-                    if self.minimum < y1 && y1 < self.maximum {
-                        intersections.push(Intersection::new(t1, *self));
-                    }
+        if disc < 0.0 {
+            return;
+        }
 
-                    Self::intersect_caps(&self, self.saved_ray, &mut intersections);
-                    intersections
+        let mut t0 = (-b - disc.sqrt()) / (2.0 * a);
+        let mut t1 = (-b + disc.sqrt()) / (2.0 * a);
+
+        if t0 > t1 {
+            (t0, t1) = (t1, t0);
+        }
+
+        let y0 = ray.origin.y + t0 * ray.direction.y;
+        if self.minimum < y0 && y0 < self.maximum && found(t0, None) {
+            return;
+        }
+
+        let y1 = ray.origin.y + t1 * ray.direction.y;
+        if self.minimum < y1 && y1 < self.maximum && found(t1, None) {
+            return;
+        }
+
+        self.intersect_caps(ray, found);
+    }
+
+    fn cone_intersect(&self, ray: Ray, found: &mut dyn FnMut(f64, Option<CubeFace>) -> bool) {
+        let epsilon: f64 = 0.00001;
+        let a = ray.direction.x.powf(2.0) - ray.direction.y.powf(2.0) + ray.direction.z.powf(2.0);
+        let b = 2.0 * ray.origin.x * ray.direction.x - 2.0 * ray.origin.y * ray.direction.y
+            + 2.0 * ray.origin.z * ray.direction.z;
+        let c = ray.origin.x.powf(2.0) - ray.origin.y.powf(2.0) + ray.origin.z.powf(2.0);
+
+        if a.abs() <= epsilon {
+            if b.abs() > epsilon {
+                let t = -c / (2.0 * b);
+                if found(t, None) {
+                    return;
                 }
             }
-            ShapeType::Cone => {
-                let epsilon: f64 = 0.00001;
-                let a = self.saved_ray.direction.x.powf(2.0) - self.saved_ray.direction.y.powf(2.0)
-                    + self.saved_ray.direction.z.powf(2.0);
-                let b = 2.0 * self.saved_ray.origin.x * self.saved_ray.direction.x
-                    - 2.0 * self.saved_ray.origin.y * self.saved_ray.direction.y
-                    + 2.0 * self.saved_ray.origin.z * self.saved_ray.direction.z;
-                let c = self.saved_ray.origin.x.powf(2.0) - self.saved_ray.origin.y.powf(2.0)
-                    + self.saved_ray.origin.z.powf(2.0);
-
-                if a.abs() <= epsilon {
-                    if b.abs() > epsilon {
-                        let t = -c / (2.0 * b);
-                        intersections.push(Intersection::new(t, *self));
-                    }
-                } else {
-                    let disc = b.powf(2.0) - 4.0 * a * c;
-                    if disc >= 0.0 {
-                        let mut t0 = (-b - disc.sqrt()) / (2.0 * a);
-                        let mut t1 = (-b + disc.sqrt()) / (2.0 * a);
-
-                        if t0 > t1 {
-                            (t0, t1) = (t1, t0);
-                        }
-
-                        let y0 = self.saved_ray.origin.y + t0 * self.saved_ray.direction.y;
-                        if self.minimum < y0 && y0 < self.maximum {
-                            intersections.push(Intersection::new(t0, *self));
-                        }
-
-                        let y1 = self.saved_ray.origin.y + t1 * self.saved_ray.direction.y;
-                        if self.minimum < y1 && y1 < self.maximum {
-                            intersections.push(Intersection::new(t1, *self));
-                        }
+        } else {
+            let disc = b.powf(2.0) - 4.0 * a * c;
+            if disc >= 0.0 {
+                let mut t0 = (-b - disc.sqrt()) / (2.0 * a);
+                let mut t1 = (-b + disc.sqrt()) / (2.0 * a);
+
+                if t0 > t1 {
+                    (t0, t1) = (t1, t0);
+                }
+
+                let y0 = ray.origin.y + t0 * ray.direction.y;
+                if self.minimum < y0 && y0 < self.maximum && found(t0, None) {
+                    return;
+                }
+
+                let y1 = ray.origin.y + t1 * ray.direction.y;
+                if self.minimum < y1 && y1 < self.maximum && found(t1, None) {
+                    return;
+                }
+            }
+        }
+
+        self.intersect_caps(ray, found);
+    }
+
+    fn implicit_intersect(&self, ray: Ray, found: &mut dyn FnMut(f64, Option<CubeFace>) -> bool) {
+        let ShapeType::Implicit { function, iso } = &self.shape_type else {
+            unreachable!("implicit ops on a {:?}", self.shape_type);
+        };
+        let xaxis = Self::check_axis(ray.origin.x, ray.direction.x);
+        let yaxis = Self::check_axis(ray.origin.y, ray.direction.y);
+        let zaxis = Self::check_axis(ray.origin.z, ray.direction.z);
+        let tmin = xaxis.0.max(yaxis.0).max(zaxis.0);
+        let tmax = xaxis.1.min(yaxis.1).min(zaxis.1);
+        if tmin > tmax {
+            return;
+        }
+
+        //march in fixed steps and bisect every sign change of the field
+        let field = |t: f64| function.0(ray.position(t)) - iso;
+        let step = 0.01 / ray.direction.magnitude();
+        let mut t0 = tmin;
+        let mut f0 = field(t0);
+        while t0 < tmax {
+            let t1 = (t0 + step).min(tmax);
+            let f1 = field(t1);
+            if (f0 < 0.0) != (f1 < 0.0) {
+                let (mut lo, mut hi) = (t0, t1);
+                for _ in 0..32 {
+                    let mid = (lo + hi) / 2.0;
+                    if (field(mid) < 0.0) == (f0 < 0.0) {
+                        lo = mid;
+                    } else {
+                        hi = mid;
                     }
                 }
+                if found((lo + hi) / 2.0, None) {
+                    return;
+                }
+            }
+            t0 = t1;
+            f0 = f1;
+        }
+    }
 
-                Self::intersect_caps(&self, self.saved_ray, &mut intersections);
+    //Test shapes and groups have no surface of their own
+    fn no_intersect(&self, _ray: Ray, _found: &mut dyn FnMut(f64, Option<CubeFace>) -> bool) {}
+
+    //Stores the matrices normal_at needs. The cache is only used while the transform
+    //is exactly the one it was built from, so moving the shape afterwards is safe.
+    pub fn cache_normal_transforms(&mut self) {
+        self.normal_cache = self.transform.inverse().map(|inverse| NormalCache {
+            transform: self.transform,
+            inverse,
+            inverse_transpose: inverse.transpose(),
+        });
+    }
 
-                intersections
+    pub fn inverse_transpose(&self) -> Matrix {
+        self.normal_transforms().1
+    }
+
+    fn normal_transforms(&self) -> (Matrix, Matrix) {
+        match &self.normal_cache {
+            Some(cache) if cache.transform.m == self.transform.m => {
+                (cache.inverse, cache.inverse_transpose)
+            }
+            _ => {
+                let inverse = self.transform.inverse().unwrap();
+                (inverse, inverse.transpose())
             }
         }
     }
 
-    pub fn normal_at(self, world_point: RayTuple) -> RayTuple {
-        let object_point = self.transform.inverse().unwrap() * world_point;
+    pub fn normal_at(&self, world_point: RayTuple) -> RayTuple {
+        let normal = self.outward_normal_at(world_point);
+        if self.inverted {
+            -normal
+        } else {
+            normal
+        }
+    }
 
-        match self.shape_type {
-            ShapeType::Sphere => {
-                let object_normal = object_point - RayTuple::point(0.0, 0.0, 0.0);
-                let mut world_normal =
-                    self.transform.inverse().unwrap().transpose() * object_normal;
-                world_normal.w = 0.0;
+    //Every shape type goes through the same inverse transpose, which keeps normals
+    //perpendicular to the surface under non uniform scaling and shearing
+    fn outward_normal_at(&self, world_point: RayTuple) -> RayTuple {
+        let (inverse, inverse_transpose) = self.normal_transforms();
+        let local_normal = self.local_normal_at(inverse * world_point);
+        let mut world_normal = inverse_transpose * local_normal;
+        world_normal.w = 0.0;
+
+        world_normal.normalize()
+    }
+
+    //Object space normal, not necessarily of unit length
+    pub fn local_normal_at(&self, object_point: RayTuple) -> RayTuple {
+        (self.ops.normal)(self, object_point)
+    }
+
+    fn sphere_normal(&self, object_point: RayTuple) -> RayTuple {
+        object_point - RayTuple::point(0.0, 0.0, 0.0)
+    }
+
+    fn plane_normal(&self, _object_point: RayTuple) -> RayTuple {
+        RayTuple::vector(0.0, 1.0, 0.0)
+    }
+
+    fn test_normal(&self, object_point: RayTuple) -> RayTuple {
+        RayTuple::vector(object_point.x, object_point.y, object_point.z)
+    }
+
+    fn cube_normal(&self, object_point: RayTuple) -> RayTuple {
+        let x_abs = object_point.x.abs();
+        let y_abs = object_point.y.abs();
+        let z_abs = object_point.z.abs();
 
-                world_normal.normalize()
+        if x_abs >= y_abs {
+            if x_abs >= z_abs {
+                RayTuple::vector(object_point.x, 0.0, 0.0)
+            } else {
+                RayTuple::vector(0.0, 0.0, object_point.z)
             }
-            ShapeType::Plane => RayTuple::vector(0.0, 1.0, 0.0),
-            ShapeType::Test => {
-                let local_normal = RayTuple::vector(object_point.x, object_point.y, object_point.z);
-                let mut world_normal = self.transform.inverse().unwrap().transpose() * local_normal;
-                world_normal.w = 0.0;
+        } else {
+            if y_abs >= z_abs {
+                RayTuple::vector(0.0, object_point.y, 0.0)
+            } else {
+                RayTuple::vector(0.0, 0.0, object_point.z)
+            }
+        }
+    }
+
+    fn cylinder_normal(&self, object_point: RayTuple) -> RayTuple {
+        if let Some(cap) = self.cap_normal(object_point, 1.0, 1.0) {
+            return cap;
+        }
+        RayTuple::vector(object_point.x, 0.0, object_point.z)
+    }
+
+    fn cone_normal(&self, object_point: RayTuple) -> RayTuple {
+        if let Some(cap) = self.cap_normal(object_point, self.maximum.abs(), self.minimum.abs()) {
+            return cap;
+        }
+        let mut y = (object_point.x.powf(2.0) + object_point.z.powf(2.0)).sqrt();
+        if object_point.y > 0.0 {
+            y = -y;
+        }
+        RayTuple::vector(object_point.x, y, object_point.z)
+    }
+
+    //Normal for a point on the top or bottom cap of a cylinder or cone. On the rim
+    //the cap wins strictly inside its radius and the side wins from the radius out,
+    //so neighbouring rim pixels don't flip between the two.
+    fn cap_normal(
+        &self,
+        object_point: RayTuple,
+        top_radius: f64,
+        bottom_radius: f64,
+    ) -> Option<RayTuple> {
+        let epsilon: f64 = 0.00001;
+        let dist = object_point.x.powf(2.0) + object_point.z.powf(2.0);
+
+        if dist < top_radius.powf(2.0) && object_point.y >= self.maximum - epsilon {
+            Some(RayTuple::vector(0.0, 1.0, 0.0))
+        } else if dist < bottom_radius.powf(2.0) && object_point.y <= self.minimum + epsilon {
+            Some(RayTuple::vector(0.0, -1.0, 0.0))
+        } else {
+            None
+        }
+    }
 
-                world_normal.normalize()
+    //Groups never produce hits, so there is no surface to have a normal
+    fn group_normal(&self, _object_point: RayTuple) -> RayTuple {
+        RayTuple::vector(0.0, 0.0, 0.0)
+    }
+
+    fn implicit_normal(&self, object_point: RayTuple) -> RayTuple {
+        let ShapeType::Implicit { function, .. } = &self.shape_type else {
+            unreachable!("implicit ops on a {:?}", self.shape_type);
+        };
+        //the field grows toward the inside, so the outward normal is minus its gradient
+        let h = 0.0001;
+        let dx = RayTuple::vector(h, 0.0, 0.0);
+        let dy = RayTuple::vector(0.0, h, 0.0);
+        let dz = RayTuple::vector(0.0, 0.0, h);
+        RayTuple::vector(
+            function.0(object_point - dx) - function.0(object_point + dx),
+            function.0(object_point - dy) - function.0(object_point + dy),
+            function.0(object_point - dz) - function.0(object_point + dz),
+        )
+    }
+
+    //Converts a world point into this shape's object space by first walking up
+    //the parent chain. Parent indices refer to positions within `shapes`.
+    pub fn world_to_object(&self, shapes: &[Shape], point: RayTuple) -> RayTuple {
+        let parent_point = match self.parent {
+            Some(p) => shapes[p].world_to_object(shapes, point),
+            None => point,
+        };
+
+        self.transform.inverse().unwrap() * parent_point
+    }
+
+    //Converts an object space normal into world space, applying each parent's
+    //inverse transpose on the way up the chain
+    pub fn normal_to_world(&self, shapes: &[Shape], normal: RayTuple) -> RayTuple {
+        let mut world_normal = self.inverse_transpose() * normal;
+        world_normal.w = 0.0;
+        world_normal = world_normal.normalize();
+
+        match self.parent {
+            Some(p) => shapes[p].normal_to_world(shapes, world_normal),
+            None => world_normal,
+        }
+    }
+
+    //Offset for pushing points off this surface, shrunk with the shape's smallest axis
+    //scale so it never reaches through a tiny or thin (flattened) object to the surface behind
+    pub fn surface_epsilon(&self) -> f64 {
+        let m = self.transform.m;
+        let scale = (0..3)
+            .map(|col| (m[0][col].powf(2.0) + m[1][col].powf(2.0) + m[2][col].powf(2.0)).sqrt())
+            .fold(f64::INFINITY, f64::min);
+        if scale.is_normal() {
+            0.00001 * scale
+        } else {
+            0.00001
+        }
+    }
+
+    //Area of the surface after its transform. Non uniform scales are approximated by the
+    //uniform scale of the same volume; shapes with no closed form (implicits) report 0.
+    pub fn surface_area(&self) -> f64 {
+        let local = match self.shape_type {
+            ShapeType::Sphere => 4.0 * PI,
+            ShapeType::Cube => 24.0,
+            ShapeType::Plane => f64::INFINITY,
+            ShapeType::Cylinder => {
+                let caps = if self.closed { 2.0 * PI } else { 0.0 };
+                2.0 * PI * (self.maximum - self.minimum) + caps
             }
-            ShapeType::Cube => {
-                let x_abs = object_point.x.abs();
-                let y_abs = object_point.y.abs();
-                let z_abs = object_point.z.abs();
-
-                if x_abs >= y_abs {
-                    if x_abs >= z_abs {
-                        RayTuple::vector(object_point.x, 0.0, 0.0)
-                    } else {
-                        RayTuple::vector(0.0, 0.0, object_point.z)
-                    }
+            ShapeType::Cone => {
+                //each nappe from its tip out to height h has lateral area sqrt(2)*pi*h^2
+                let (low, high) = (self.minimum, self.maximum);
+                let lateral = if low < 0.0 && high > 0.0 {
+                    low.powf(2.0) + high.powf(2.0)
                 } else {
-                    if y_abs >= z_abs {
-                        RayTuple::vector(0.0, object_point.y, 0.0)
-                    } else {
-                        RayTuple::vector(0.0, 0.0, object_point.z)
-                    }
-                }
+                    (high.powf(2.0) - low.powf(2.0)).abs()
+                };
+                let caps = if self.closed {
+                    PI * (low.powf(2.0) + high.powf(2.0))
+                } else {
+                    0.0
+                };
+                2.0_f64.sqrt() * PI * lateral + caps
             }
-            ShapeType::Cylinder => {
-                let epsilon: f64 = 0.00001;
-                let dist = object_point.x.powf(2.0) + object_point.z.powf(2.0);
+            ShapeType::Test | ShapeType::Group | ShapeType::Implicit { .. } => 0.0,
+        };
 
-                if dist < 1.0 && object_point.y >= self.maximum - epsilon {
-                    return RayTuple::vector(0.0, 1.0, 0.0);
-                } else if dist < 1.0 && object_point.y <= self.minimum + epsilon {
-                    return RayTuple::vector(0.0, -1.0, 0.0);
-                } else {
-                    return RayTuple::vector(object_point.x, 0.0, object_point.z);
-                }
+        local * Matrix::determinant(self.transform).abs().powf(2.0 / 3.0)
+    }
+
+    //Object space bounds of the shape's own geometry, groups have none
+    pub fn bounds(&self) -> BoundingBox {
+        let unit = BoundingBox::new(
+            RayTuple::point(-1.0, -1.0, -1.0),
+            RayTuple::point(1.0, 1.0, 1.0),
+        );
+        match self.shape_type {
+            ShapeType::Sphere | ShapeType::Test | ShapeType::Cube | ShapeType::Implicit { .. } => {
+                unit
             }
+            ShapeType::Plane => BoundingBox::new(
+                RayTuple::point(f64::NEG_INFINITY, 0.0, f64::NEG_INFINITY),
+                RayTuple::point(f64::INFINITY, 0.0, f64::INFINITY),
+            ),
+            ShapeType::Cylinder => BoundingBox::new(
+                RayTuple::point(-1.0, self.minimum, -1.0),
+                RayTuple::point(1.0, self.maximum, 1.0),
+            ),
             ShapeType::Cone => {
-                let epsilon: f64 = 0.00001;
-                let dist = object_point.x.powf(2.0) + object_point.z.powf(2.0);
+                let limit = self.minimum.abs().max(self.maximum.abs());
+                BoundingBox::new(
+                    RayTuple::point(-limit, self.minimum, -limit),
+                    RayTuple::point(limit, self.maximum, limit),
+                )
+            }
+            ShapeType::Group => BoundingBox::empty(),
+        }
+    }
 
-                if dist < 1.0 && object_point.y >= self.maximum - epsilon {
-                    return RayTuple::vector(0.0, 1.0, 0.0);
-                } else if dist < 1.0 && object_point.y <= self.minimum + epsilon {
-                    return RayTuple::vector(0.0, -1.0, 0.0);
-                } else {
-                    let mut y = (object_point.x.powf(2.0) + object_point.z.powf(2.0)).sqrt();
-                    if object_point.y > 0.0 {
-                        y = -y;
-                    }
-                    return RayTuple::vector(object_point.x, y, object_point.z);
-                }
+    //Whether the shape at `index` is below `group` somewhere in its parent chain
+    fn is_descendant(shapes: &[Shape], index: usize, group: usize) -> bool {
+        let mut parent = shapes[index].parent;
+        while let Some(p) = parent {
+            if p == group {
+                return true;
+            }
+            parent = shapes[p].parent;
+        }
+        false
+    }
+
+    //Bounds of everything below the group at `group`, each shape with its own
+    //transform the way intersection sees it
+    pub fn group_bounds(shapes: &[Shape], group: usize) -> BoundingBox {
+        let mut result = BoundingBox::empty();
+        for (i, child) in shapes.iter().enumerate() {
+            if Self::is_descendant(shapes, i, group) {
+                result.merge(child.bounds().transform(child.transform));
             }
         }
+        result
+    }
+
+    //Moves the geometry of the shape at `index` so it is centered in target_box and
+    //scaled uniformly to fit it, e.g. for imported meshes. A shape gets a new transform;
+    //for a group the fit is applied to every non group shape below it, since group
+    //transforms don't move them.
+    pub fn scale_to_fit(shapes: &mut [Shape], index: usize, target_box: BoundingBox) {
+        let is_group = shapes[index].shape_type == ShapeType::Group;
+        let content = if is_group {
+            Self::group_bounds(shapes, index)
+        } else {
+            shapes[index].bounds()
+        };
+        if content.is_empty() {
+            return;
+        }
+
+        //flat or unbounded axes can't constrain the scale
+        let size = content.size();
+        let target = target_box.size();
+        let scale = [(size.x, target.x), (size.y, target.y), (size.z, target.z)]
+            .iter()
+            .filter(|(s, _)| s.is_finite() && *s > 0.0)
+            .map(|(s, t)| t / s)
+            .fold(f64::INFINITY, f64::min);
+        if !scale.is_finite() {
+            return;
+        }
+
+        let from = content.center();
+        let to = target_box.center();
+        let fit = Matrix::translation(to.x, to.y, to.z)
+            * Matrix::scaling(scale, scale, scale)
+            * Matrix::translation(-from.x, -from.y, -from.z);
+        if !is_group {
+            shapes[index].transform = fit;
+            return;
+        }
+        for i in 0..shapes.len() {
+            if shapes[i].shape_type != ShapeType::Group && Self::is_descendant(shapes, i, index) {
+                shapes[i].transform = fit * shapes[i].transform;
+            }
+        }
+    }
+
+    //The face a point on the unit cube lies on is the axis with the largest component,
+    //the same rule normal_at uses
+    fn cube_face(object_point: RayTuple) -> CubeFace {
+        let x_abs = object_point.x.abs();
+        let y_abs = object_point.y.abs();
+        let z_abs = object_point.z.abs();
+
+        if x_abs >= y_abs && x_abs >= z_abs {
+            if object_point.x > 0.0 {
+                CubeFace::PosX
+            } else {
+                CubeFace::NegX
+            }
+        } else if y_abs >= z_abs {
+            if object_point.y > 0.0 {
+                CubeFace::PosY
+            } else {
+                CubeFace::NegY
+            }
+        } else if object_point.z > 0.0 {
+            CubeFace::PosZ
+        } else {
+            CubeFace::NegZ
+        }
     }
 
     fn check_axis(origin: f64, direction: f64) -> (f64, f64) {
@@ -419,10 +1013,10 @@ impl Shape {
         let x = ray.origin.x + t * ray.direction.x;
         let z = ray.origin.z + t * ray.direction.z;
 
-        (x.powf(2.0) + z.powf(2.0)) <= radius
+        (x.powf(2.0) + z.powf(2.0)) <= radius.powf(2.0)
     }
 
-    fn intersect_caps(&self, ray: Ray, xs: &mut Vec<Intersection>) {
+    fn intersect_caps(&self, ray: Ray, found: &mut dyn FnMut(f64, Option<CubeFace>) -> bool) {
         let epsilon: f64 = 0.00001;
         if !self.closed || ray.direction.y.abs() <= epsilon {
             return;
@@ -434,8 +1028,8 @@ impl Shape {
         } else {
             self.minimum.abs()
         };
-        if Self::check_cap(ray, t, radius) {
-            xs.push(Intersection::new(t, *self));
+        if Self::check_cap(ray, t, radius) && found(t, None) {
+            return;
         }
 
         let t = (self.maximum - ray.origin.y) / ray.direction.y;
@@ -445,7 +1039,7 @@ impl Shape {
             self.maximum.abs()
         };
         if Self::check_cap(ray, t, radius) {
-            xs.push(Intersection::new(t, *self));
+            found(t, None);
         }
     }
 }
@@ -525,11 +1119,11 @@ pub fn chapter_thirteen_cylinders() {
     let mut wall = Shape::cube();
     let mut wall_pattern = Pattern::checkers_pattern(lightgray, darkgray);
     wall_pattern.transform = Matrix::scaling(0.1, 0.1, 1.0);
-    wall.material.pattern = Some(wall_pattern);
+    wall.material.pattern = Some(wall_pattern.clone());
     wall.transform = Matrix::translation(0.0, 0.0, -0.5) * Matrix::scaling(5.0, 3.0, 1.0);
 
     let mut left_tower = Shape::cylinder();
-    left_tower.material.pattern = Some(wall_pattern);
+    left_tower.material.pattern = Some(wall_pattern.clone());
     left_tower.maximum = 4.0;
     left_tower.minimum = 0.0;
     left_tower.transform = Matrix::translation(-6.0, 0.0, -1.0) * Matrix::scaling(1.5, 1.0, 1.5);
@@ -575,7 +1169,8 @@ pub fn chapter_thirteen_cylinders() {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::f64::consts::PI;
+    use crate::light::Light;
+    use std::f64::consts::{FRAC_1_SQRT_2, PI};
 
     #[test]
     fn ray_intersects_sphere_at_two_points() {
@@ -583,7 +1178,7 @@ mod tests {
             RayTuple::point(0.0, 0.0, -5.0),
             RayTuple::vector(0.0, 0.0, 1.0),
         );
-        let mut s = Shape::sphere();
+        let s = Shape::sphere();
         let xs = s.intersect(r);
 
         assert_eq!(xs.len(), 2);
@@ -597,7 +1192,7 @@ mod tests {
             RayTuple::point(0.0, 1.0, -5.0),
             RayTuple::vector(0.0, 0.0, 1.0),
         );
-        let mut s = Shape::sphere();
+        let s = Shape::sphere();
         let xs = s.intersect(r);
 
         assert_eq!(xs.len(), 2);
@@ -611,7 +1206,7 @@ mod tests {
             RayTuple::point(0.0, 2.0, -5.0),
             RayTuple::vector(0.0, 0.0, 1.0),
         );
-        let mut s = Shape::sphere();
+        let s = Shape::sphere();
         let xs = s.intersect(r);
 
         assert_eq!(xs.len(), 0);
@@ -623,7 +1218,7 @@ mod tests {
             RayTuple::point(0.0, 0.0, 0.0),
             RayTuple::vector(0.0, 0.0, 1.0),
         );
-        let mut s = Shape::sphere();
+        let s = Shape::sphere();
         let xs = s.intersect(r);
 
         assert_eq!(xs.len(), 2);
@@ -637,7 +1232,7 @@ mod tests {
             RayTuple::point(0.0, 0.0, 5.0),
             RayTuple::vector(0.0, 0.0, 1.0),
         );
-        let mut s = Shape::sphere();
+        let s = Shape::sphere();
         let xs = s.intersect(r);
 
         assert_eq!(xs.len(), 2);
@@ -651,12 +1246,12 @@ mod tests {
             RayTuple::point(0.0, 0.0, -5.0),
             RayTuple::vector(0.0, 0.0, 1.0),
         );
-        let mut s: Shape = Shape::sphere();
+        let s: Shape = Shape::sphere();
         let xs = s.intersect(r);
 
         assert_eq!(xs.len(), 2);
-        assert_eq!(xs[0].object, s);
-        assert_eq!(xs[1].object, s);
+        assert_eq!(*xs[0].object, s);
+        assert_eq!(*xs[1].object, s);
     }
 
     #[test]
@@ -740,9 +1335,9 @@ mod tests {
     fn normal_on_translated_sphere() {
         let mut s = Shape::sphere();
         s.transform = Matrix::translation(0.0, 1.0, 0.0);
-        let n = s.normal_at(RayTuple::point(0.0, 1.70711, -0.70711));
+        let n = s.normal_at(RayTuple::point(0.0, 1.0 + FRAC_1_SQRT_2, -FRAC_1_SQRT_2));
 
-        assert_eq!(n, RayTuple::vector(0.0, 0.70711, -0.70711));
+        assert_eq!(n, RayTuple::vector(0.0, FRAC_1_SQRT_2, -FRAC_1_SQRT_2));
     }
 
     #[test]
@@ -771,7 +1366,7 @@ mod tests {
         let mut s = Shape::sphere();
         let mut m = Material::new();
         m.ambient = 1.0;
-        s.material = m;
+        s.material = m.clone();
         assert_eq!(s.material, m);
     }
 
@@ -800,7 +1395,7 @@ mod tests {
         let mut s = Shape::test_shape();
         let mut m = Material::new();
         m.ambient = 1.0;
-        s.material = m;
+        s.material = m.clone();
         assert_eq!(s.material, m);
     }
 
@@ -812,10 +1407,10 @@ mod tests {
         );
         let mut s = Shape::test_shape();
         s.transform = Matrix::scaling(2.0, 2.0, 2.0);
-        let _xs = s.intersect(r);
+        let local = s.local_ray(r);
 
-        assert_eq!(s.saved_ray.origin, RayTuple::point(0.0, 0.0, -2.5));
-        assert_eq!(s.saved_ray.direction, RayTuple::vector(0.0, 0.0, 0.5));
+        assert_eq!(local.origin, RayTuple::point(0.0, 0.0, -2.5));
+        assert_eq!(local.direction, RayTuple::vector(0.0, 0.0, 0.5));
     }
 
     #[test]
@@ -826,19 +1421,19 @@ mod tests {
         );
         let mut s = Shape::test_shape();
         s.transform = Matrix::translation(5.0, 0.0, 0.0);
-        let _xs = s.intersect(r);
+        let local = s.local_ray(r);
 
-        assert_eq!(s.saved_ray.origin, RayTuple::point(-5.0, 0.0, -5.0));
-        assert_eq!(s.saved_ray.direction, RayTuple::vector(0.0, 0.0, 1.0));
+        assert_eq!(local.origin, RayTuple::point(-5.0, 0.0, -5.0));
+        assert_eq!(local.direction, RayTuple::vector(0.0, 0.0, 1.0));
     }
 
     #[test]
     fn compute_normal_on_translated_shape() {
         let mut s = Shape::test_shape();
         s.transform = Matrix::translation(0.0, 1.0, 0.0);
-        let n = s.normal_at(RayTuple::point(0.0, 1.70711, -0.70711));
+        let n = s.normal_at(RayTuple::point(0.0, 1.0 + FRAC_1_SQRT_2, -FRAC_1_SQRT_2));
 
-        assert_eq!(n, RayTuple::vector(0.0, 0.70711, -0.70711));
+        assert_eq!(n, RayTuple::vector(0.0, FRAC_1_SQRT_2, -FRAC_1_SQRT_2));
     }
 
     #[test]
@@ -870,7 +1465,7 @@ mod tests {
 
     #[test]
     fn intersect_ray_parallel_to_plane() {
-        let mut p = Shape::plane();
+        let p = Shape::plane();
         let r = Ray::new(
             RayTuple::point(0.0, 10.0, 0.0),
             RayTuple::vector(0.0, 0.0, 1.0),
@@ -882,7 +1477,7 @@ mod tests {
 
     #[test]
     fn intersect_coplanar_ray_plane() {
-        let mut p = Shape::plane();
+        let p = Shape::plane();
         let r = Ray::new(
             RayTuple::point(0.0, 0.0, 0.0),
             RayTuple::vector(0.0, 0.0, 1.0),
@@ -894,7 +1489,7 @@ mod tests {
 
     #[test]
     fn ray_intersect_plane_from_above() {
-        let mut p = Shape::plane();
+        let p = Shape::plane();
         let r = Ray::new(
             RayTuple::point(0.0, 1.0, 0.0),
             RayTuple::vector(0.0, -1.0, 0.0),
@@ -903,12 +1498,12 @@ mod tests {
 
         assert_eq!(xs.len(), 1);
         assert_eq!(xs[0].t, 1.0);
-        assert_eq!(xs[0].object, p);
+        assert_eq!(*xs[0].object, p);
     }
 
     #[test]
     fn ray_intersect_plane_from_below() {
-        let mut p = Shape::plane();
+        let p = Shape::plane();
         let r = Ray::new(
             RayTuple::point(0.0, -1.0, 0.0),
             RayTuple::vector(0.0, 1.0, 0.0),
@@ -917,7 +1512,166 @@ mod tests {
 
         assert_eq!(xs.len(), 1);
         assert_eq!(xs[0].t, 1.0);
-        assert_eq!(xs[0].object, p);
+        assert_eq!(*xs[0].object, p);
+    }
+
+    #[test]
+    fn plane_from_normal_and_distance() {
+        let p = Shape::plane_from(RayTuple::vector(0.0, 0.0, 1.0), 3.0);
+        let r = Ray::new(
+            RayTuple::point(0.0, 0.0, 0.0),
+            RayTuple::vector(0.0, 0.0, 1.0),
+        );
+        let xs = p.intersect(r);
+
+        assert_eq!(xs.len(), 1);
+        assert_eq!(xs[0].t, 3.0);
+        assert_eq!(
+            p.normal_at(RayTuple::point(2.0, -1.0, 3.0)),
+            RayTuple::vector(0.0, 0.0, 1.0)
+        );
+    }
+
+    #[test]
+    fn converting_point_from_world_to_object_space() {
+        let mut g1 = Shape::group();
+        g1.transform = Matrix::rotation_y(PI / 2.0);
+        let mut g2 = Shape::group();
+        g2.transform = Matrix::scaling(2.0, 2.0, 2.0);
+        g2.parent = Some(0);
+        let mut s = Shape::sphere();
+        s.transform = Matrix::translation(5.0, 0.0, 0.0);
+        s.parent = Some(1);
+        let shapes = vec![g1, g2, s];
+
+        let p = shapes[2].world_to_object(&shapes, RayTuple::point(-2.0, 0.0, -10.0));
+
+        assert_eq!(p, RayTuple::point(0.0, 0.0, -1.0));
+    }
+
+    #[test]
+    fn converting_normal_from_object_to_world_space() {
+        let mut g1 = Shape::group();
+        g1.transform = Matrix::rotation_y(PI / 2.0);
+        let mut g2 = Shape::group();
+        g2.transform = Matrix::scaling(1.0, 2.0, 3.0);
+        g2.parent = Some(0);
+        let mut s = Shape::sphere();
+        s.transform = Matrix::translation(5.0, 0.0, 0.0);
+        s.parent = Some(1);
+        let shapes = vec![g1, g2, s];
+
+        let coord = 3.0_f64.sqrt() / 3.0;
+        let n = shapes[2].normal_to_world(&shapes, RayTuple::vector(coord, coord, coord));
+
+        assert_eq!(n, RayTuple::vector(0.28571, 0.42857, -0.85714));
+    }
+
+    #[test]
+    fn scaling_a_group_to_fit_a_unit_box() {
+        let g = Shape::group();
+        let mut a = Shape::cube();
+        a.transform = Matrix::translation(8.0, 3.0, -2.0) * Matrix::scaling(5.0, 5.0, 5.0);
+        a.parent = Some(0);
+        let mut inner = Shape::group();
+        inner.parent = Some(0);
+        let mut b = Shape::sphere();
+        b.transform = Matrix::translation(8.0, 3.0, -2.0);
+        b.parent = Some(2);
+        let mut shapes = vec![g, a, inner, b];
+
+        let unit = BoundingBox::new(
+            RayTuple::point(-0.5, -0.5, -0.5),
+            RayTuple::point(0.5, 0.5, 0.5),
+        );
+        Shape::scale_to_fit(&mut shapes, 0, unit);
+
+        //the children are moved, the groups keep the identity
+        assert_eq!(shapes[0].transform, Matrix::identity());
+        assert_eq!(shapes[2].transform, Matrix::identity());
+        let fitted = Shape::group_bounds(&shapes, 0);
+        assert!(unit.contains_point(fitted.min) && unit.contains_point(fitted.max));
+        assert_eq!(fitted.center(), RayTuple::point(0.0, 0.0, 0.0));
+        assert_eq!(fitted.size(), RayTuple::vector(1.0, 1.0, 1.0));
+
+        //and render where the bounds say they are
+        let r = Ray::new(
+            RayTuple::point(0.0, 0.0, -5.0),
+            RayTuple::vector(0.0, 0.0, 1.0),
+        );
+        let xs = shapes[1].intersect(r);
+        assert!((xs[0].t - 4.5).abs() < 0.00001);
+    }
+
+    #[test]
+    fn a_group_has_no_normal_to_give() {
+        let g = Shape::group();
+        assert_eq!(
+            g.normal_at(RayTuple::point(0.0, 0.0, 0.0)),
+            RayTuple::vector(0.0, 0.0, 0.0)
+        );
+    }
+
+    #[test]
+    fn surface_area_of_unit_shapes() {
+        assert!((Shape::sphere().surface_area() - 12.56637).abs() < 0.00001);
+        assert_eq!(Shape::cube().surface_area(), 24.0);
+
+        let mut sphere = Shape::sphere();
+        sphere.transform = Matrix::translation(3.0, 0.0, 1.0) * Matrix::scaling(2.0, 2.0, 2.0);
+        assert!((sphere.surface_area() - 16.0 * PI).abs() < 0.00001);
+
+        let mut cyl = Shape::cylinder();
+        cyl.minimum = 0.0;
+        cyl.maximum = 2.0;
+        cyl.closed = true;
+        assert!((cyl.surface_area() - 6.0 * PI).abs() < 0.00001);
+        assert_eq!(Shape::plane().surface_area(), f64::INFINITY);
+    }
+
+    #[test]
+    fn moving_shape_is_hit_where_it_is_at_ray_time() {
+        let r = Ray::new(
+            RayTuple::point(0.0, 0.0, -5.0),
+            RayTuple::vector(0.0, 0.0, 1.0),
+        );
+
+        let mut moving = Shape::sphere();
+        moving.velocity = RayTuple::vector(0.0, 0.0, 2.0);
+        let early = moving.intersect(r);
+        let late = moving.intersect(r.with_time(1.0));
+        assert_eq!(early[0].t, 4.0);
+        assert_eq!(late[0].t, 6.0);
+        assert_eq!(
+            late[0].object.normal_at(r.position(6.0)),
+            RayTuple::vector(0.0, 0.0, -1.0)
+        );
+
+        let still = Shape::sphere();
+        assert_eq!(still.intersect(r)[0].t, 4.0);
+        assert_eq!(still.intersect(r.with_time(1.0))[0].t, 4.0);
+    }
+
+    #[test]
+    fn walls_face_the_origin() {
+        let wall = Shape::wall_z(5.0);
+        assert_eq!(
+            wall.normal_at(RayTuple::point(3.0, -2.0, 5.0)),
+            RayTuple::vector(0.0, 0.0, -1.0)
+        );
+        let r = Ray::new(
+            RayTuple::point(0.0, 1.0, -2.0),
+            RayTuple::vector(0.0, 0.0, 1.0),
+        );
+        let xs = wall.intersect(r);
+        assert_eq!(xs.len(), 1);
+        assert!((xs[0].t - 7.0).abs() < 0.00001);
+
+        let wall = Shape::wall_x(-3.0);
+        assert_eq!(
+            wall.normal_at(RayTuple::point(-3.0, 1.0, 1.0)),
+            RayTuple::vector(1.0, 0.0, 0.0)
+        );
     }
 
     #[test]
@@ -931,7 +1685,7 @@ mod tests {
 
     #[test]
     fn ray_intersects_cube() {
-        let mut c = Shape::cube();
+        let c = Shape::cube();
 
         let test_tuples: Vec<(RayTuple, RayTuple, f64, f64)> = vec![
             (
@@ -988,9 +1742,22 @@ mod tests {
         }
     }
 
+    #[test]
+    fn ray_entering_cube_reports_hit_face() {
+        let c = Shape::cube();
+        let r = Ray::new(
+            RayTuple::point(5.0, 0.5, 0.0),
+            RayTuple::vector(-1.0, 0.0, 0.0),
+        );
+        let xs: Vec<Intersection> = c.intersect(r);
+
+        assert_eq!(xs[0].face, Some(CubeFace::PosX));
+        assert_eq!(xs[1].face, Some(CubeFace::NegX));
+    }
+
     #[test]
     fn ray_misses_cube() {
-        let mut c = Shape::cube();
+        let c = Shape::cube();
 
         let test_tuples: Vec<(RayTuple, RayTuple)> = vec![
             (
@@ -1076,7 +1843,7 @@ mod tests {
 
     #[test]
     fn ray_misses_cylinder() {
-        let mut cyl = Shape::cylinder();
+        let cyl = Shape::cylinder();
 
         let test_tuples: Vec<(RayTuple, RayTuple)> = vec![
             (
@@ -1104,7 +1871,7 @@ mod tests {
 
     #[test]
     fn ray_hits_cylinder() {
-        let mut cyl = Shape::cylinder();
+        let cyl = Shape::cylinder();
 
         let test_tuples: Vec<(RayTuple, RayTuple, f64, f64)> = vec![
             (
@@ -1227,7 +1994,7 @@ mod tests {
     fn default_closed_cylinder() {
         let cyl = Shape::cylinder();
 
-        assert!(cyl.closed == false);
+        assert!(!cyl.closed);
     }
 
     #[test]
@@ -1317,7 +2084,7 @@ mod tests {
 
     #[test]
     fn intersect_cone() {
-        let mut shape = Shape::cone();
+        let shape = Shape::cone();
 
         let test_tuples: Vec<(RayTuple, RayTuple, f64, f64)> = vec![
             (
@@ -1354,7 +2121,7 @@ mod tests {
 
     #[test]
     fn intersect_cone_with_parallel_ray() {
-        let mut shape = Shape::cone();
+        let shape = Shape::cone();
         let direction = RayTuple::vector(0.0, 1.0, 1.0).normalize();
         let r = Ray::new(RayTuple::point(0.0, 0.0, -1.0), direction);
         let xs = shape.intersect(r);
@@ -1363,6 +2130,32 @@ mod tests {
         assert_eq!(xs[0].t, 0.3535533905932738);
     }
 
+    #[test]
+    fn ray_along_cylinder_axis_hits_both_caps() {
+        let mut cyl = Shape::cylinder();
+        cyl.minimum = 1.0;
+        cyl.maximum = 2.0;
+        cyl.closed = true;
+
+        let down = Ray::new(
+            RayTuple::point(0.0, 5.0, 0.0),
+            RayTuple::vector(0.0, -1.0, 0.0),
+        );
+        let xs = cyl.intersect(down);
+        assert_eq!(xs.len(), 2);
+        assert_eq!(xs[0].t, 4.0);
+        assert_eq!(xs[1].t, 3.0);
+
+        let up = Ray::new(
+            RayTuple::point(0.5, 0.0, 0.0),
+            RayTuple::vector(0.0, 1.0, 0.0),
+        );
+        let xs = cyl.intersect(up);
+        assert_eq!(xs.len(), 2);
+        assert_eq!(xs[0].t, 1.0);
+        assert_eq!(xs[1].t, 2.0);
+    }
+
     #[test]
     fn intersecting_cone_end_caps() {
         let mut shape = Shape::cone();
@@ -1416,9 +2209,374 @@ mod tests {
         ];
 
         for test in test_tuples {
-            let n = shape.normal_at(test.0);
+            let n = shape.local_normal_at(test.0);
 
             assert_eq!(n, test.1);
         }
     }
+
+    #[test]
+    fn single_metaball_intersects_like_a_sphere() {
+        //a gaussian of radius 0.5 crosses 1/e exactly half a unit from its center
+        let s = Shape::metaballs(
+            vec![(RayTuple::point(0.0, 0.0, 0.0), 0.5)],
+            (-1.0_f64).exp(),
+        );
+        let r = Ray::new(
+            RayTuple::point(0.0, 0.0, -5.0),
+            RayTuple::vector(0.0, 0.0, 1.0),
+        );
+        let xs = s.intersect(r);
+
+        assert_eq!(xs.len(), 2);
+        assert!((xs[0].t - 4.5).abs() < 0.0001);
+        assert!((xs[1].t - 5.5).abs() < 0.0001);
+        assert_eq!(
+            s.normal_at(RayTuple::point(0.0, 0.0, -0.5)),
+            RayTuple::vector(0.0, 0.0, -1.0)
+        );
+    }
+
+    #[test]
+    fn ray_misses_metaball_field() {
+        let s = Shape::metaballs(
+            vec![(RayTuple::point(0.0, 0.0, 0.0), 0.5)],
+            (-1.0_f64).exp(),
+        );
+        let r = Ray::new(
+            RayTuple::point(0.0, 0.9, -5.0),
+            RayTuple::vector(0.0, 0.0, 1.0),
+        );
+
+        assert_eq!(s.intersect(r).len(), 0);
+    }
+
+    #[test]
+    fn is_hit_agrees_with_intersect() {
+        let rays = vec![
+            Ray::new(
+                RayTuple::point(0.0, 0.0, -5.0),
+                RayTuple::vector(0.0, 0.0, 1.0),
+            ),
+            Ray::new(
+                RayTuple::point(0.0, 2.0, -5.0),
+                RayTuple::vector(0.0, 0.0, 1.0),
+            ),
+            Ray::new(
+                RayTuple::point(0.0, 1.0, 0.0),
+                RayTuple::vector(0.0, -1.0, 0.0),
+            ),
+            Ray::new(
+                RayTuple::point(0.0, 10.0, 0.0),
+                RayTuple::vector(0.0, 0.0, 1.0),
+            ),
+        ];
+
+        for shape in [Shape::sphere(), Shape::plane()] {
+            for r in &rays {
+                let expected = shape.intersect(*r).iter().any(|i| i.t > 0.0);
+                assert_eq!(shape.is_hit(*r, f64::INFINITY), expected);
+            }
+        }
+
+        //hits past max_t do not count
+        let s = Shape::sphere();
+        assert!(!s.is_hit(rays[0], 3.0));
+        assert!(s.is_hit(rays[0], 5.0));
+    }
+
+    #[test]
+    fn inverted_sphere_normal_points_inward() {
+        let mut s = Shape::sphere();
+        s.inverted = true;
+
+        assert_eq!(
+            s.normal_at(RayTuple::point(1.0, 0.0, 0.0)),
+            RayTuple::vector(-1.0, 0.0, 0.0)
+        );
+    }
+
+    #[test]
+    fn inverted_cube_lights_its_interior() {
+        let mut room = Shape::cube();
+        room.transform = Matrix::scaling(5.0, 5.0, 5.0);
+        room.inverted = true;
+
+        let mut w = World::new();
+        w.lights = vec![Light::point_light(
+            RayTuple::point(0.0, 0.0, 0.0),
+            Color::new(1.0, 1.0, 1.0),
+        )];
+        w.objects.push(room);
+
+        let r = Ray::new(
+            RayTuple::point(0.0, 0.0, 0.0),
+            RayTuple::vector(1.0, 0.0, 0.0),
+        );
+        let xs = w.intersect_world(r);
+        let hit = Intersection::hit(xs.clone()).unwrap();
+        let comps = hit.prepare_computations(r, &xs);
+
+        //the wall faces the viewer, so the hit counts as outside and receives diffuse light
+        assert!(!comps.inside);
+        assert_eq!(comps.normalv, RayTuple::vector(-1.0, 0.0, 0.0));
+        assert_eq!(w.color_at(r, 5), Color::new(1.9, 1.9, 1.9));
+    }
+
+    #[test]
+    fn material_helpers_update_the_shapes_material() {
+        let mut s = Shape::sphere();
+        s.set_color(Color::new(0.2, 0.3, 0.4));
+        assert_eq!(s.material.color, Color::new(0.2, 0.3, 0.4));
+
+        s.material_mut().reflective = 0.7;
+        s.material_mut().ambient = 0.0;
+        assert_eq!(s.material.reflective, 0.7);
+        assert_eq!(s.material.ambient, 0.0);
+
+        let stripes = Pattern::stripe_pattern(Color::new(1.0, 1.0, 1.0), Color::new(0.0, 0.0, 0.0));
+        s.set_pattern(stripes.clone());
+        assert_eq!(s.material.pattern, Some(stripes));
+    }
+
+    #[test]
+    fn separately_built_spheres_are_equivalent_but_not_equal() {
+        let mut a = Shape::sphere();
+        a.transform = Matrix::translation(1.0, 2.0, 3.0);
+        let mut b = Shape::sphere();
+        b.transform = Matrix::translation(1.0, 2.0, 3.0);
+
+        assert!(a.equivalent(&b));
+        assert_ne!(a, b);
+
+        b.material.ambient = 0.5;
+        assert!(!a.equivalent(&b));
+
+        //the back material counts as part of the material
+        b.material.ambient = a.material.ambient;
+        b.material.back_material = Some(Box::new(Material::new()));
+        assert!(!a.equivalent(&b));
+    }
+
+    #[test]
+    fn cached_inverse_transpose_matches_computed_one() {
+        let mut s = Shape::sphere();
+        s.transform = Matrix::rotation_z(PI / 5.0) * Matrix::scaling(1.0, 0.5, 1.0);
+        let p = RayTuple::point(0.0, 2.0_f64.sqrt() / 2.0, -(2.0_f64.sqrt()) / 2.0);
+        let before = s.normal_at(p);
+
+        s.cache_normal_transforms();
+        assert_eq!(
+            s.inverse_transpose(),
+            s.transform.inverse().unwrap().transpose()
+        );
+        assert_eq!(s.normal_at(p), before);
+
+        //a stale cache is ignored once the transform changes
+        s.transform = Matrix::translation(0.0, 1.0, 0.0);
+        assert_eq!(
+            s.normal_at(RayTuple::point(0.0, 1.0 + FRAC_1_SQRT_2, -FRAC_1_SQRT_2)),
+            RayTuple::vector(0.0, FRAC_1_SQRT_2, -FRAC_1_SQRT_2)
+        );
+    }
+
+    #[test]
+    fn normals_stay_perpendicular_to_sheared_surfaces() {
+        let shear = Matrix::shearing(1.0, 0.0, 0.5, 0.0, 0.0, 0.3);
+
+        let mut sphere = Shape::sphere();
+        sphere.transform = shear;
+        let q = RayTuple::vector(1.0, 2.0, -2.0).normalize();
+        let tangents = [
+            RayTuple::vector(2.0, -1.0, 0.0),
+            RayTuple::vector(0.0, 1.0, 1.0),
+        ];
+        let n = sphere.normal_at(shear * RayTuple::point(q.x, q.y, q.z));
+        for t in tangents {
+            assert!(n.dot(shear * t).abs() < 0.00001);
+        }
+        assert!(n.dot(shear * q) > 0.0);
+
+        //flat sided shapes share the same path
+        let mut cube = Shape::cube();
+        cube.transform = shear;
+        let n = cube.normal_at(shear * RayTuple::point(0.3, 1.0, -0.4));
+        for t in [
+            RayTuple::vector(1.0, 0.0, 0.0),
+            RayTuple::vector(0.0, 0.0, 1.0),
+        ] {
+            assert!(n.dot(shear * t).abs() < 0.00001);
+        }
+        assert!((n.magnitude() - 1.0).abs() < 0.00001);
+    }
+
+    #[test]
+    fn every_shape_type_dispatches_to_its_own_math() {
+        let along_z = Ray::new(
+            RayTuple::point(0.0, 0.0, -5.0),
+            RayTuple::vector(0.0, 0.0, 1.0),
+        );
+        let ts = |s: &Shape| {
+            s.intersect(along_z)
+                .iter()
+                .map(|i| i.t)
+                .collect::<Vec<f64>>()
+        };
+        //hits along z, then a point on the surface and its object space normal
+        let cases = [
+            (
+                ShapeType::Sphere,
+                vec![4.0, 6.0],
+                (0.0, 1.0, 0.0),
+                (0.0, 1.0, 0.0),
+            ),
+            (
+                ShapeType::Cube,
+                vec![4.0, 6.0],
+                (1.0, 0.5, -0.8),
+                (1.0, 0.0, 0.0),
+            ),
+            (
+                ShapeType::Cylinder,
+                vec![4.0, 6.0],
+                (0.0, 5.0, -1.0),
+                (0.0, 0.0, -1.0),
+            ),
+            (
+                ShapeType::Cone,
+                vec![5.0, 5.0],
+                (-1.0, -1.0, 0.0),
+                (-1.0, 1.0, 0.0),
+            ),
+            (
+                ShapeType::Plane,
+                vec![],
+                (10.0, 0.0, -10.0),
+                (0.0, 1.0, 0.0),
+            ),
+            (ShapeType::Test, vec![], (1.0, 2.0, 3.0), (1.0, 2.0, 3.0)),
+        ];
+        for (shape_type, expected, (px, py, pz), (nx, ny, nz)) in cases {
+            let s = Shape::new(shape_type);
+            assert_eq!(ts(&s), expected, "{:?}", s.shape_type);
+            let n = s.local_normal_at(RayTuple::point(px, py, pz));
+            assert_eq!(n, RayTuple::vector(nx, ny, nz), "{:?}", s.shape_type);
+        }
+        assert_eq!(ts(&Shape::cube()), vec![4.0, 6.0]);
+        assert!(ts(&Shape::group()).is_empty());
+
+        //a field sphere of radius 0.5
+        let implicit = Shape::implicit(|p| 1.0 - p.x * p.x - p.y * p.y - p.z * p.z, 0.75);
+        let xs = ts(&implicit);
+        assert!((xs[0] - 4.5).abs() < 0.00001 && (xs[1] - 5.5).abs() < 0.00001);
+        let n = implicit.local_normal_at(RayTuple::point(0.5, 0.0, 0.0));
+        assert_eq!(n.normalize(), RayTuple::vector(1.0, 0.0, 0.0));
+
+        let unit = BoundingBox::new(
+            RayTuple::point(-1.0, -1.0, -1.0),
+            RayTuple::point(1.0, 1.0, 1.0),
+        );
+        assert_eq!(Shape::sphere().bounds(), unit);
+        assert_eq!(Shape::cube().bounds(), unit);
+        assert_eq!(implicit.bounds(), unit);
+        assert!(!Shape::plane().bounds().is_finite());
+        assert!(Shape::group().bounds().is_empty());
+    }
+
+    #[test]
+    fn rotated_translated_cube_intersects_in_object_space() {
+        let mut c = Shape::cube();
+        c.transform = Matrix::translation(3.0, 0.0, 0.0) * Matrix::rotation_y(PI / 4.0);
+
+        //turned 45 degrees the cube's cross section is the diamond |x - 3| + |z| <= sqrt(2)
+        let r = Ray::new(
+            RayTuple::point(3.0, 0.0, -5.0),
+            RayTuple::vector(0.0, 0.0, 1.0),
+        );
+        let xs = c.intersect(r);
+        assert_eq!(xs.len(), 2);
+        assert!((xs[0].t - (5.0 - 2.0_f64.sqrt())).abs() < 0.00001);
+        assert!((xs[1].t - (5.0 + 2.0_f64.sqrt())).abs() < 0.00001);
+
+        let r = Ray::new(
+            RayTuple::point(3.5, 0.5, -5.0),
+            RayTuple::vector(0.0, 0.0, 1.0),
+        );
+        let half_width = 2.0_f64.sqrt() - 0.5;
+        let xs = c.intersect(r);
+        assert_eq!(xs.len(), 2);
+        assert!((xs[0].t - (5.0 - half_width)).abs() < 0.00001);
+        assert!((xs[1].t - (5.0 + half_width)).abs() < 0.00001);
+
+        let r = Ray::new(
+            RayTuple::point(4.5, 0.0, -5.0),
+            RayTuple::vector(0.0, 0.0, 1.0),
+        );
+        assert!(c.intersect(r).is_empty());
+    }
+
+    #[test]
+    fn cap_wins_only_inside_the_rim() {
+        let mut cyl = Shape::cylinder();
+        cyl.minimum = 1.0;
+        cyl.maximum = 2.0;
+        cyl.closed = true;
+
+        let inside = cyl.local_normal_at(RayTuple::point(0.99999, 2.0, 0.0));
+        let rim = cyl.local_normal_at(RayTuple::point(1.0, 2.0, 0.0));
+        let outside = cyl.local_normal_at(RayTuple::point(1.00001, 2.0, 0.0));
+        assert_eq!(inside, RayTuple::vector(0.0, 1.0, 0.0));
+        assert_eq!(rim, RayTuple::vector(1.0, 0.0, 0.0));
+        assert_eq!(outside, RayTuple::vector(1.00001, 0.0, 0.0));
+
+        //a cone's cap is as wide as the cone at that height, not the unit circle
+        let mut cone = Shape::cone();
+        cone.minimum = 0.0;
+        cone.maximum = 2.0;
+        cone.closed = true;
+        assert_eq!(
+            cone.local_normal_at(RayTuple::point(1.5, 2.0, 0.0)),
+            RayTuple::vector(0.0, 1.0, 0.0)
+        );
+        assert_eq!(
+            cone.local_normal_at(RayTuple::point(1.99999, 2.0, 0.0)),
+            RayTuple::vector(0.0, 1.0, 0.0)
+        );
+        assert_eq!(
+            cone.local_normal_at(RayTuple::point(2.0, 2.0, 0.0)),
+            RayTuple::vector(2.0, -2.0, 0.0)
+        );
+
+        let r = Ray::new(
+            RayTuple::point(1.5, 5.0, 0.0),
+            RayTuple::vector(0.0, -1.0, 0.0),
+        );
+        let xs = cone.intersect(r);
+        assert_eq!(xs.len(), 2);
+        assert!(xs.iter().any(|i| (i.t - 3.0).abs() < 0.00001));
+    }
+
+    #[test]
+    fn shapes_moving_differently_are_not_equivalent() {
+        let a = Shape::sphere();
+        let mut b = Shape::sphere();
+        b.velocity = RayTuple::vector(0.0, 1.0, 0.0);
+
+        assert!(!a.equivalent(&b));
+        assert!(!b.equivalent(&a));
+    }
+
+    #[test]
+    fn shapes_in_different_groups_are_not_equivalent() {
+        let a = Shape::sphere();
+        let mut b = Shape::sphere();
+        b.parent = Some(0);
+
+        assert!(!a.equivalent(&b));
+        let mut c = Shape::sphere();
+        c.parent = Some(1);
+        assert!(!b.equivalent(&c));
+        c.parent = Some(0);
+        assert!(b.equivalent(&c));
+    }
 }
@@ -0,0 +1,132 @@
+#![allow(dead_code)]
+use crate::color::Color;
+
+//Spectral colors sampled in a handful of evenly spaced wavelength bins (nanometers).
+//Converted to RGB through the CIE 1931 color matching functions at output time.
+pub const BIN_COUNT: usize = 11;
+pub const FIRST_WAVELENGTH: f64 = 400.0;
+pub const BIN_WIDTH: f64 = 30.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Spectrum {
+    pub bins: [f64; BIN_COUNT],
+}
+
+impl Spectrum {
+    pub fn flat(value: f64) -> Self {
+        Self {
+            bins: [value; BIN_COUNT],
+        }
+    }
+
+    pub fn wavelength(bin: usize) -> f64 {
+        FIRST_WAVELENGTH + bin as f64 * BIN_WIDTH
+    }
+
+    //All the power in the bin closest to the wavelength
+    pub fn from_wavelength(wavelength: f64, power: f64) -> Self {
+        let mut s = Self::flat(0.0);
+        let bin = ((wavelength - FIRST_WAVELENGTH) / BIN_WIDTH).round();
+        s.bins[(bin.max(0.0) as usize).min(BIN_COUNT - 1)] = power;
+        s
+    }
+
+    //Crude upsampling, blue below 490nm, green up to 580nm and red above
+    pub fn from_color(c: Color) -> Self {
+        let mut s = Self::flat(0.0);
+        for (i, bin) in s.bins.iter_mut().enumerate() {
+            let wavelength = Self::wavelength(i);
+            *bin = if wavelength < 490.0 {
+                c.blue
+            } else if wavelength < 580.0 {
+                c.green
+            } else {
+                c.red
+            };
+        }
+        s
+    }
+
+    //Linear interpolation between bin centers
+    pub fn sample(&self, wavelength: f64) -> f64 {
+        let position =
+            ((wavelength - FIRST_WAVELENGTH) / BIN_WIDTH).clamp(0.0, (BIN_COUNT - 1) as f64);
+        let lower = position.floor() as usize;
+        let upper = (lower + 1).min(BIN_COUNT - 1);
+        let fraction = position - lower as f64;
+
+        self.bins[lower] * (1.0 - fraction) + self.bins[upper] * fraction
+    }
+
+    //Balanced so a flat spectrum of value v comes out as Color(v, v, v)
+    pub fn to_color(self) -> Color {
+        let (r, g, b) = Self::to_linear_rgb(&self.bins);
+        let (wr, wg, wb) = Self::to_linear_rgb(&[1.0; BIN_COUNT]);
+
+        Color::new(r / wr, g / wg, b / wb)
+    }
+
+    fn to_linear_rgb(bins: &[f64; BIN_COUNT]) -> (f64, f64, f64) {
+        let (mut x, mut y, mut z) = (0.0, 0.0, 0.0);
+        for (i, value) in bins.iter().enumerate() {
+            let (xb, yb, zb) = color_matching(Self::wavelength(i));
+            x += value * xb;
+            y += value * yb;
+            z += value * zb;
+        }
+
+        (
+            3.2406 * x - 1.5372 * y - 0.4986 * z,
+            -0.9689 * x + 1.8758 * y + 0.0415 * z,
+            0.0557 * x - 0.2040 * y + 1.0570 * z,
+        )
+    }
+}
+
+//Multi lobe gaussian fit of the CIE 1931 2 degree observer (Wyman, Sloan and Shirley 2013)
+fn color_matching(wavelength: f64) -> (f64, f64, f64) {
+    let g = |mu: f64, sigma1: f64, sigma2: f64| {
+        let sigma = if wavelength < mu { sigma1 } else { sigma2 };
+        (-0.5 * ((wavelength - mu) / sigma).powf(2.0)).exp()
+    };
+
+    let x =
+        1.056 * g(599.8, 37.9, 31.0) + 0.362 * g(442.0, 16.0, 26.7) - 0.065 * g(501.1, 20.4, 26.2);
+    let y = 0.821 * g(568.8, 46.9, 40.5) + 0.286 * g(530.9, 16.3, 31.1);
+    let z = 1.217 * g(437.0, 11.8, 36.0) + 0.681 * g(459.0, 26.0, 13.8);
+
+    (x, y, z)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flat_spectrum_is_neutral() {
+        let c = Spectrum::flat(0.5).to_color();
+
+        assert_eq!(c, Color::new(0.5, 0.5, 0.5));
+    }
+
+    #[test]
+    fn red_wavelength_leans_red() {
+        let c = Spectrum::from_wavelength(620.0, 1.0).to_color();
+
+        assert!(c.red > c.green);
+        assert!(c.red > c.blue);
+    }
+
+    #[test]
+    fn sample_interpolates_between_bins() {
+        let mut s = Spectrum::flat(0.0);
+        s.bins[1] = 1.0;
+
+        assert_eq!(s.sample(430.0), 1.0);
+        assert_eq!(s.sample(415.0), 0.5);
+        assert_eq!(
+            Spectrum::from_color(Color::new(1.0, 0.0, 0.0)).sample(650.0),
+            1.0
+        );
+    }
+}
@@ -1,29 +1,70 @@
 #![allow(dead_code)]
+use crate::bounds::BoundingBox;
 use crate::camera::Camera;
+use crate::canvas::{Canvas, CanvasRgba};
 use crate::color::Color;
 use crate::computations::Computations;
-use crate::intersection::Intersection;
+use crate::intersection::{Intersection, NearestHits};
 use crate::light::Light;
 use crate::matrix::Matrix;
 use crate::ray::Ray;
 use crate::raytuple::RayTuple;
+use crate::rng::Rng;
+use crate::sampling;
 use crate::shape::{Shape, ShapeType};
-use std::cmp::Ordering;
 use std::f64::consts::FRAC_PI_3;
+use std::io;
+use std::sync::Arc;
+use uuid::Uuid;
 
+//Hard cap on reflection/refraction recursion, however large a caller's `remaining` is,
+//so facing mirrors can't overflow the stack
+pub const MAX_RECURSION_DEPTH: i32 = 32;
+
+#[derive(Clone)]
 pub struct World {
-    pub light: Light,
+    pub lights: Vec<Light>,
     pub objects: Vec<Shape>,
+    pub fog: Option<(Color, f64)>,
+    pub normalize_lighting: bool,
+    //(samples, radius) for ambient occlusion, off when None
+    pub occlusion: Option<(usize, f64)>,
+    //separate bounce budgets, so mirrors and glass each get their own depth
+    pub max_reflection_depth: i32,
+    pub max_refraction_depth: i32,
+    //cube map faces (+x, -x, +y, -y, +z, -z) seen by rays that miss everything
+    pub skybox: Option<Arc<[Canvas; 6]>>,
+    //extra distance shadow rays start toward the light, on top of over_point's offset
+    //along the normal, to keep grazing lights from shadowing their own surface
+    pub shadow_light_offset: f64,
+    //mixed into the seeds of sampled effects (soft shadows, occlusion), change it to
+    //draw a different set of samples at the same points
+    pub sample_seed: u64,
+}
+
+//Saved copy of a world's state for undo. Shapes share their textures and implicit
+//functions through Arc, so taking one is cheap.
+#[derive(Clone)]
+pub struct WorldSnapshot {
+    world: World,
 }
 
 impl World {
     pub fn new() -> Self {
         Self {
-            light: Light::point_light(
+            lights: vec![Light::point_light(
                 RayTuple::point(-10.0, 10.0, -10.0),
                 Color::new(1.0, 1.0, 1.0),
-            ),
+            )],
             objects: Vec::new(),
+            fog: None,
+            normalize_lighting: false,
+            occlusion: None,
+            max_reflection_depth: MAX_RECURSION_DEPTH,
+            max_refraction_depth: MAX_RECURSION_DEPTH,
+            skybox: None,
+            shadow_light_offset: 0.0,
+            sample_seed: 0,
         }
     }
 
@@ -37,113 +78,563 @@ impl World {
         s2.transform = Matrix::scaling(0.5, 0.5, 0.5);
 
         Self {
-            light: Light::point_light(
+            lights: vec![Light::point_light(
                 RayTuple::point(-10.0, 10.0, -10.0),
                 Color::new(1.0, 1.0, 1.0),
-            ),
+            )],
             objects: vec![s1, s2],
+            fog: None,
+            normalize_lighting: false,
+            occlusion: None,
+            max_reflection_depth: MAX_RECURSION_DEPTH,
+            max_refraction_depth: MAX_RECURSION_DEPTH,
+            skybox: None,
+            shadow_light_offset: 0.0,
+            sample_seed: 0,
         }
     }
 
-    pub fn intersect_world(&mut self, r: Ray) -> Vec<Intersection> {
-        let mut resulting_intersections: Vec<Intersection> = Vec::new();
+    //Loads the six faces in +x, -x, +y, -y, +z, -z order
+    pub fn set_skybox(&mut self, paths: [&str; 6]) -> io::Result<()> {
+        let mut faces = Vec::with_capacity(6);
+        for path in paths {
+            faces.push(Canvas::load_ppm(path)?);
+        }
+        let faces: [Canvas; 6] = faces.try_into().unwrap();
+        self.skybox = Some(Arc::new(faces));
+        Ok(())
+    }
+
+    //Color the skybox shows in a direction, black without one. The face is picked by the
+    //largest axis and the other two span it, looking at the face from inside the cube.
+    pub fn sky_color(&self, direction: RayTuple) -> Color {
+        let faces = match &self.skybox {
+            Some(faces) => faces,
+            None => return Color::new(0.0, 0.0, 0.0),
+        };
+
+        let (x, y, z) = (direction.x, direction.y, direction.z);
+        let (face, major, s, t) = if x.abs() >= y.abs() && x.abs() >= z.abs() {
+            if x > 0.0 {
+                (0, x, -z, -y)
+            } else {
+                (1, x, z, -y)
+            }
+        } else if y.abs() >= z.abs() {
+            if y > 0.0 {
+                (2, y, x, z)
+            } else {
+                (3, y, x, -z)
+            }
+        } else if z > 0.0 {
+            (4, z, x, -y)
+        } else {
+            (5, z, -x, -y)
+        };
+
+        let image = &faces[face];
+        let u = (s / major.abs() + 1.0) / 2.0;
+        let v = (t / major.abs() + 1.0) / 2.0;
+        let px = ((u * image.get_width() as f64) as i32).clamp(0, image.get_width() - 1);
+        let py = ((v * image.get_height() as f64) as i32).clamp(0, image.get_height() - 1);
+        image.pixel_at(px, py)
+    }
+
+    pub fn snapshot(&self) -> WorldSnapshot {
+        WorldSnapshot {
+            world: self.clone(),
+        }
+    }
+
+    pub fn restore(&mut self, snapshot: WorldSnapshot) {
+        *self = snapshot.world;
+    }
+
+    //Appends other's objects and lights. Parent indices are shifted to the objects'
+    //new positions. Everything else (fog, skybox, depths, occlusion) stays as self has it.
+    pub fn merge(&mut self, other: World) {
+        let offset = self.objects.len();
+        self.objects.extend(other.objects.into_iter().map(|mut o| {
+            o.parent = o.parent.map(|p| p + offset);
+            o
+        }));
+        self.lights.extend(other.lights);
+    }
+
+    //Renders a copy of the world holding only the object with this id, lit by every
+    //light. Alpha is the coverage mask, so pixels off the object are transparent.
+    //Letterbox bars are opaque, as in Camera::render_rgba.
+    pub fn render_only(&self, camera: &Camera, id: Uuid) -> CanvasRgba {
+        let mut isolated = self.clone();
+        isolated.objects.retain(|o| o.id() == id);
+
+        let mut image = CanvasRgba::new(camera.hsize(), camera.vsize());
+        for y in 0..camera.vsize() {
+            for x in 0..camera.hsize() {
+                if let Some(bar) = camera.letterbox_color(x, y) {
+                    image.write_pixel(x, y, bar, 1.0);
+                    continue;
+                }
+                let ray = camera.ray_for_pixel(x, y);
+                let (color, alpha) = isolated.color_at_coverage(ray, camera.max_depth);
+                image.write_pixel(x, y, color, alpha);
+            }
+        }
+        image
+    }
+
+    //World space box around every object. Like intersection, it uses each object's own
+    //transform and not its parents'.
+    pub fn bounds(&self) -> BoundingBox {
+        let mut result = BoundingBox::empty();
+        for o in &self.objects {
+            result.merge(o.bounds().transform(o.transform));
+        }
+        result
+    }
 
+    //Call once the scene is built, every object keeps its inverse transpose for shading
+    pub fn cache_normal_transforms(&mut self) {
         for o in &mut self.objects {
+            o.cache_normal_transforms();
+        }
+    }
+
+    pub fn intersect_world(&self, r: Ray) -> Vec<Intersection<'_>> {
+        let mut resulting_intersections: Vec<Intersection> = Vec::new();
+
+        for o in &self.objects {
             let mut xs = o.intersect(r);
+            #[cfg(feature = "logging")]
+            log::debug!("object {} intersections: {}", o.id(), xs.len());
             resulting_intersections.append(&mut xs);
         }
-        resulting_intersections.sort_by(|a, b| {
-            if a.t < b.t {
-                Ordering::Less
-            } else if a.t == b.t {
-                Ordering::Equal
-            } else {
-                Ordering::Greater
-            }
-        });
-        return resulting_intersections;
+        resulting_intersections.sort_by(|a, b| Intersection::order(r, a, b));
+        resulting_intersections
     }
 
-    pub fn shade_hit(&mut self, comps: Computations, remaining: i32) -> Color {
-        let shadowed = self.is_shadowed(comps.over_point);
+    //Same hit as Intersection::hit(intersect_world(r)) without collecting and sorting
+    //every intersection
+    pub fn nearest_hit(&self, r: Ray) -> Option<Intersection<'_>> {
+        self.intersect_world_bounded(r, 1).pop()
+    }
 
-        let surface = comps.object.material.lighting(
-            comps.object,
-            &self.light,
-            comps.over_point,
-            comps.eyev,
-            comps.normalv,
-            shadowed,
-        );
+    //Keeps only the `capacity` nearest intersections in front of the ray origin, sorted.
+    //Objects that can't get closer than the ones already kept are never intersected.
+    pub fn intersect_world_bounded(&self, r: Ray, capacity: usize) -> Vec<Intersection<'_>> {
+        let mut nearest = NearestHits::new(r, capacity);
+        for o in &self.objects {
+            nearest.offer(o);
+        }
+        nearest.into_hits()
+    }
+
+    //The nearest hit after everything behind the ray origin, so prepare_computations sees
+    //the shapes the ray starts inside of
+    fn intersect_to_hit(&self, r: Ray) -> Vec<Intersection<'_>> {
+        let mut nearest = NearestHits::with_behind(r, 1);
+        for o in &self.objects {
+            nearest.offer(o);
+        }
+        nearest.into_hits()
+    }
+
+    pub fn shade_hit(&self, comps: Computations, remaining: i32) -> Color {
+        let (reflect_remaining, refract_remaining) = self.depths(remaining);
+        self.shade_hit_with_depths(comps, reflect_remaining, refract_remaining)
+    }
+
+    //Both budgets start from `remaining`, capped by the world's own limits
+    fn depths(&self, remaining: i32) -> (i32, i32) {
+        let remaining = remaining.min(MAX_RECURSION_DEPTH);
+        (
+            remaining.min(self.max_reflection_depth),
+            remaining.min(self.max_refraction_depth),
+        )
+    }
+
+    //Reflection rays only spend reflect_remaining and refraction rays only refract_remaining
+    pub fn shade_hit_with_depths(
+        &self,
+        comps: Computations,
+        reflect_remaining: i32,
+        refract_remaining: i32,
+    ) -> Color {
+        //the back material, when set, only replaces the surface lighting
+        let surface_material = match &comps.object.material.back_material {
+            Some(back) if comps.inside => back,
+            _ => &comps.object.material,
+        };
+
+        let occlusion = match self.occlusion {
+            Some((samples, radius)) => {
+                self.ambient_occlusion(comps.over_point, comps.normalv, samples, radius)
+            }
+            None => 1.0,
+        };
+
+        let mut surface = Color::new(0.0, 0.0, 0.0);
+        for light in &self.lights {
+            let intensity = self.intensity_at(light, comps.over_point, comps.time);
+            let terms = surface_material.lighting_components(
+                comps.object,
+                light,
+                comps.over_point,
+                comps.eyev,
+                comps.normalv,
+                false,
+            );
+
+            //partially lit points scale only the direct terms, ambient is darkened by occlusion
+            surface =
+                surface + terms.ambient * occlusion + (terms.diffuse + terms.specular) * intensity;
+        }
+        //averaging keeps exposure steady as fill lights are added
+        if self.normalize_lighting && self.lights.len() > 1 {
+            surface = surface * (1.0 / self.lights.len() as f64);
+        }
+        surface = surface + surface_material.emission;
 
-        let reflected = self.reflected_color(comps, remaining);
-        let refracted = self.refracted_color(comps, remaining);
+        let reflected =
+            self.reflected_color_with_depths(comps, reflect_remaining, refract_remaining);
+        let refracted =
+            self.refracted_color_with_depths(comps, reflect_remaining, refract_remaining);
 
-        let material = comps.object.material;
-        if material.reflective > 0.0 && material.transparency > 0.0 {
+        let material = &comps.object.material;
+        let color = if material.reflective > 0.0 && material.transparency > 0.0 {
             let reflectance = Intersection::schlick(comps);
-            return surface + reflected * reflectance + refracted * (1.0 - reflectance);
+            surface + reflected * reflectance + refracted * (1.0 - reflectance)
+        } else {
+            surface + reflected + refracted
+        };
+
+        self.apply_fog(color, comps.t)
+    }
+
+    //Exponential distance fog, blends toward the fog color the further the hit is
+    fn apply_fog(&self, color: Color, distance: f64) -> Color {
+        match self.fog {
+            Some((fog_color, density)) => {
+                let fog_amount = 1.0 - (-density * distance).exp();
+                color * (1.0 - fog_amount) + fog_color * fog_amount
+            }
+            None => color,
         }
+    }
+
+    pub fn color_at(&self, r: Ray, remaining: i32) -> Color {
+        self.color_at_coverage(r, remaining).0
+    }
+
+    //Like color_at but also reports coverage, 1.0 when the ray hit something and 0.0 when it missed
+    pub fn color_at_coverage(&self, r: Ray, remaining: i32) -> (Color, f64) {
+        let (reflect_remaining, refract_remaining) = self.depths(remaining);
+        let (color, hit_t) = self.color_at_with_depths(r, reflect_remaining, refract_remaining);
+        (color, if hit_t.is_some() { 1.0 } else { 0.0 })
+    }
 
-        surface + reflected + refracted
+    //Shaded color plus the albedo and world normal at the first hit, the auxiliary
+    //buffers a denoiser wants. The color is color_at's for the same `remaining`.
+    //Misses give the sky, black and a zero vector.
+    pub fn color_and_aux_at(&self, r: Ray, remaining: i32) -> (Color, Color, RayTuple) {
+        let xs = self.intersect_to_hit(r);
+        let hit = match Intersection::hit_with_context(&xs) {
+            Some((hit, _)) => hit,
+            None => {
+                return (
+                    self.sky_color(r.direction),
+                    Color::new(0.0, 0.0, 0.0),
+                    RayTuple::vector(0.0, 0.0, 0.0),
+                )
+            }
+        };
+        let comps = hit.prepare_computations(r, &xs);
+        let material = match &comps.object.material.back_material {
+            Some(back) if comps.inside => back,
+            _ => &comps.object.material,
+        };
+        let albedo =
+            material.surface_color(comps.object, comps.over_point, comps.eyev, comps.normalv);
+        let normal = comps.normalv;
+
+        let (reflect_remaining, refract_remaining) = self.depths(remaining);
+        let color = self.shade_hit_with_depths(comps, reflect_remaining, refract_remaining);
+        (color, albedo, normal)
     }
 
-    pub fn color_at(&mut self, r: Ray, remaining: i32) -> Color {
-        let xs = self.intersect_world(r);
-        let option_hit = Intersection::hit(xs);
-        if let Some(hit) = option_hit {
-            let dummyxs: Vec<Intersection> = Vec::new(); //this is to fix refraction update
-            let comps = hit.prepare_computations(r, dummyxs);
-            self.shade_hit(comps, remaining)
+    //Also gives the t of the hit, so callers that care how far the ray went don't
+    //have to trace it again
+    fn color_at_with_depths(
+        &self,
+        r: Ray,
+        reflect_remaining: i32,
+        refract_remaining: i32,
+    ) -> (Color, Option<f64>) {
+        #[cfg(feature = "logging")]
+        log::debug!(
+            "color_at with {} reflections and {} refractions remaining",
+            reflect_remaining,
+            refract_remaining
+        );
+        let xs = self.intersect_to_hit(r);
+        if let Some((hit, _)) = Intersection::hit_with_context(&xs) {
+            let comps = hit.prepare_computations(r, &xs);
+            (
+                self.shade_hit_with_depths(comps, reflect_remaining, refract_remaining),
+                Some(hit.t),
+            )
         } else {
-            return Color::new(0.0, 0.0, 0.0);
+            (self.sky_color(r.direction), None)
         }
     }
 
-    pub fn is_shadowed(&mut self, p: RayTuple) -> bool {
-        let v = self.light.position - p;
-        let distance = v.magnitude();
-        let direction = v.normalize();
+    //Path traced color: direct light from every light plus one cosine weighted diffuse
+    //bounce per hit, followed for `depth` bounces. Average many calls for a clean image.
+    pub fn color_at_gi(&self, r: Ray, depth: i32, rng: &mut Rng) -> Color {
+        let xs = self.intersect_to_hit(r);
+        let hit = match Intersection::hit_with_context(&xs) {
+            Some((hit, _)) => hit,
+            None => return self.sky_color(r.direction),
+        };
+        let comps = hit.prepare_computations(r, &xs);
+        let material = match &comps.object.material.back_material {
+            Some(back) if comps.inside => back,
+            _ => &comps.object.material,
+        };
+
+        //ambient is left out, the bounce gathers the light it stands in for
+        let mut color = material.emission;
+        for light in &self.lights {
+            let intensity = self.intensity_at(light, comps.over_point, comps.time);
+            let terms = material.lighting_components(
+                comps.object,
+                light,
+                comps.over_point,
+                comps.eyev,
+                comps.normalv,
+                false,
+            );
+            color = color + (terms.diffuse + terms.specular) * intensity;
+        }
+
+        //cosine weighted directions cancel the Lambert term, so the bounce is just
+        //scaled by the surface's albedo
+        if depth > 0 && material.diffuse > 0.0 {
+            let direction = sampling::cosine_weighted_hemisphere(comps.normalv, rng);
+            let bounce = Ray::new(comps.over_point, direction).with_time(comps.time);
+            let albedo =
+                material.surface_color(comps.object, comps.over_point, comps.eyev, comps.normalv)
+                    * material.diffuse;
+            color = color + albedo * self.color_at_gi(bounce, depth - 1, rng);
+        }
+        color
+    }
+
+    //Fraction of hemisphere rays around the normal that travel `radius` without hitting
+    //anything. Directions come from a generator seeded by the point, so renders repeat.
+    pub fn ambient_occlusion(
+        &self,
+        point: RayTuple,
+        normal: RayTuple,
+        samples: usize,
+        radius: f64,
+    ) -> f64 {
+        if samples == 0 {
+            return 1.0;
+        }
+
+        let mut rng = Rng::new(self.point_seed(point));
+        let mut open = 0;
+        for _ in 0..samples {
+            let r = Ray::new(point, sampling::uniform_hemisphere(normal, &mut rng));
+            if !self.objects.iter().any(|o| o.is_hit(r, radius)) {
+                open += 1;
+            }
+        }
 
-        let r = Ray::new(p, direction);
-        let intersections = self.intersect_world(r);
+        open as f64 / samples as f64
+    }
 
-        if let Some(hit) = Intersection::hit(intersections) {
-            if hit.t < distance {
-                return true;
+    //Direct light reaching a point on a surface facing `normal`, summed over every light
+    //with cosine falloff and shadows but no material, for finding dark spots
+    pub fn irradiance_at(&self, point: RayTuple, normal: RayTuple) -> Color {
+        let mut total = Color::new(0.0, 0.0, 0.0);
+        for light in &self.lights {
+            let cosine = (light.position - point).normalize().dot(normal);
+            if cosine > 0.0 {
+                total = total + light.intensity * (cosine * self.intensity_at(light, point, 0.0));
             }
         }
+        total
+    }
+
+    //Seed for sampled effects taken from a point's bits, nearby points still differ
+    fn point_seed(&self, point: RayTuple) -> u64 {
+        point.x.to_bits()
+            ^ point.y.to_bits().rotate_left(21)
+            ^ point.z.to_bits().rotate_left(42)
+            ^ self.sample_seed.wrapping_mul(0x9e37_79b9_7f4a_7c15)
+    }
+
+    //Fraction of the light's samples that reach the point, 0.0 outside a spotlight's cone
+    //Area light jitter is seeded by the point, so renders repeat.
+    pub fn intensity_at(&self, light: &Light, p: RayTuple, time: f64) -> f64 {
+        self.intensity_at_with(light, p, time, &mut Rng::new(self.point_seed(p)))
+    }
+
+    pub fn intensity_at_with(&self, light: &Light, p: RayTuple, time: f64, rng: &mut Rng) -> f64 {
+        if !light.illuminates(p) {
+            return 0.0;
+        }
+
+        let directions = light.sample_directions(p, rng);
+        let samples = directions.len();
+        let lit = directions
+            .into_iter()
+            .filter(|d| !self.is_shadowed(p + *d, p, time))
+            .count();
+
+        lit as f64 / samples as f64
+    }
+
+    //Whether any of light `light_index` reaches the point, for line of sight questions
+    //outside of shading. A light that doesn't exist lights nothing.
+    pub fn point_is_lit(&self, point: RayTuple, light_index: usize) -> bool {
+        self.lights
+            .get(light_index)
+            .is_some_and(|light| self.intensity_at(light, point, 0.0) > 0.0)
+    }
+
+    //Moving objects are checked where they are at `time`, the time of the ray being shaded
+    pub fn is_shadowed(&self, light_position: RayTuple, p: RayTuple, time: f64) -> bool {
+        let to_light = light_position - p;
+        let offset = self.shadow_light_offset.min(to_light.magnitude());
+        let p = p + to_light.normalize() * offset;
+        let distance = (light_position - p).magnitude();
+        let r = Ray::from_points(p, light_position).with_time(time);
+        #[cfg(feature = "logging")]
+        log::debug!("shadow test from {} over {}", p, distance);
+        self.objects.iter().any(|o| o.is_hit(r, distance))
+    }
+
+    pub fn reflected_color(&self, comps: Computations, remaining: i32) -> Color {
+        let (reflect_remaining, refract_remaining) = self.depths(remaining);
+        self.reflected_color_with_depths(comps, reflect_remaining, refract_remaining)
+    }
+
+    fn reflected_color_with_depths(
+        &self,
+        comps: Computations,
+        reflect_remaining: i32,
+        refract_remaining: i32,
+    ) -> Color {
+        if reflect_remaining < 1 || comps.object.material.reflective == 0.0 {
+            return Color::new(0.0, 0.0, 0.0);
+        }
+
+        let reflect_ray = Ray::new(comps.over_point, comps.reflectv).with_time(comps.time);
+        #[cfg(feature = "logging")]
+        log::debug!("reflection ray at depth {}", reflect_remaining);
+        let (color, hit_t) =
+            self.color_at_with_depths(reflect_ray, reflect_remaining - 1, refract_remaining);
+
+        color * comps.object.material.reflective * Self::reflection_fade(&comps, reflect_ray, hit_t)
+    }
+
+    //Misses count as infinitely far, so the sky fades out of a falloff reflection too
+    fn reflection_fade(comps: &Computations, reflect_ray: Ray, hit_t: Option<f64>) -> f64 {
+        let falloff = comps.object.material.reflective_falloff;
+        if falloff == 0.0 {
+            return 1.0;
+        }
+
+        match hit_t {
+            Some(t) => (-falloff * t * reflect_ray.direction.magnitude()).exp(),
+            None => 0.0,
+        }
+    }
 
-        false
+    pub fn refracted_color(&self, comps: Computations, remaining: i32) -> Color {
+        let (reflect_remaining, refract_remaining) = self.depths(remaining);
+        self.refracted_color_with_depths(comps, reflect_remaining, refract_remaining)
     }
 
-    pub fn reflected_color(&mut self, comps: Computations, remaining: i32) -> Color {
-        if remaining < 1 || comps.object.material.reflective == 0.0 {
+    fn refracted_color_with_depths(
+        &self,
+        comps: Computations,
+        reflect_remaining: i32,
+        refract_remaining: i32,
+    ) -> Color {
+        if refract_remaining < 1 || comps.object.material.transparency == 0.0 {
             return Color::new(0.0, 0.0, 0.0);
         }
+        #[cfg(feature = "logging")]
+        log::debug!("refraction ray at depth {}", refract_remaining);
+
+        //dispersive materials bend each channel by its own index, so trace one ray per channel
+        if comps.n1_channels != [comps.n1; 3] || comps.n2_channels != [comps.n2; 3] {
+            let mut channels = [0.0; 3];
+            for (c, channel) in channels.iter_mut().enumerate() {
+                if let Some(refract_ray) =
+                    Self::refract_ray(&comps, comps.n1_channels[c], comps.n2_channels[c])
+                {
+                    let (color, hit_t) = self.color_at_with_depths(
+                        refract_ray,
+                        reflect_remaining,
+                        refract_remaining - 1,
+                    );
+                    let color = color * Self::absorption(&comps, refract_ray, hit_t);
+                    *channel = [color.red, color.green, color.blue][c];
+                }
+            }
+
+            return Color::new(channels[0], channels[1], channels[2])
+                * comps.object.material.transparency;
+        }
 
-        let reflect_ray = Ray::new(comps.over_point, comps.reflectv);
-        let color = self.color_at(reflect_ray, remaining - 1);
+        match Self::refract_ray(&comps, comps.n1, comps.n2) {
+            Some(refract_ray) => {
+                let (color, hit_t) = self.color_at_with_depths(
+                    refract_ray,
+                    reflect_remaining,
+                    refract_remaining - 1,
+                );
+                color
+                    * Self::absorption(&comps, refract_ray, hit_t)
+                    * comps.object.material.transparency
+            }
+            None => Color::new(0.0, 0.0, 0.0),
+        }
+    }
+
+    //Tint from the refracted ray's trip through the object it just entered, measured
+    //to the next surface it meets at `hit_t`. Rays leaving an object travel through clear air.
+    fn absorption(comps: &Computations, refract_ray: Ray, hit_t: Option<f64>) -> Color {
+        let material = &comps.object.material;
+        let clear = Color::new(1.0, 1.0, 1.0);
+        if comps.inside || material.transmission_color == clear {
+            return clear;
+        }
 
-        color * comps.object.material.reflective
+        match hit_t {
+            Some(t) => material.transmittance(t * refract_ray.direction.magnitude()),
+            None => clear,
+        }
     }
 
-    pub fn refracted_color(&mut self, comps: Computations, remaining: i32) -> Color {
-        let n_ratio = comps.n1 / comps.n2;
+    //None under total internal reflection
+    fn refract_ray(comps: &Computations, n1: f64, n2: f64) -> Option<Ray> {
+        let n_ratio = n1 / n2;
         let cos_i = comps.eyev.dot(comps.normalv);
         let sin2_t = n_ratio.powf(2.0) * (1.0 - cos_i.powf(2.0));
 
-        if remaining == 0 || comps.object.material.transparency == 0.0 || sin2_t > 1.0 {
-            Color::new(0.0, 0.0, 0.0)
-        } else {
-            let cos_t = (1.0_f64 - sin2_t).sqrt();
-            let direction = comps.normalv * (n_ratio * cos_i - cos_t) - comps.eyev * n_ratio;
-            let refract_ray = Ray::new(comps.under_point, direction);
-
-            let color =
-                self.color_at(refract_ray, remaining - 1) * comps.object.material.transparency;
-            color
+        if sin2_t > 1.0 {
+            return None;
         }
+
+        let cos_t = (1.0_f64 - sin2_t).sqrt();
+        let direction = comps.normalv * (n_ratio * cos_i - cos_t) - comps.eyev * n_ratio;
+        Some(Ray::new(comps.under_point, direction).with_time(comps.time))
     }
 
     pub fn chapter_twelve_cube() {
@@ -212,6 +703,47 @@ impl World {
 mod tests {
     use super::*;
     use crate::{intersections, pattern::Pattern};
+    use std::f64::consts::{FRAC_1_SQRT_2, FRAC_PI_2};
+
+    //Keeps each test thread's log messages apart, since tests run in parallel
+    #[cfg(feature = "logging")]
+    mod capture {
+        use std::cell::RefCell;
+        use std::sync::Once;
+
+        thread_local! {
+            pub static MESSAGES: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+        }
+
+        struct CaptureLogger;
+
+        impl log::Log for CaptureLogger {
+            fn enabled(&self, _: &log::Metadata) -> bool {
+                true
+            }
+
+            fn log(&self, record: &log::Record) {
+                MESSAGES.with(|m| m.borrow_mut().push(record.args().to_string()));
+            }
+
+            fn flush(&self) {}
+        }
+
+        static LOGGER: CaptureLogger = CaptureLogger;
+        static INIT: Once = Once::new();
+
+        pub fn start() {
+            INIT.call_once(|| {
+                log::set_logger(&LOGGER).unwrap();
+                log::set_max_level(log::LevelFilter::Debug);
+            });
+            MESSAGES.with(|m| m.borrow_mut().clear());
+        }
+
+        pub fn taken() -> Vec<String> {
+            MESSAGES.with(|m| m.borrow_mut().drain(..).collect())
+        }
+    }
 
     //I've done some modifications to this test, since we are using UUID's in sphere initialization,
     //I only test to make sure the objects contain the non-default characteristics.
@@ -230,14 +762,14 @@ mod tests {
         s2.transform = Matrix::scaling(0.5, 0.5, 0.5);
         let w = World::default_world();
 
-        assert_eq!(w.light, l);
+        assert_eq!(w.lights, vec![l]);
         assert_eq!(w.objects[0].material, s1.material);
         assert_eq!(w.objects[1].transform, s2.transform);
     }
 
     #[test]
     fn intersect_world_with_ray() {
-        let mut w = World::default_world();
+        let w = World::default_world();
         let r = Ray::new(
             RayTuple::point(0.0, 0.0, -5.0),
             RayTuple::vector(0.0, 0.0, 1.0),
@@ -253,15 +785,15 @@ mod tests {
 
     #[test]
     fn shading_an_intersection() {
-        let mut w = World::default_world();
+        let w = World::default_world();
         let r = Ray::new(
             RayTuple::point(0.0, 0.0, -5.0),
             RayTuple::vector(0.0, 0.0, 1.0),
         );
-        let shape = w.objects[0];
+        let shape = &w.objects[0];
         let i = Intersection::new(4.0, shape);
         let dummyxs: Vec<Intersection> = Vec::new(); //this is to fix refraction update
-        let comps = i.prepare_computations(r, dummyxs);
+        let comps = i.prepare_computations(r, &dummyxs);
         let c = w.shade_hit(comps, 5);
 
         assert_eq!(c, Color::new(0.38066, 0.47583, 0.2855));
@@ -270,16 +802,19 @@ mod tests {
     #[test]
     fn shading_an_intersection_from_inside() {
         let mut w = World::default_world();
-        w.light = Light::point_light(RayTuple::point(0.0, 0.25, 0.0), Color::new(1.0, 1.0, 1.0));
+        w.lights = vec![Light::point_light(
+            RayTuple::point(0.0, 0.25, 0.0),
+            Color::new(1.0, 1.0, 1.0),
+        )];
 
         let r = Ray::new(
             RayTuple::point(0.0, 0.0, 0.0),
             RayTuple::vector(0.0, 0.0, 1.0),
         );
-        let shape = w.objects[1];
+        let shape = &w.objects[1];
         let i = Intersection::new(0.5, shape);
         let dummyxs: Vec<Intersection> = Vec::new(); //this is to fix refraction update
-        let comps = i.prepare_computations(r, dummyxs);
+        let comps = i.prepare_computations(r, &dummyxs);
         let c = w.shade_hit(comps, 5);
 
         assert_eq!(c, Color::new(0.90498, 0.90498, 0.90498));
@@ -287,7 +822,7 @@ mod tests {
 
     #[test]
     fn color_when_ray_misses() {
-        let mut w = World::default_world();
+        let w = World::default_world();
         let r = Ray::new(
             RayTuple::point(0.0, 0.0, -5.0),
             RayTuple::vector(0.0, 1.0, 0.0),
@@ -298,7 +833,7 @@ mod tests {
 
     #[test]
     fn color_when_ray_hits() {
-        let mut w = World::default_world();
+        let w = World::default_world();
         let r = Ray::new(
             RayTuple::point(0.0, 0.0, -5.0),
             RayTuple::vector(0.0, 0.0, 1.0),
@@ -323,40 +858,43 @@ mod tests {
 
     #[test]
     fn there_is_no_shadow() {
-        let mut w = World::default_world();
+        let w = World::default_world();
         let p = RayTuple::point(0.0, 10.0, 0.0);
 
-        assert!(!w.is_shadowed(p));
+        assert!(!w.is_shadowed(w.lights[0].position, p, 0.0));
     }
 
     #[test]
     fn there_is_shadow() {
-        let mut w = World::default_world();
+        let w = World::default_world();
         let p = RayTuple::point(10.0, -10.0, 10.0);
 
-        assert!(w.is_shadowed(p));
+        assert!(w.is_shadowed(w.lights[0].position, p, 0.0));
     }
 
     #[test]
     fn there_is_no_shadow_object_behind_light() {
-        let mut w = World::default_world();
+        let w = World::default_world();
         let p = RayTuple::point(-20.0, 20.0, -20.0);
 
-        assert!(!w.is_shadowed(p));
+        assert!(!w.is_shadowed(w.lights[0].position, p, 0.0));
     }
 
     #[test]
     fn there_is_no_shadow_point_infront() {
-        let mut w = World::default_world();
+        let w = World::default_world();
         let p = RayTuple::point(-2.0, 2.0, -2.0);
 
-        assert!(!w.is_shadowed(p));
+        assert!(!w.is_shadowed(w.lights[0].position, p, 0.0));
     }
 
     #[test]
     fn shade_hit_is_given_intersection_in_shadow() {
         let mut w = World::new();
-        w.light = Light::point_light(RayTuple::point(0.0, 0.0, -10.0), Color::new(1.0, 1.0, 1.0));
+        w.lights = vec![Light::point_light(
+            RayTuple::point(0.0, 0.0, -10.0),
+            Color::new(1.0, 1.0, 1.0),
+        )];
         let s1 = Shape::new(ShapeType::Sphere);
         w.objects.push(s1);
 
@@ -368,10 +906,10 @@ mod tests {
             RayTuple::point(0.0, 0.0, 5.0),
             RayTuple::vector(0.0, 0.0, 1.0),
         );
-        let i = Intersection::new(4.0, s2);
+        let i = Intersection::new(4.0, &w.objects[1]);
 
         let dummyxs: Vec<Intersection> = Vec::new(); //this is to fix refraction update
-        let comps = i.prepare_computations(r, dummyxs);
+        let comps = i.prepare_computations(r, &dummyxs);
         let c = w.shade_hit(comps, 5);
 
         assert_eq!(c, Color::new(0.1, 0.1, 0.1));
@@ -385,9 +923,9 @@ mod tests {
             RayTuple::vector(0.0, 0.0, 1.0),
         );
         w.objects[1].material.ambient = 1.0;
-        let i = Intersection::new(1.0, w.objects[1]);
+        let i = Intersection::new(1.0, &w.objects[1]);
         let dummyxs: Vec<Intersection> = Vec::new(); //this is to fix refraction update
-        let comps = i.prepare_computations(r, dummyxs);
+        let comps = i.prepare_computations(r, &dummyxs);
         let color = w.reflected_color(comps, 5);
 
         assert_eq!(color, Color::new(0.0, 0.0, 0.0));
@@ -404,9 +942,9 @@ mod tests {
             RayTuple::point(0.0, 0.0, -3.0),
             RayTuple::vector(0.0, -2.0_f64.sqrt() / 2.0, 2.0_f64.sqrt() / 2.0),
         );
-        let i = Intersection::new(2.0_f64.sqrt(), w.objects[2]);
+        let i = Intersection::new(2.0_f64.sqrt(), &w.objects[2]);
         let dummyxs: Vec<Intersection> = Vec::new(); //this is to fix refraction update
-        let comps = i.prepare_computations(r, dummyxs);
+        let comps = i.prepare_computations(r, &dummyxs);
         let color = w.reflected_color(comps, 5);
 
         assert_eq!(color, Color::new(0.19033, 0.23791, 0.14274));
@@ -423,9 +961,9 @@ mod tests {
             RayTuple::point(0.0, 0.0, -3.0),
             RayTuple::vector(0.0, -2.0_f64.sqrt() / 2.0, 2.0_f64.sqrt() / 2.0),
         );
-        let i = Intersection::new(2.0_f64.sqrt(), w.objects[2]);
+        let i = Intersection::new(2.0_f64.sqrt(), &w.objects[2]);
         let dummyxs: Vec<Intersection> = Vec::new(); //this is to fix refraction update
-        let comps = i.prepare_computations(r, dummyxs);
+        let comps = i.prepare_computations(r, &dummyxs);
         let color = w.shade_hit(comps, 5);
 
         assert_eq!(color, Color::new(0.87675, 0.92434, 0.82917));
@@ -434,7 +972,10 @@ mod tests {
     #[test]
     fn color_at_with_mutually_reflective_surfaces() {
         let mut w = World::new();
-        w.light = Light::point_light(RayTuple::point(0.0, 0.0, 0.0), Color::new(1.0, 1.0, 1.0));
+        w.lights = vec![Light::point_light(
+            RayTuple::point(0.0, 0.0, 0.0),
+            Color::new(1.0, 1.0, 1.0),
+        )];
 
         let mut lower = Shape::plane();
         lower.material.reflective = 1.0;
@@ -466,9 +1007,9 @@ mod tests {
             RayTuple::point(0.0, 0.0, -3.0),
             RayTuple::vector(0.0, -2.0_f64.sqrt() / 2.0, 2.0_f64.sqrt() / 2.0),
         );
-        let i = Intersection::new(2.0_f64.sqrt(), shape);
+        let i = Intersection::new(2.0_f64.sqrt(), &w.objects[2]);
         let dummyxs: Vec<Intersection> = Vec::new(); //this is to fix refraction update
-        let comps = i.prepare_computations(r, dummyxs);
+        let comps = i.prepare_computations(r, &dummyxs);
         let color = w.reflected_color(comps, 0);
 
         assert_eq!(color, Color::new(0.0, 0.0, 0.0));
@@ -476,14 +1017,14 @@ mod tests {
 
     #[test]
     fn refracted_color_of_opaque_surface() {
-        let mut w = World::default_world();
-        let s = w.objects[0];
+        let w = World::default_world();
+        let s = &w.objects[0];
         let r = Ray::new(
             RayTuple::point(0.0, 0.0, -5.0),
             RayTuple::vector(0.0, 0.0, 1.0),
         );
         let xs = intersections!(Intersection::new(4.0, s), Intersection::new(6.0, s));
-        let comps = xs[0].prepare_computations(r, xs);
+        let comps = xs[0].prepare_computations(r, &xs);
         let c = w.refracted_color(comps, 5);
 
         assert_eq!(c, Color::new(0.0, 0.0, 0.0));
@@ -499,8 +1040,11 @@ mod tests {
             RayTuple::point(0.0, 0.0, -5.0),
             RayTuple::vector(0.0, 0.0, 1.0),
         );
-        let xs = intersections!(Intersection::new(4.0, *s), Intersection::new(6.0, *s));
-        let comps = xs[0].prepare_computations(r, xs);
+        let xs = intersections!(
+            Intersection::new(4.0, &w.objects[0]),
+            Intersection::new(6.0, &w.objects[0])
+        );
+        let comps = xs[0].prepare_computations(r, &xs);
         let c = w.refracted_color(comps, 0);
 
         assert_eq!(c, Color::new(0.0, 0.0, 0.0));
@@ -518,11 +1062,11 @@ mod tests {
             RayTuple::vector(0.0, 1.0, 0.0),
         );
         let xs = intersections!(
-            Intersection::new(-2.0_f64.sqrt() / 2.0, *s),
-            Intersection::new(2.0_f64.sqrt() / 2.0, *s)
+            Intersection::new(-2.0_f64.sqrt() / 2.0, &w.objects[0]),
+            Intersection::new(2.0_f64.sqrt() / 2.0, &w.objects[0])
         );
 
-        let comps = xs[1].prepare_computations(r, xs);
+        let comps = xs[1].prepare_computations(r, &xs);
         let c = w.refracted_color(comps, 5);
 
         assert_eq!(c, Color::new(0.0, 0.0, 0.0));
@@ -543,18 +1087,77 @@ mod tests {
             RayTuple::vector(0.0, 1.0, 0.0),
         );
         let xs = intersections!(
-            Intersection::new(-0.9899, w.objects[0]),
-            Intersection::new(-0.4899, w.objects[1]),
-            Intersection::new(0.4899, w.objects[1]),
-            Intersection::new(0.9899, w.objects[0])
+            Intersection::new(-0.9899, &w.objects[0]),
+            Intersection::new(-0.4899, &w.objects[1]),
+            Intersection::new(0.4899, &w.objects[1]),
+            Intersection::new(0.9899, &w.objects[0])
         );
-        let comps = xs[2].prepare_computations(r, xs);
+        let comps = xs[2].prepare_computations(r, &xs);
         let c = w.refracted_color(comps, 5);
 
         //colors slightly adjusted for rounded book values
         assert_eq!(c, Color::new(0.0, 0.99887, 0.04721));
     }
 
+    #[test]
+    fn refracted_color_with_equal_channel_indices() {
+        let mut w = World::default_world();
+
+        w.objects[0].material.ambient = 1.0;
+        w.objects[0].material.pattern = Some(Pattern::test_pattern());
+
+        w.objects[1].material.transparency = 1.0;
+        w.objects[1].material.refractive_index = 1.5;
+        w.objects[1].material.ior_r = Some(1.5);
+        w.objects[1].material.ior_g = Some(1.5);
+        w.objects[1].material.ior_b = Some(1.5);
+
+        let r = Ray::new(
+            RayTuple::point(0.0, 0.0, 0.1),
+            RayTuple::vector(0.0, 1.0, 0.0),
+        );
+        let xs = intersections!(
+            Intersection::new(-0.9899, &w.objects[0]),
+            Intersection::new(-0.4899, &w.objects[1]),
+            Intersection::new(0.4899, &w.objects[1]),
+            Intersection::new(0.9899, &w.objects[0])
+        );
+        let comps = xs[2].prepare_computations(r, &xs);
+        let c = w.refracted_color(comps, 5);
+
+        assert_eq!(c, Color::new(0.0, 0.99887, 0.04721));
+    }
+
+    #[test]
+    fn refracted_color_with_dispersion_splits_channels() {
+        let mut w = World::default_world();
+
+        w.objects[0].material.ambient = 1.0;
+        w.objects[0].material.pattern = Some(Pattern::test_pattern());
+
+        w.objects[1].material.transparency = 1.0;
+        w.objects[1].material.refractive_index = 1.5;
+        w.objects[1].material.ior_r = Some(1.3);
+        w.objects[1].material.ior_b = Some(1.7);
+
+        let r = Ray::new(
+            RayTuple::point(0.0, 0.0, 0.1),
+            RayTuple::vector(0.0, 1.0, 0.0),
+        );
+        let xs = intersections!(
+            Intersection::new(-0.9899, &w.objects[0]),
+            Intersection::new(-0.4899, &w.objects[1]),
+            Intersection::new(0.4899, &w.objects[1]),
+            Intersection::new(0.9899, &w.objects[0])
+        );
+        let comps = xs[2].prepare_computations(r, &xs);
+        let c = w.refracted_color(comps, 5);
+
+        //green still follows the single index path, blue is bent somewhere else
+        assert!((c.green - 0.99887).abs() < 0.0001);
+        assert!((c.blue - 0.04721).abs() > 0.0001);
+    }
+
     #[test]
     fn shade_hit_with_transparent_material() {
         let mut w = World::default_world();
@@ -574,9 +1177,9 @@ mod tests {
             RayTuple::point(0.0, 0.0, -3.0),
             RayTuple::vector(0.0, -2.0_f64.sqrt() / 2.0, 2.0_f64.sqrt() / 2.0),
         );
-        let xs = intersections!(Intersection::new(2.0_f64.sqrt(), floor));
+        let xs = intersections!(Intersection::new(2.0_f64.sqrt(), &w.objects[2]));
 
-        let comps = xs[0].prepare_computations(r, xs);
+        let comps = xs[0].prepare_computations(r, &xs);
         let color = w.shade_hit(comps, 5);
 
         assert_eq!(color, Color::new(0.93642, 0.68642, 0.68642));
@@ -603,10 +1206,960 @@ mod tests {
         ball.transform = Matrix::translation(0.0, -3.5, -0.5);
         w.objects.push(ball);
 
-        let xs = intersections!(Intersection::new(2.0_f64.sqrt(), floor));
-        let comps = xs[0].prepare_computations(r, xs);
+        let xs = intersections!(Intersection::new(2.0_f64.sqrt(), &w.objects[2]));
+        let comps = xs[0].prepare_computations(r, &xs);
         let color = w.shade_hit(comps, 5);
 
         assert_eq!(color, Color::new(0.93391, 0.69643, 0.69243));
     }
+
+    #[test]
+    fn normalized_lighting_averages_light_contributions() {
+        let key = Light::point_light(
+            RayTuple::point(-10.0, 10.0, -10.0),
+            Color::new(1.0, 1.0, 1.0),
+        );
+        let fill = Light::point_light(
+            RayTuple::point(10.0, 10.0, -10.0),
+            Color::new(0.5, 0.5, 0.5),
+        );
+        let r = Ray::new(
+            RayTuple::point(0.0, 0.0, -5.0),
+            RayTuple::vector(0.0, 0.0, 1.0),
+        );
+
+        let mut w = World::default_world();
+        w.lights = vec![key];
+        let key_only = w.color_at(r, 5);
+        w.lights = vec![fill];
+        let fill_only = w.color_at(r, 5);
+
+        w.lights = vec![key, fill];
+        assert_eq!(w.color_at(r, 5), key_only + fill_only);
+
+        w.normalize_lighting = true;
+        assert_eq!(w.color_at(r, 5), (key_only + fill_only) * 0.5);
+    }
+
+    #[test]
+    fn spot_light_with_radius_softens_shadow_edge() {
+        let mut w = World::new();
+        let mut blocker = Shape::cube();
+        blocker.transform = Matrix::translation(-10.0, 5.0, 0.0) * Matrix::scaling(10.0, 0.1, 10.0);
+        w.objects.push(blocker);
+
+        let hard = Light::spot_light(
+            RayTuple::point(0.0, 10.0, 0.0),
+            RayTuple::vector(0.0, -1.0, 0.0),
+            FRAC_PI_3,
+            0.0,
+            Color::new(1.0, 1.0, 1.0),
+        );
+        assert_eq!(
+            w.intensity_at(&hard, RayTuple::point(0.2, 0.0, 0.0), 0.0),
+            1.0
+        );
+        assert_eq!(
+            w.intensity_at(&hard, RayTuple::point(-0.2, 0.0, 0.0), 0.0),
+            0.0
+        );
+
+        let soft = Light::spot_light(
+            RayTuple::point(0.0, 10.0, 0.0),
+            RayTuple::vector(0.0, -1.0, 0.0),
+            FRAC_PI_3,
+            1.0,
+            Color::new(1.0, 1.0, 1.0),
+        );
+        let edge = w.intensity_at(&soft, RayTuple::point(0.0, 0.0, 0.0), 0.0);
+        assert!(edge > 0.0 && edge < 1.0);
+        assert_eq!(
+            w.intensity_at(&soft, RayTuple::point(5.0, 0.0, 0.0), 0.0),
+            1.0
+        );
+        assert_eq!(
+            w.intensity_at(&soft, RayTuple::point(-5.0, 0.0, 0.0), 0.0),
+            0.0
+        );
+    }
+
+    #[test]
+    fn stratified_area_light_sampling_has_less_noise() {
+        let mut w = World::new();
+        let mut blocker = Shape::cube();
+        blocker.transform = Matrix::translation(-10.0, 5.0, 0.0) * Matrix::scaling(10.0, 0.1, 10.0);
+        w.objects.push(blocker);
+
+        let random = Light::area_light(
+            RayTuple::point(-1.0, 10.0, -1.0),
+            RayTuple::vector(2.0, 0.0, 0.0),
+            4,
+            RayTuple::vector(0.0, 0.0, 2.0),
+            4,
+            Color::new(1.0, 1.0, 1.0),
+        );
+        let mut stratified = random;
+        stratified.set_stratified(true);
+
+        assert_eq!(
+            w.intensity_at(&stratified, RayTuple::point(5.0, 0.0, 0.0), 0.0),
+            1.0
+        );
+
+        let variance = |light: &Light| {
+            let mut rng = Rng::new(3);
+            let edge = RayTuple::point(0.0, 0.0, 0.0);
+            let samples: Vec<f64> = (0..200)
+                .map(|_| w.intensity_at_with(light, edge, 0.0, &mut rng))
+                .collect();
+            let mean = samples.iter().sum::<f64>() / samples.len() as f64;
+            samples.iter().map(|s| (s - mean).powi(2)).sum::<f64>() / samples.len() as f64
+        };
+        assert!(variance(&stratified) < variance(&random));
+    }
+
+    #[test]
+    fn fog_pulls_distant_surfaces_toward_fog_color() {
+        let fog_color = Color::new(1.0, 1.0, 1.0);
+        let mut w = World::default_world();
+        w.fog = Some((fog_color, 0.05));
+
+        let near = w.color_at(
+            Ray::new(
+                RayTuple::point(0.0, 0.0, -5.0),
+                RayTuple::vector(0.0, 0.0, 1.0),
+            ),
+            5,
+        );
+        let far = w.color_at(
+            Ray::new(
+                RayTuple::point(0.0, 0.0, -50.0),
+                RayTuple::vector(0.0, 0.0, 1.0),
+            ),
+            5,
+        );
+
+        let distance_to_fog = |c: Color| {
+            (fog_color.red - c.red).abs()
+                + (fog_color.green - c.green).abs()
+                + (fog_color.blue - c.blue).abs()
+        };
+        assert!(distance_to_fog(far) < distance_to_fog(near));
+        assert_ne!(near, Color::new(0.38066, 0.47583, 0.2855));
+    }
+
+    #[test]
+    fn bounded_intersection_keeps_only_the_front_hit() {
+        let mut w = World::new();
+        for i in 0..50 {
+            let mut s = Shape::sphere();
+            s.transform = Matrix::translation(0.0, 0.0, i as f64 * 3.0);
+            w.objects.push(s);
+        }
+        let r = Ray::new(
+            RayTuple::point(0.0, 0.0, -5.0),
+            RayTuple::vector(0.0, 0.0, 1.0),
+        );
+
+        assert_eq!(w.intersect_world(r).len(), 100);
+
+        let xs = w.intersect_world_bounded(r, 1);
+        assert_eq!(xs.len(), 1);
+        assert_eq!(xs[0].t, 4.0);
+        assert_eq!(*xs[0].object, w.objects[0]);
+    }
+
+    #[test]
+    fn plane_with_back_material_shades_each_side() {
+        let mut w = World::new();
+        w.lights = vec![
+            Light::point_light(RayTuple::point(0.0, 10.0, 0.0), Color::new(1.0, 1.0, 1.0)),
+            Light::point_light(RayTuple::point(0.0, -10.0, 0.0), Color::new(1.0, 1.0, 1.0)),
+        ];
+        let mut floor = Shape::plane();
+        floor.material.color = Color::new(0.0, 1.0, 0.0);
+        floor.material.specular = 0.0;
+        let mut back = floor.material.clone();
+        back.color = Color::new(1.0, 0.0, 0.0);
+        floor.material.back_material = Some(Box::new(back));
+        w.objects.push(floor);
+
+        let from_above = Ray::new(
+            RayTuple::point(0.0, 1.0, 0.0),
+            RayTuple::vector(0.0, -1.0, 0.0),
+        );
+        let from_below = Ray::new(
+            RayTuple::point(0.0, -1.0, 0.0),
+            RayTuple::vector(0.0, 1.0, 0.0),
+        );
+        let above = w.color_at(from_above, 5);
+        let below = w.color_at(from_below, 5);
+
+        assert!(above.green > 0.0 && above.red == 0.0);
+        assert!(below.red > 0.0 && below.green == 0.0);
+    }
+
+    #[test]
+    fn ambient_occlusion_on_open_plane_and_in_crevice() {
+        let mut w = World::new();
+        w.objects.push(Shape::plane());
+        let up = RayTuple::vector(0.0, 1.0, 0.0);
+
+        let open = w.ambient_occlusion(RayTuple::point(0.0, 0.0001, 0.0), up, 64, 2.0);
+        assert_eq!(open, 1.0);
+
+        let mut left = Shape::sphere();
+        left.transform = Matrix::translation(-1.05, 1.0, 0.0);
+        let mut right = Shape::sphere();
+        right.transform = Matrix::translation(1.05, 1.0, 0.0);
+        w.objects.push(left);
+        w.objects.push(right);
+
+        let crevice = w.ambient_occlusion(RayTuple::point(0.0, 0.0001, 0.0), up, 64, 2.0);
+        assert!(crevice < 0.8);
+    }
+
+    #[test]
+    fn occlusion_darkens_only_the_ambient_term() {
+        let mut w = World::default_world();
+        let r = Ray::new(
+            RayTuple::point(0.0, 0.0, -5.0),
+            RayTuple::vector(0.0, 0.0, 1.0),
+        );
+        let lit = w.color_at(r, 5);
+
+        //nothing else is near the front of the sphere, so occlusion changes nothing
+        w.occlusion = Some((16, 0.5));
+        assert_eq!(w.color_at(r, 5), lit);
+    }
+
+    #[test]
+    fn facing_mirrors_with_huge_depth_terminate() {
+        let mut w = World::new();
+        w.lights = vec![Light::point_light(
+            RayTuple::point(0.0, 0.0, 0.0),
+            Color::new(1.0, 1.0, 1.0),
+        )];
+
+        let mut lower = Shape::plane();
+        lower.material.reflective = 1.0;
+        lower.transform = Matrix::translation(0.0, -1.0, 0.0);
+        w.objects.push(lower);
+
+        let mut upper = Shape::plane();
+        upper.material.reflective = 1.0;
+        upper.transform = Matrix::translation(0.0, 1.0, 0.0);
+        w.objects.push(upper);
+
+        let r = Ray::new(
+            RayTuple::point(0.0, 0.0, 0.0),
+            RayTuple::vector(0.0, 1.0, 0.0),
+        );
+        let deep = w.color_at(r, 1_000_000);
+
+        assert_eq!(deep, w.color_at(r, MAX_RECURSION_DEPTH));
+    }
+
+    #[test]
+    fn camera_dimensions_are_readable_outside_camera() {
+        let c = Camera::new(160, 120, FRAC_PI_3);
+
+        assert_eq!(c.hsize(), 160);
+        assert_eq!(c.vsize(), 120);
+        assert_eq!(c.field_of_view(), FRAC_PI_3);
+        assert!(c.pixel_size() > 0.0);
+    }
+
+    #[test]
+    fn concentric_shells_refract_in_nesting_order() {
+        let mut shells = Vec::new();
+        for (radius, index) in [(3.0, 1.5), (2.0, 2.0), (1.0, 2.5)] {
+            let mut s = Shape::glass_sphere();
+            s.transform = Matrix::scaling(radius, radius, radius);
+            s.material.refractive_index = index;
+            shells.push(s);
+        }
+        let r = Ray::new(
+            RayTuple::point(0.0, 0.0, -5.0),
+            RayTuple::vector(0.0, 0.0, 1.0),
+        );
+        let expected = [
+            (1.0, 1.5),
+            (1.5, 2.0),
+            (2.0, 2.5),
+            (2.5, 2.0),
+            (2.0, 1.5),
+            (1.5, 1.0),
+        ];
+
+        for order in [[0, 1, 2], [2, 0, 1], [1, 2, 0]] {
+            let mut w = World::new();
+            for i in order {
+                w.objects.push(shells[i].clone());
+            }
+            let xs = w.intersect_world(r);
+            assert_eq!(xs.len(), 6);
+            for (i, (n1, n2)) in expected.iter().enumerate() {
+                let comps = xs[i].prepare_computations(r, &xs);
+                assert_eq!((comps.n1, comps.n2), (*n1, *n2));
+            }
+        }
+    }
+
+    #[test]
+    fn coincident_boundary_enters_before_it_exits() {
+        let mut a = Shape::cube();
+        a.material.transparency = 1.0;
+        a.material.refractive_index = 1.5;
+        let mut b = Shape::cube();
+        b.transform = Matrix::translation(2.0, 0.0, 0.0);
+        b.material.transparency = 1.0;
+        b.material.refractive_index = 2.0;
+        let r = Ray::new(
+            RayTuple::point(-5.0, 0.0, 0.0),
+            RayTuple::vector(1.0, 0.0, 0.0),
+        );
+
+        for objects in [vec![a.clone(), b.clone()], vec![b.clone(), a.clone()]] {
+            let mut w = World::new();
+            w.objects = objects;
+            let xs = w.intersect_world(r);
+
+            //at the shared face b is entered first, so light passes straight from a into b
+            assert_eq!(xs[1].t, 6.0);
+            assert_eq!(*xs[1].object, b);
+            let comps = xs[1].prepare_computations(r, &xs);
+            assert_eq!((comps.n1, comps.n2), (1.5, 2.0));
+        }
+    }
+
+    #[cfg(feature = "logging")]
+    #[test]
+    fn only_reflective_surfaces_log_reflection_rays() {
+        let reflection_events = |reflective: f64| {
+            let mut w = World::new();
+            let mut sphere = Shape::sphere();
+            sphere.material.reflective = reflective;
+            w.objects.push(sphere);
+            let r = Ray::new(
+                RayTuple::point(0.0, 0.0, -5.0),
+                RayTuple::vector(0.0, 0.0, 1.0),
+            );
+
+            capture::start();
+            w.color_at(r, 5);
+            let messages = capture::taken();
+            assert!(messages.iter().any(|m| m.starts_with("shadow test")));
+            messages
+                .iter()
+                .filter(|m| m.starts_with("reflection ray"))
+                .count()
+        };
+
+        assert!(reflection_events(0.5) >= 1);
+        assert_eq!(reflection_events(0.0), 0);
+    }
+
+    #[test]
+    fn restoring_a_snapshot_undoes_edits() {
+        let mut w = World::default_world();
+        let original = w.objects[0].material.color;
+        let snapshot = w.snapshot();
+
+        w.objects[0].material.color = Color::new(1.0, 0.0, 0.0);
+        w.objects.push(Shape::plane());
+        w.lights.clear();
+
+        w.restore(snapshot);
+        assert_eq!(w.objects[0].material.color, original);
+        assert_eq!(w.objects.len(), 2);
+        assert_eq!(w.lights.len(), 1);
+    }
+
+    #[test]
+    fn world_bounds_cover_every_object() {
+        let mut w = World::new();
+        w.objects.push(Shape::sphere());
+        let mut far = Shape::sphere();
+        far.transform = Matrix::translation(5.0, 0.0, 0.0);
+        w.objects.push(far);
+
+        let b = w.bounds();
+        assert_eq!(b.min, RayTuple::point(-1.0, -1.0, -1.0));
+        assert_eq!(b.max, RayTuple::point(6.0, 1.0, 1.0));
+    }
+
+    #[test]
+    fn world_bounds_match_what_a_grouped_ray_hits() {
+        let mut w = World::new();
+        let mut group = Shape::group();
+        group.transform = Matrix::translation(0.0, 10.0, 0.0);
+        let mut sphere = Shape::sphere();
+        sphere.parent = Some(0);
+        w.objects = vec![group, sphere];
+
+        let b = w.bounds();
+        assert_eq!(b.min, RayTuple::point(-1.0, -1.0, -1.0));
+        assert_eq!(b.max, RayTuple::point(1.0, 1.0, 1.0));
+        let r = Ray::new(
+            RayTuple::point(0.0, 0.0, -5.0),
+            RayTuple::vector(0.0, 0.0, 1.0),
+        );
+        assert_eq!(w.nearest_hit(r).unwrap().t, 4.0);
+    }
+
+    #[test]
+    fn reflection_and_refraction_depths_are_independent() {
+        let r = Ray::new(
+            RayTuple::point(0.0, 0.0, 0.0),
+            RayTuple::vector(0.0, 0.0, 1.0),
+        );
+
+        //hall of mirrors: every bounce adds a little more of the far mirror
+        let mut mirrors = World::new();
+        for z in [1.0, -1.0] {
+            let mut mirror = Shape::wall_z(z);
+            mirror.material.reflective = 0.5;
+            mirror.material.ambient = 0.5;
+            mirrors.objects.push(mirror);
+        }
+        mirrors.max_reflection_depth = 2;
+        let two = mirrors.color_at(r, 10);
+        mirrors.max_refraction_depth = 0;
+        assert_eq!(mirrors.color_at(r, 10), two);
+        mirrors.max_refraction_depth = MAX_RECURSION_DEPTH;
+        assert_eq!(mirrors.color_at(r, 2), two);
+        mirrors.max_reflection_depth = 3;
+        assert_ne!(mirrors.color_at(r, 10), two);
+
+        //a row of glass panes: each refraction reaches one pane further
+        let mut panes = World::new();
+        for z in [1.0, 2.0, 3.0] {
+            let mut pane = Shape::wall_z(z);
+            pane.material.transparency = 0.5;
+            pane.material.ambient = 0.5;
+            panes.objects.push(pane);
+        }
+        panes.max_refraction_depth = 1;
+        let one = panes.color_at(r, 10);
+        panes.max_reflection_depth = 0;
+        assert_eq!(panes.color_at(r, 10), one);
+        panes.max_refraction_depth = 2;
+        assert_ne!(panes.color_at(r, 10), one);
+    }
+
+    #[test]
+    fn emissive_surface_glows_without_lights() {
+        let mut w = World::new();
+        w.lights.clear();
+        let mut glowing = Shape::sphere();
+        glowing.material.emission = Color::new(0.9, 0.6, 0.2);
+        w.objects.push(glowing);
+        let r = Ray::new(
+            RayTuple::point(0.0, 0.0, -5.0),
+            RayTuple::vector(0.0, 0.0, 1.0),
+        );
+
+        assert_eq!(w.color_at(r, 5), Color::new(0.9, 0.6, 0.2));
+
+        w.objects[0].material.emission = Color::new(0.0, 0.0, 0.0);
+        assert_eq!(w.color_at(r, 5), Color::new(0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn missed_rays_see_the_skybox() {
+        let colors = [
+            Color::new(1.0, 0.0, 0.0),
+            Color::new(0.0, 1.0, 0.0),
+            Color::new(0.0, 0.0, 1.0),
+            Color::new(1.0, 1.0, 0.0),
+            Color::new(0.0, 1.0, 1.0),
+            Color::new(1.0, 0.0, 1.0),
+        ];
+        let dir = std::env::temp_dir();
+        let paths: Vec<String> = (0..6)
+            .map(|i| {
+                let path = dir.join(format!("ray_skybox_{}_{}.ppm", std::process::id(), i));
+                let face = Canvas::from_fn(4, 4, |_, _| colors[i]);
+                std::fs::write(&path, face.to_ppm()).unwrap();
+                path.to_string_lossy().into_owned()
+            })
+            .collect();
+
+        let mut w = World::new();
+        let loaded = w.set_skybox([
+            &paths[0], &paths[1], &paths[2], &paths[3], &paths[4], &paths[5],
+        ]);
+        for path in &paths {
+            std::fs::remove_file(path).unwrap();
+        }
+        assert!(loaded.is_ok());
+
+        let origin = RayTuple::point(0.0, 0.0, 0.0);
+        let axes = [
+            RayTuple::vector(1.0, 0.0, 0.0),
+            RayTuple::vector(-1.0, 0.0, 0.0),
+            RayTuple::vector(0.0, 1.0, 0.0),
+            RayTuple::vector(0.0, -1.0, 0.0),
+            RayTuple::vector(0.0, 0.0, 1.0),
+            RayTuple::vector(0.0, 0.0, -1.0),
+        ];
+        for (axis, color) in axes.iter().zip(colors) {
+            assert_eq!(w.color_at(Ray::new(origin, *axis), 5), color);
+        }
+
+        assert!(w.set_skybox(["missing.ppm"; 6]).is_err());
+    }
+
+    #[test]
+    fn path_tracing_bleeds_color_from_a_red_wall() {
+        let mut w = World::new();
+        w.lights = vec![Light::point_light(
+            RayTuple::point(0.0, 0.8, 0.0),
+            Color::new(1.0, 1.0, 1.0),
+        )];
+        let mut room = Shape::cube();
+        room.inverted = true;
+        room.material.specular = 0.0;
+        w.objects.push(room);
+        let mut red_wall = Shape::wall_x(0.9);
+        red_wall.material.color = Color::new(1.0, 0.0, 0.0);
+        red_wall.material.specular = 0.0;
+        w.objects.push(red_wall);
+
+        //white floor right next to the red wall
+        let r = Ray::from_points(
+            RayTuple::point(0.0, 0.0, -0.5),
+            RayTuple::point(0.7, -1.0, 0.0),
+        );
+
+        let direct = w.color_at(r, 5);
+        assert!((direct.red - direct.blue).abs() < 0.00001);
+
+        let mut rng = Rng::new(1);
+        let samples = 256;
+        let mut total = Color::new(0.0, 0.0, 0.0);
+        for _ in 0..samples {
+            total = total + w.color_at_gi(r, 2, &mut rng);
+        }
+        let gi = total * (1.0 / samples as f64);
+        assert!(gi.red > gi.blue * 1.1);
+        assert!((gi.green - gi.blue).abs() < 0.05);
+    }
+
+    #[test]
+    fn aux_buffers_report_albedo_and_normal() {
+        let w = World::default_world();
+        let r = Ray::new(
+            RayTuple::point(0.0, 0.0, -5.0),
+            RayTuple::vector(0.0, 0.0, 1.0),
+        );
+
+        let (color, albedo, normal) = w.color_and_aux_at(r, 5);
+        assert_eq!(color, w.color_at(r, 5));
+        assert_eq!(albedo, w.objects[0].material.color);
+        assert_eq!(
+            normal,
+            w.objects[0].normal_at(RayTuple::point(0.0, 0.0, -1.0))
+        );
+
+        let miss = Ray::new(
+            RayTuple::point(0.0, 0.0, -5.0),
+            RayTuple::vector(0.0, 1.0, 0.0),
+        );
+        let (color, albedo, normal) = w.color_and_aux_at(miss, 5);
+        assert_eq!(color, w.sky_color(miss.direction));
+        assert_eq!(albedo, Color::new(0.0, 0.0, 0.0));
+        assert_eq!(normal, RayTuple::vector(0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn rendering_one_object_in_isolation() {
+        let w = World::default_world();
+        let mut c = Camera::new(11, 11, FRAC_PI_2);
+        c.transform = Matrix::view_transform(
+            RayTuple::point(0.0, 0.0, -5.0),
+            RayTuple::point(0.0, 0.0, 0.0),
+            RayTuple::vector(0.0, 1.0, 0.0),
+        );
+
+        //the inner sphere is hidden in the full scene but shows up on its own
+        let inner = w.objects[1].clone();
+        let layer = w.render_only(&c, inner.id());
+
+        let mut alone = World::default_world();
+        alone.objects = vec![inner];
+        for y in 0..11 {
+            for x in 0..11 {
+                let (color, alpha) = layer.pixel_at(x, y);
+                let ray = c.ray_for_pixel(x, y);
+                let (expected, coverage) = alone.color_at_coverage(ray, 5);
+                assert_eq!(alpha, coverage);
+                assert_eq!(color, expected);
+            }
+        }
+        assert_eq!(layer.pixel_at(5, 5).1, 1.0);
+        assert_eq!(layer.pixel_at(0, 0), (Color::new(0.0, 0.0, 0.0), 0.0));
+        assert_eq!(w.objects.len(), 2);
+    }
+
+    #[test]
+    fn nearest_hit_matches_sorted_hit() {
+        let w = World::default_world();
+        let rays = [
+            Ray::new(
+                RayTuple::point(0.0, 0.0, -5.0),
+                RayTuple::vector(0.0, 0.0, 1.0),
+            ),
+            Ray::new(
+                RayTuple::point(0.0, 0.0, 0.0),
+                RayTuple::vector(0.0, 0.0, 1.0),
+            ),
+            Ray::new(
+                RayTuple::point(0.0, 0.0, 0.75),
+                RayTuple::vector(0.0, 0.0, -1.0),
+            ),
+            Ray::new(
+                RayTuple::point(0.0, 0.0, -5.0),
+                RayTuple::vector(0.0, 1.0, 0.0),
+            ),
+        ];
+
+        for r in rays {
+            assert_eq!(w.nearest_hit(r), Intersection::hit(w.intersect_world(r)));
+        }
+    }
+
+    #[test]
+    fn merging_worlds_keeps_both_scenes() {
+        let mut floor = World::new();
+        floor.objects.push(Shape::plane());
+        floor.fog = Some((Color::new(1.0, 1.0, 1.0), 0.1));
+
+        let mut ball = World::new();
+        let group = Shape::group();
+        let mut sphere = Shape::sphere();
+        sphere.transform = Matrix::translation(0.0, 3.0, 0.0);
+        sphere.parent = Some(0);
+        ball.objects = vec![group, sphere];
+        ball.lights = vec![Light::point_light(
+            RayTuple::point(5.0, 5.0, 5.0),
+            Color::new(1.0, 1.0, 1.0),
+        )];
+
+        floor.merge(ball);
+        assert_eq!(floor.objects.len(), 3);
+        assert_eq!(floor.lights.len(), 2);
+        assert_eq!(floor.objects[2].parent, Some(1));
+        assert_eq!(floor.fog, Some((Color::new(1.0, 1.0, 1.0), 0.1)));
+
+        let down = Ray::new(
+            RayTuple::point(0.0, 1.0, 0.0),
+            RayTuple::vector(0.0, -1.0, 0.0),
+        );
+        let hit = Intersection::hit(floor.intersect_world(down)).unwrap();
+        assert_eq!(hit.object.id(), floor.objects[0].id());
+
+        let across = Ray::new(
+            RayTuple::point(0.0, 3.0, -5.0),
+            RayTuple::vector(0.0, 0.0, 1.0),
+        );
+        let hit = Intersection::hit(floor.intersect_world(across)).unwrap();
+        assert_eq!(hit.object.id(), floor.objects[2].id());
+        assert_eq!(hit.t, 4.0);
+    }
+
+    #[test]
+    fn light_offset_removes_grazing_shadow_acne() {
+        //far from the camera the hit points scatter around the surface by more than
+        //over_point's offset, and a nearly tangent light shows it as speckles
+        let mut w = World::new();
+        w.objects.push(Shape::sphere());
+        w.lights = vec![Light::point_light(
+            RayTuple::point(100.0, 0.0, -5.0),
+            Color::new(1.0, 1.0, 1.0),
+        )];
+        let distance: f64 = 1e6;
+        let mut c = Camera::new(20, 20, 2.0 * (1.2 / distance).atan());
+        c.transform = Matrix::view_transform(
+            RayTuple::point(0.0, 0.0, -distance),
+            RayTuple::point(0.0, 0.0, 0.0),
+            RayTuple::vector(0.0, 1.0, 0.0),
+        );
+
+        //pixels facing the light that still come out shadowed
+        let speckles = |w: &World| {
+            let mut count = 0;
+            for y in 0..20 {
+                for x in 0..20 {
+                    let r = c.ray_for_pixel(x, y);
+                    if let Some(hit) = w.nearest_hit(r) {
+                        let comps = hit.prepare_computations(r, &[]);
+                        let light = w.lights[0].position;
+                        let lightv = (light - comps.over_point).normalize();
+                        if lightv.dot(comps.normalv) > 0.0
+                            && w.is_shadowed(light, comps.over_point, 0.0)
+                        {
+                            count += 1;
+                        }
+                    }
+                }
+            }
+            count
+        };
+
+        assert!(speckles(&w) > 0);
+        w.shadow_light_offset = 0.01;
+        assert_eq!(speckles(&w), 0);
+    }
+
+    #[test]
+    fn irradiance_probe_sees_light_only_when_facing_it() {
+        let w = World::default_world();
+        let light = w.lights[0].position;
+        let point = RayTuple::point(-2.0, 2.0, -2.0);
+        let toward = (light - point).normalize();
+
+        let facing = w.irradiance_at(point, toward);
+        assert!(facing.red > 0.99 && facing.green > 0.99 && facing.blue > 0.99);
+
+        let away = w.irradiance_at(point, -toward);
+        assert_eq!(away, Color::new(0.0, 0.0, 0.0));
+
+        //behind the spheres the light is blocked even when facing it
+        let hidden = RayTuple::point(2.0, -2.0, 2.0);
+        let blocked = w.irradiance_at(hidden, (light - hidden).normalize());
+        assert_eq!(blocked, Color::new(0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn thicker_tinted_glass_transmits_darker_color() {
+        let through_slab = |thickness: f64| {
+            let mut w = World::new();
+            w.lights.clear();
+
+            let mut backdrop = Shape::plane();
+            backdrop.transform =
+                Matrix::translation(0.0, 0.0, 10.0) * Matrix::rotation_x(FRAC_PI_2);
+            backdrop.material.color = Color::new(0.0, 0.0, 0.0);
+            backdrop.material.ambient = 0.0;
+            backdrop.material.emission = Color::new(1.0, 1.0, 1.0);
+
+            let mut slab = Shape::cube();
+            slab.transform = Matrix::scaling(5.0, 5.0, thickness / 2.0);
+            slab.material.color = Color::new(0.0, 0.0, 0.0);
+            slab.material.ambient = 0.0;
+            slab.material.diffuse = 0.0;
+            slab.material.specular = 0.0;
+            slab.material.transparency = 1.0;
+            slab.material.refractive_index = 1.0;
+            slab.material.transmission_color = Color::new(0.9, 0.5, 0.5);
+
+            w.objects = vec![backdrop, slab];
+            let r = Ray::new(
+                RayTuple::point(0.0, 0.0, -5.0),
+                RayTuple::vector(0.0, 0.0, 1.0),
+            );
+            w.color_at(r, 5)
+        };
+
+        let thin = through_slab(0.5);
+        let thick = through_slab(2.0);
+
+        assert_eq!(
+            thin,
+            Color::new(0.9_f64.sqrt(), 0.5_f64.sqrt(), 0.5_f64.sqrt())
+        );
+        assert_eq!(thick, Color::new(0.81, 0.25, 0.25));
+        assert!(thick.luminance() < thin.luminance());
+        assert!(thick.green / thick.red < thin.green / thin.red);
+    }
+
+    #[test]
+    fn point_is_lit_unless_something_blocks_the_light() {
+        let w = World::default_world();
+
+        assert!(w.point_is_lit(RayTuple::point(0.0, 10.0, 0.0), 0));
+        assert!(w.point_is_lit(RayTuple::point(-2.0, 2.0, -2.0), 0));
+        assert!(!w.point_is_lit(RayTuple::point(10.0, -10.0, 10.0), 0));
+        assert!(!w.point_is_lit(RayTuple::point(0.0, 10.0, 0.0), 1));
+    }
+
+    #[test]
+    fn reflection_falloff_dims_distant_reflections() {
+        let reflection = |wall_z: f64, falloff: f64| {
+            let mut w = World::new();
+            w.lights.clear();
+
+            let mut floor = Shape::plane();
+            floor.material.reflective = 1.0;
+            floor.material.reflective_falloff = falloff;
+
+            let mut wall = Shape::plane();
+            wall.transform = Matrix::translation(0.0, 0.0, wall_z) * Matrix::rotation_x(FRAC_PI_2);
+            wall.material.color = Color::new(0.0, 0.0, 0.0);
+            wall.material.ambient = 0.0;
+            wall.material.emission = Color::new(1.0, 1.0, 1.0);
+
+            w.objects = vec![floor, wall];
+            let r = Ray::new(
+                RayTuple::point(0.0, 1.0, 0.0),
+                RayTuple::vector(0.0, -2.0_f64.sqrt() / 2.0, 2.0_f64.sqrt() / 2.0),
+            );
+            let i = Intersection::new(2.0_f64.sqrt(), &w.objects[0]);
+            let comps = i.prepare_computations(r, &[]);
+            w.reflected_color(comps, 5)
+        };
+
+        assert_eq!(reflection(3.0, 0.0), Color::new(1.0, 1.0, 1.0));
+        assert_eq!(reflection(30.0, 0.0), Color::new(1.0, 1.0, 1.0));
+
+        let near = reflection(3.0, 0.1);
+        let far = reflection(30.0, 0.1);
+        let fade = (-0.1 * 2.0 * 2.0_f64.sqrt()).exp();
+        assert_eq!(near, Color::new(fade, fade, fade));
+        assert!(far.red < near.red && far.red > 0.0);
+    }
+
+    #[test]
+    fn world_bounds_spread_along_plane_axes() {
+        let mut w = World::new();
+        for x in [-2.0, 2.0] {
+            let mut s = Shape::sphere();
+            s.transform = Matrix::translation(x, 0.0, 0.0);
+            w.objects.push(s);
+        }
+
+        let b = w.bounds();
+        assert_eq!((b.min.x, b.max.x), (-3.0, 3.0));
+        assert_eq!((b.min.z, b.max.z), (-1.0, 1.0));
+
+        w.objects.push(Shape::plane());
+        let b = w.bounds();
+        assert_eq!((b.min.x, b.max.x), (f64::NEG_INFINITY, f64::INFINITY));
+        assert_eq!((b.min.z, b.max.z), (f64::NEG_INFINITY, f64::INFINITY));
+        assert_eq!((b.min.y, b.max.y), (-1.0, 1.0));
+    }
+
+    #[test]
+    fn shadows_follow_moving_occluders() {
+        let mut w = World::new();
+        w.lights = vec![Light::point_light(
+            RayTuple::point(0.0, 0.0, -10.0),
+            Color::new(1.0, 1.0, 1.0),
+        )];
+        //sits between the light and s2 at time 0, out of the way by time 1
+        let mut occluder = Shape::new(ShapeType::Sphere);
+        occluder.velocity = RayTuple::vector(3.0, 0.0, 0.0);
+        w.objects.push(occluder);
+
+        let mut s2 = Shape::new(ShapeType::Sphere);
+        s2.transform = Matrix::translation(0.0, 0.0, 10.0);
+        w.objects.push(s2);
+
+        let p = RayTuple::point(0.0, 0.0, 8.9);
+        assert!(w.is_shadowed(w.lights[0].position, p, 0.0));
+        assert!(!w.is_shadowed(w.lights[0].position, p, 1.0));
+
+        let shade_at = |time: f64| {
+            let r = Ray::new(
+                RayTuple::point(0.0, 0.0, 5.0),
+                RayTuple::vector(0.0, 0.0, 1.0),
+            )
+            .with_time(time);
+            let i = Intersection::new(4.0, &w.objects[1]);
+            w.shade_hit(i.prepare_computations(r, &[]), 5)
+        };
+        assert_eq!(shade_at(0.0), Color::new(0.1, 0.1, 0.1));
+        assert_eq!(shade_at(1.0), Color::new(1.9, 1.9, 1.9));
+    }
+
+    #[test]
+    fn bounded_intersection_skips_objects_past_the_kept_hits() {
+        use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
+
+        let mut w = World::new();
+        w.objects.push(Shape::sphere());
+        //implicit spheres farther down the ray, counting every time their field is sampled
+        let samples = Arc::new(AtomicUsize::new(0));
+        for i in 1..=10 {
+            let counter = Arc::clone(&samples);
+            let mut s = Shape::implicit(
+                move |p| {
+                    counter.fetch_add(1, AtomicOrdering::Relaxed);
+                    let v = p - RayTuple::point(0.0, 0.0, 0.0);
+                    v.dot(v)
+                },
+                0.5,
+            );
+            s.transform = Matrix::translation(0.0, 0.0, i as f64 * 3.0);
+            w.objects.push(s);
+        }
+        let r = Ray::new(
+            RayTuple::point(0.0, 0.0, -5.0),
+            RayTuple::vector(0.0, 0.0, 1.0),
+        );
+
+        //both sphere hits fill the list, so none of the implicits are marched
+        let xs = w.intersect_world_bounded(r, 2);
+        assert_eq!(xs.iter().map(|i| i.t).collect::<Vec<f64>>(), vec![4.0, 6.0]);
+        assert_eq!(samples.load(AtomicOrdering::Relaxed), 0);
+
+        //one more slot has to look at the first implicit, but still not the others
+        let xs = w.intersect_world_bounded(r, 3);
+        assert_eq!(xs.len(), 3);
+        assert_eq!(xs[2].object.id(), w.objects[1].id());
+        let first_only = samples.swap(0, AtomicOrdering::Relaxed);
+        assert!(first_only > 0);
+
+        w.intersect_world(r);
+        assert!(samples.load(AtomicOrdering::Relaxed) > 5 * first_only);
+    }
+
+    #[test]
+    fn color_at_sees_total_internal_reflection_from_inside_glass() {
+        let glass_world = |refractive_index: f64| {
+            let mut w = World::new();
+            let mut glass = Shape::glass_sphere();
+            glass.material.color = Color::new(0.0, 0.0, 0.0);
+            glass.material.ambient = 0.0;
+            glass.material.diffuse = 0.0;
+            glass.material.specular = 0.0;
+            glass.material.refractive_index = refractive_index;
+            w.objects.push(glass);
+            let mut backdrop = Shape::plane();
+            backdrop.transform = Matrix::translation(0.0, 5.0, 0.0);
+            backdrop.material.ambient = 0.0;
+            backdrop.material.emission = Color::new(1.0, 1.0, 1.0);
+            w.objects.push(backdrop);
+            w
+        };
+        //leaves the sphere 45 degrees off its normal, past the critical angle for 1.5
+        let r = Ray::new(
+            RayTuple::point(0.0, 0.0, FRAC_1_SQRT_2),
+            RayTuple::vector(0.0, 1.0, 0.0),
+        );
+
+        assert_eq!(glass_world(1.5).color_at(r, 5), Color::new(0.0, 0.0, 0.0));
+        assert_eq!(glass_world(1.0).color_at(r, 5), Color::new(1.0, 1.0, 1.0));
+    }
+
+    #[test]
+    fn aux_color_follows_the_recursion_depth() {
+        let mut w = World::default_world();
+        let mut floor = Shape::plane();
+        floor.material.reflective = 0.5;
+        floor.transform = Matrix::translation(0.0, -1.0, 0.0);
+        w.objects.push(floor);
+        let r = Ray::new(
+            RayTuple::point(0.0, 0.0, -3.0),
+            RayTuple::vector(0.0, -FRAC_1_SQRT_2, FRAC_1_SQRT_2),
+        );
+
+        let (flat, _, _) = w.color_and_aux_at(r, 0);
+        let (reflected, _, _) = w.color_and_aux_at(r, 5);
+        assert_eq!(flat, w.color_at(r, 0));
+        assert_eq!(reflected, w.color_at(r, 5));
+        assert_ne!(flat, reflected);
+    }
 }